@@ -1,21 +1,42 @@
 //! MMA7660FC I2Cドライバを使用したESP32でのサンプルコード
 //!
 //! このコードは、I2C経由でMMA7660FC加速度センサーを初期化し、
-//! 1秒ごとにX, Y, Z軸の加速度を読み取ってコンソールに出力します。
+//! X, Y, Z軸の加速度をOLEDにストリップチャートとしてリアルタイムに表示します。
+//!
+//! - BOOTボタン短押し: 時間軸 (1ピクセルあたりのサンプル間隔) を切り替え
+//! - BOOTボタン長押し: 自動スケーリングと固定範囲 (±1.5g) を切り替え
 
-// 作成したドライバをモジュールとして読み込みます
+use std::time::{Duration, Instant};
 
-//use std::thread;
-//use std::time::Duration;
+use embedded_graphics::{
+    mono_font::{ascii::FONT_5X7, MonoTextStyle},
+    pixelcolor::BinaryColor,
+    prelude::*,
+    primitives::Rectangle,
+    text::{Alignment, Baseline, Text, TextStyleBuilder},
+};
 
-//esp_idf_svc::hal::prelude::Periferal;
 use esp_idf_svc::hal::prelude::Peripherals;
 use esp_idf_svc::hal::delay::FreeRtos;
+use esp_idf_svc::hal::gpio::{PinDriver, Pull};
 use esp_idf_svc::hal::units::FromValueType;
 
 use esp_idf_svc::hal::i2c::{I2cConfig, I2cDriver};
 
-use esp32s2_common_lib::mma7660fc::{Mode, DEFAULT_I2C_ADDRESS, Mma7660fc};
+use esp32s2_common_lib::input::{Button, ButtonEvent};
+use esp32s2_common_lib::mma7660fc::{Acceleration, Mode, DEFAULT_I2C_ADDRESS, Mma7660fc};
+use esp32s2_common_lib::buffered_display::Controller;
+use esp32s2_common_lib::sh1106_display::set_buffered_display;
+use esp32s2_common_lib::widgets::strip_chart::{SeriesStyle, StripChart};
+
+/// 1カウントあたりの加速度 (g)。6ビットで ±1.5g を表す
+const G_PER_COUNT: f32 = 1.5 / 32.0;
+
+/// 選択できる時間軸 (1サンプル = 横1ピクセルの間隔, ミリ秒)
+const TIMEBASES_MS: [u64; 5] = [20, 50, 100, 200, 500];
+
+/// 固定表示の範囲 (g)
+const FIXED_RANGE: (f32, f32) = (-1.5, 1.5);
 
 fn main() -> anyhow::Result<()> {
     // ランタイムのパッチをリンクします
@@ -28,6 +49,22 @@ fn main() -> anyhow::Result<()> {
     // ペリフェラルを取得します
     let peripherals = Peripherals::take()?;
 
+    // --- ディスプレイの初期化 ---
+    let (mut display, _rst_driver) = set_buffered_display(
+        Controller::from_build_env(option_env!("DISPLAY_CONTROLLER"))?,
+        peripherals.pins.gpio38,
+        peripherals.pins.gpio37,
+        peripherals.pins.gpio36,
+        peripherals.pins.gpio35,
+        peripherals.pins.gpio34,
+        peripherals.spi2,
+    )?;
+
+    // --- BOOTボタン (GPIO0, 押すとLOW) ---
+    let mut boot_pin = PinDriver::input(peripherals.pins.gpio0)?;
+    boot_pin.set_pull(Pull::Up)?;
+    let mut button = Button::active_low(boot_pin);
+
     // --- I2Cの初期化 ---
     let i2c = peripherals.i2c0;
 
@@ -55,21 +92,98 @@ fn main() -> anyhow::Result<()> {
 
     FreeRtos::delay_ms(100); // モード変更が安定するまで少し待機
 
+    // 上下1行ずつを凡例と設定表示に使う
+    let mut chart = StripChart::new(Rectangle::new(Point::new(0, 9), Size::new(128, 47)), 3)
+        .with_min_span(0.25);
+    let mut timebase = 1;
+    let mut accel = Acceleration::default();
+    let mut last_sample: Option<Instant> = None;
+    let mut last_log = Instant::now();
+
     // --- メインループ ---
     loop {
-        // 加速度データを取得します
-        match sensor.get_acceleration() {
-            Ok(accel) => {
-                // 取得した値をログに出力します
-                log::info!("加速度: x={}, y={}, z={}", accel.x, accel.y, accel.z);
+        match button.poll() {
+            Some(ButtonEvent::Released { long: false }) => {
+                // 時間軸が混ざらないように、切り替えたら表示をやり直す
+                timebase = (timebase + 1) % TIMEBASES_MS.len();
+                chart.clear();
+                log::info!("時間軸: {}ms", TIMEBASES_MS[timebase]);
             }
-            Err(e) => {
-                log::error!("加速度の読み取りに失敗しました: {:?}", e);
+            Some(ButtonEvent::LongPress) => {
+                if chart.is_auto_range() {
+                    chart.set_range(Some(FIXED_RANGE));
+                } else {
+                    chart.set_range(None);
+                }
             }
+            _ => {}
         }
 
-        // 1秒待機
-        FreeRtos::delay_ms(1000);
+        let interval = Duration::from_millis(TIMEBASES_MS[timebase]);
+        if last_sample.is_none_or(|t| t.elapsed() >= interval) {
+            last_sample = Some(Instant::now());
+
+            // 加速度データを取得します
+            match sensor.get_acceleration() {
+                Ok(a) => {
+                    accel = a;
+                    chart.push(&[
+                        a.x as f32 * G_PER_COUNT,
+                        a.y as f32 * G_PER_COUNT,
+                        a.z as f32 * G_PER_COUNT,
+                    ]);
+                }
+                Err(e) => {
+                    log::error!("加速度の読み取りに失敗しました: {:?}", e);
+                }
+            }
+
+            // シリアルへの出力は1秒ごとにする
+            if last_log.elapsed() >= Duration::from_secs(1) {
+                last_log = Instant::now();
+                log::info!("加速度: x={}, y={}, z={}", accel.x, accel.y, accel.z);
+            }
+
+            display.clear();
+            draw_screen(&mut display, &chart, &accel, TIMEBASES_MS[timebase])
+                .map_err(|e| anyhow::anyhow!("Draw chart error: {:?}", e))?;
+            display.flush().map_err(|e| anyhow::anyhow!("Display flush error: {:?}", e))?;
+        }
+
+        FreeRtos::delay_ms(10);
     }
 }
 
+/// 凡例 (線の種類と現在値 [g])、チャート、時間軸と縦軸の範囲 [g] を描画する
+fn draw_screen<D>(display: &mut D, chart: &StripChart, accel: &Acceleration, timebase_ms: u64) -> Result<(), D::Error>
+    where D: DrawTarget<Color = BinaryColor>
+{
+    let style = MonoTextStyle::new(&FONT_5X7, BinaryColor::On);
+
+    let values = [("X", accel.x), ("Y", accel.y), ("Z", accel.z)];
+    for (i, (name, count)) in values.iter().enumerate() {
+        let x = i as i32 * 43;
+        SeriesStyle::for_index(i).draw_sample(display, Point::new(x, 3), 6)?;
+        let label = format!("{}{:+.1}", name, *count as f32 * G_PER_COUNT);
+        Text::with_baseline(&label, Point::new(x + 8, 0), style, Baseline::Top).draw(display)?;
+    }
+
+    chart.draw(display)?;
+
+    let width = display.bounding_box().size.width as i32;
+    Text::with_baseline(&format!("{}ms", timebase_ms), Point::new(0, 57), style, Baseline::Top).draw(display)?;
+    let (min, max) = chart.range();
+    let scale = if chart.is_auto_range() {
+        format!("AUTO {:+.1}..{:+.1}", min, max)
+    } else {
+        format!("FIX {:+.1}..{:+.1}", min, max)
+    };
+    Text::with_text_style(
+        &scale,
+        Point::new(width - 1, 57),
+        style,
+        TextStyleBuilder::new().alignment(Alignment::Right).baseline(Baseline::Top).build(),
+    )
+    .draw(display)?;
+    Ok(())
+}
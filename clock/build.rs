@@ -1,10 +1,15 @@
 fn main() {
     embuild::espidf::sysenv::output();
     let _ = dotenvy::from_filename(".env");
+    // .env やシェルの SSID を変えたら埋め込む値を更新する
+    println!("cargo:rerun-if-changed=.env");
 
-    for (key, value) in std::env::vars() {
-        println!("cargo:rustc-env={}={}", key, value);
-        //if key.starts_with("API_") || key.starts_with("APP_") { }
+    // Wi-Fi の接続先の初期値 (NVS に保存されていないときだけ使う) とディスプレイのコントローラ。
+    // ほかの環境変数は埋め込まない
+    for key in ["SSID", "SSID_PASSWORD", "DISPLAY_CONTROLLER"] {
+        println!("cargo:rerun-if-env-changed={}", key);
+        if let Ok(value) = std::env::var(key) {
+            println!("cargo:rustc-env={}={}", key, value);
+        }
     }
 }
-
@@ -1,7 +1,7 @@
 use anyhow::Result;
-use esp_idf_hal::sys::{gettimeofday, timeval, tzset};
+use esp_idf_hal::sys::{gettimeofday, localtime_r, timeval, tm, tzset};
 use esp_idf_svc::hal::prelude::Peripherals;
-use esp_idf_svc::hal::gpio::PinDriver;
+use esp_idf_svc::hal::gpio::{AnyIOPin, IOPin, Input, PinDriver, Pull};
 use esp_idf_svc::hal::rmt::{
     FixedLengthSignal, 
     PinState,
@@ -9,50 +9,83 @@ use esp_idf_svc::hal::rmt::{
     TxRmtDriver
 };
 
-use esp_idf_svc::hal::{
-    gpio::AnyIOPin,
-    spi::{
-        config::Config as SpiConfig,
-        config::MODE_3,
-        SpiDeviceDriver,
-        SpiDriver,
-        SpiDriverConfig
-    },
-    units::FromValueType,
-};
 use esp_idf_svc::{
-    wifi::{EspWifi, ClientConfiguration, Configuration, BlockingWifi},
+    wifi::{EspWifi, BlockingWifi},
     nvs::EspDefaultNvsPartition,
     eventloop::EspSystemEventLoop,
     sntp::{EspSntp, SyncStatus},
 };
 
 use esp_idf_svc::hal::delay::FreeRtos;
+use esp_idf_svc::hal::i2c::{I2cConfig, I2cDriver};
+use esp_idf_svc::hal::units::FromValueType;
 
-use heapless::String;
-use sh1106::interface::DisplayInterface;
+use sh1106::{displayrotation::DisplayRotation, interface::DisplayInterface};
 use std::env;
+use std::sync::{Arc, Mutex};
 use std::thread::sleep;
-use std::time::Duration;
+use std::time::{Duration, Instant};
 
-use sh1106::{prelude::*, Builder};
 use embedded_graphics::{
     mono_font::{ascii::* , MonoTextStyle},
     pixelcolor::BinaryColor,
     prelude::*,
-    text::Text,
+    text::{Alignment, Baseline, Text, TextStyleBuilder},
 };
 
-const SSID_STR: &'static str = env!("SSID");
-const SSID_PASSWORD_STR: &'static str = env!("SSID_PASSWORD");
+use esp32s2_common_lib::buffered_display::{BufferedDisplay, Controller, DEFAULT_CONTRAST};
+use esp32s2_common_lib::input::{Button, ButtonInput, InputEvent, InputSource};
+use esp32s2_common_lib::menu::{Menu, MenuEvent, MenuItem};
+use esp32s2_common_lib::mma7660fc::{Mode, DEFAULT_I2C_ADDRESS, Mma7660fc};
+use esp32s2_common_lib::orientation::OrientationTracker;
+use esp32s2_common_lib::screensaver::{PowerState, Screensaver};
+use esp32s2_common_lib::sh1106_display::set_buffered_display;
+use esp32s2_common_lib::widgets::seven_segment::{SevenSegment, SevenSegmentStyle};
+use esp32s2_common_lib::widgets::text_console::{ConsoleLogger, TextConsole};
+use esp32s2_common_lib::provisioning::{ProvisioningPortal, DEFAULT_AP_SSID};
+use esp32s2_common_lib::wifi_credentials::{CredentialStore, Credentials, KnownNetwork};
+use esp32s2_common_lib::wifi_manager::{WifiManager, WifiManagerConfig};
+
+mod settings;
+
+use settings::SettingsStore;
+
+/// NVS に接続先が保存されていないときに使う、ビルド時の接続先 (.env)
+const BUILD_SSID: Option<&str> = option_env!("SSID");
+const BUILD_SSID_PASSWORD: Option<&str> = option_env!("SSID_PASSWORD");
+
+/// 起動時に Wi-Fi の接続を待つ時間。過ぎると設定用のポータルを開く
+const WIFI_CONNECT_TIMEOUT: Duration = Duration::from_secs(30);
+
+/// 時刻表示と加速度センサーの更新間隔
+const CLOCK_TICK: Duration = Duration::from_millis(500);
+
+/// 設定メニューで選べるタイムゾーン (表示名, POSIX TZ)
+///
+/// POSIX TZフォーマットでは、UTCからのオフセットの符号が逆になることに注意
+/// JSTはUTC+9だが、"JST-9"と指定する
+const TIME_ZONES: &[(&str, &str)] = &[
+    ("JST", "JST-9"),
+    ("UTC", "UTC0"),
+    ("CET", "CET-1CEST,M3.5.0,M10.5.0/3"),
+    ("EST", "EST5EDT,M3.2.0,M11.1.0"),
+    ("PST", "PST8PDT,M3.2.0,M11.1.0"),
+];
 
 fn main() -> anyhow::Result<()> {
     esp_idf_svc::sys::link_patches();
 
+    // 起動中のログをシリアルとOLEDの両方に出す
+    let console = Arc::new(Mutex::new(TextConsole::new_full_screen()));
+    ConsoleLogger::new(console.clone())
+        .with_forward(Box::new(esp_idf_svc::log::EspLogger::new()))
+        .install()
+        .map_err(|e| anyhow::anyhow!("Logger init error: {:?}", e))?;
 
     let peripherals = Peripherals::take()?;
     let sys_loop = EspSystemEventLoop::take().unwrap();
     let nvs = EspDefaultNvsPartition::take().unwrap();
+    let mut settings = SettingsStore::new(nvs.clone())?;
 
     // 1. ピン定義
     let rst_pin = peripherals.pins.gpio38;
@@ -63,55 +96,65 @@ fn main() -> anyhow::Result<()> {
     let cs_pin = peripherals.pins.gpio34;
     let spi_peripheral = peripherals.spi2;
 
-    // 2. SPIドライバ初期化
-    let spi_driver = SpiDriver::new(
-        spi_peripheral,
+    // 2. ディスプレイ初期化 (変更のあった範囲だけを転送する)
+    let (mut display, _rst_driver) = set_buffered_display(
+        Controller::from_build_env(option_env!("DISPLAY_CONTROLLER"))?,
+        rst_pin,
+        dc_pin,
         sclk_pin,
         sda_pin,
-        None::<AnyIOPin>,
-        &SpiDriverConfig::new(),
+        cs_pin,
+        spi_peripheral
     )?;
 
-    // 3. SPI通信設定
-    let spi_config = SpiConfig::new()
-       .baudrate(40.MHz().into())
-       .data_mode(MODE_3); // `esp-idf-hal`が提供する`SpiMode`を使用
-
-    // 4. SPIデバイスドライバ作成
-    let spi_device = SpiDeviceDriver::new(spi_driver, None::<AnyIOPin>, &spi_config)?;
-
-    // 5. 制御ピンのドライバを作成
-    let dc_driver = PinDriver::output(dc_pin)?;
-    let cs_driver = PinDriver::output(cs_pin)?;
-    let mut rst_driver = PinDriver::output(rst_pin)?;
-
-    // 6. ディスプレイドライバ初期化
-    // ハードウェアリセットを実行
-    rst_driver.set_low()?;
-    FreeRtos::delay_ms(50);
-    rst_driver.set_high()?;
-    FreeRtos::delay_ms(50);
-
-    // sh1106のBuilderに、生のSPIデバイスと制御ピンを直接渡す
-    let mut display: GraphicsMode<_> = Builder::new()
-       .with_size(DisplaySize::Display128x64)
-       .with_rotation(DisplayRotation::Rotate0)
-       .connect_spi(spi_device, dc_driver, cs_driver)
-       .into();
-    display.init().map_err(|e| anyhow::anyhow!("Display init error: {:?}", e))?;
-    log::info!("OLED Initialized");
-
     backlight.set_high()?;
     
-    show_msg_log(&mut display, format!("{}", "connecting wifi...").as_str())?;
+    // 接続先は NVS に保存したものを優先し、なければビルド時の値を使う
+    let mut credential_store = CredentialStore::new(nvs.clone())?;
+    let fallback = Credentials::from_build_env(BUILD_SSID, BUILD_SSID_PASSWORD);
+    let networks = credential_store.load_or(fallback)?;
+
     let wifi_driver = EspWifi::new(
         peripherals.modem,
         sys_loop.clone(),
         Some(nvs)
     )?;
-    let mut wifi_driver = BlockingWifi::wrap(wifi_driver, sys_loop)?;
-    connect_wifi(&mut wifi_driver)?;
-    show_msg_log(&mut display, format!("{}", "connected to Wifi!").as_str())?;
+
+    // 接続できたあとは、切断されても WifiManager がバックグラウンドで再接続する
+    let connection = if networks.is_empty() {
+        log::error!("no wifi credentials");
+        Err(wifi_driver)
+    } else {
+        log::info!("connecting wifi...");
+        show_msg_log(&mut display, &console)?;
+        let manager = WifiManager::start(wifi_driver, &sys_loop, networks, WifiManagerConfig::default())?;
+        match manager.wait_connected(WIFI_CONNECT_TIMEOUT) {
+            Some(_) => Ok(manager),
+            None => {
+                log::error!("wifi timed out");
+                Err(manager.stop())
+            }
+        }
+    };
+
+    // 接続先がないか接続できなければ、アクセスポイントになって接続先を設定してもらう
+    let _wifi_manager = match connection {
+        Ok(manager) => manager,
+        Err(wifi_driver) => {
+            let mut wifi_driver = BlockingWifi::wrap(wifi_driver, sys_loop)?;
+            let portal = ProvisioningPortal::new(DEFAULT_AP_SSID);
+            log::info!("join AP {}", portal.ap_ssid());
+            log::info!("to set up wifi");
+            show_msg_log(&mut display, &console)?;
+            let saved = portal.run(&mut wifi_driver, &mut credential_store)?;
+            log::info!("saved {}", saved.ssid);
+            log::info!("restarting...");
+            show_msg_log(&mut display, &console)?;
+            esp_idf_svc::hal::reset::restart();
+        }
+    };
+    log::info!("connected to Wifi!");
+    show_msg_log(&mut display, &console)?;
 
     // NeoPixel (WS2812B) on GPIO18
     //let led_pin = peripherals.pins.gpio18;
@@ -119,67 +162,248 @@ fn main() -> anyhow::Result<()> {
     //let config = TransmitConfig::new().clock_divider(1);
     //let mut neopixel_tx = TxRmtDriver::new(channel, led_pin, &config)?;
 
-    esp_idf_svc::log::EspLogger::initialize_default();
-
     //let listener = TcpListener::bind("0.0.0.0:8080")?;
     //log::info!("TCP server listening on 0.0.0.0:8080");
 
     // --- 2. SNTPサービスによる時刻同期 ---
     log::info!("Initializing SNTP...");
-    show_msg_log(&mut display, format!("{}", "Initializing SNTP...").as_str())?;
+    show_msg_log(&mut display, &console)?;
 
     let sntp = EspSntp::new_default()?;
 
     log::info!("Waiting for time synchronization...");
-    show_msg_log(&mut display, format!("{}", "Waiting for time synchronization...").as_str())?;
+    show_msg_log(&mut display, &console)?;
     while sntp.get_sync_status() != SyncStatus::Completed {
         //FreeRtos::delay_ms(5);
         sleep(Duration::from_millis(100));
     }
     log::info!("Time synchronized successfully!");
-    show_msg_log(&mut display, format!("{}", "Time synchronized successfully!").as_str())?;
-
+    show_msg_log(&mut display, &console)?;
+
+    // --- 3. 焼き付き防止 ---
+    // 加速度センサーで動きを検知したら表示を元に戻す (センサーがなくても時計は動作する)
+    let i2c_config = I2cConfig::new().baudrate(100.kHz().into());
+    let i2c_driver = I2cDriver::new(
+        peripherals.i2c0,
+        peripherals.pins.gpio8,
+        peripherals.pins.gpio9,
+        &i2c_config
+    )?;
+    let mut sensor = Mma7660fc::new(i2c_driver, DEFAULT_I2C_ADDRESS);
+    let sensor_ready = match sensor.set_mode(Mode::Active) {
+        Ok(_) => true,
+        Err(e) => {
+            log::warn!("Accelerometer not available: {:?}", e);
+            false
+        }
+    };
+    let mut screensaver = Screensaver::new(Duration::from_secs(30), Duration::from_secs(300));
+
+    // --- 4. 設定メニュー ---
+    // BOOT(GPIO0): 短押しで決定・長押しで戻る, GPIO4: 上, GPIO5: 下
+    let mut buttons = ButtonInput::new()
+        .with_long_press(input_button(peripherals.pins.gpio0.downgrade())?, InputEvent::Select, InputEvent::Back)
+        .with_button(input_button(peripherals.pins.gpio4.downgrade())?, InputEvent::Up)
+        .with_button(input_button(peripherals.pins.gpio5.downgrade())?, InputEvent::Down);
+    let mut menu = settings_menu(&credential_store.networks()?);
+    let mut orientation = OrientationTracker::new();
+    let mut menu_open = false;
+    // 保存した設定を読み込んで反映する
+    settings.load(&mut menu)?;
+    apply_time_zone(&menu);
+    apply_power_settings(&menu, &mut screensaver);
+    screensaver
+        .apply(&mut display)
+        .map_err(|e| anyhow::anyhow!("Display power error: {:?}", e))?;
+    display
+        .set_invert(menu.toggle_value("invert").unwrap_or(false))
+        .map_err(|e| anyhow::anyhow!("Display invert error: {:?}", e))?;
+
+    let mut last_tick: Option<Instant> = None;
     loop {
-            // タイムゾーンを日本標準時 (JST) に設定
-            // POSIX TZフォーマットでは、UTCからのオフセットの符号が逆になることに注意
-            // JSTはUTC+9だが、"JST-9"と指定する
-            env::set_var("TZ", "JST-9");
-            unsafe { tzset() }
-            // gettimeofdayを呼び出してUNIXタイムスタンプを取得
-            let mut tv = timeval{
-                tv_sec: 0,
-                tv_usec: 0,
-            };
-            unsafe {
-                gettimeofday(&mut tv, std::ptr::null_mut());
+        let mut redraw = false;
+        let mut power_changed = false;
+
+        if let Some(event) = buttons.poll() {
+            // 減光・消灯中のボタン操作は表示を戻すだけにする
+            let asleep = screensaver.state() != PowerState::Normal;
+            screensaver.reset();
+            redraw = true;
+            if asleep {
+                // 表示を戻すだけ
+            } else if !menu_open {
+                if event == InputEvent::Select {
+                    menu.reset();
+                    menu_open = true;
+                }
+            } else {
+                match menu.handle(event) {
+                    MenuEvent::Changed("tz") => apply_time_zone(&menu),
+                    MenuEvent::Changed("brightness") | MenuEvent::Changed("dim_after") => {
+                        apply_power_settings(&menu, &mut screensaver);
+                        power_changed = true;
+                    }
+                    MenuEvent::Changed("invert") => {
+                        display
+                            .set_invert(menu.toggle_value("invert").unwrap_or(false))
+                            .map_err(|e| anyhow::anyhow!("Display invert error: {:?}", e))?;
+                    }
+                    MenuEvent::Changed("rotate") => {
+                        // 自動回転を止めたら通常の向きに戻す
+                        if !menu.toggle_value("rotate").unwrap_or(false) {
+                            orientation = OrientationTracker::new();
+                            display.set_rotation(DisplayRotation::Rotate0);
+                        }
+                    }
+                    MenuEvent::Action("restart") => {
+                        settings.save(&menu)?;
+                        apply_wifi_selection(&mut menu, &mut credential_store)?;
+                        esp_idf_svc::hal::reset::restart();
+                    }
+                    // 閉じたときにまとめて保存する (編集中の Up/Down ごとに書き込まない)
+                    MenuEvent::Action("close") | MenuEvent::Exit => {
+                        menu_open = false;
+                        settings.save(&menu)?;
+                        apply_wifi_selection(&mut menu, &mut credential_store)?;
+                    }
+                    _ => {}
+                }
             }
-            // chronoを使用して人間が可読な形式に変換
-            let dt = chrono::DateTime::from_timestamp(tv.tv_sec, tv.tv_usec as u32 * 1000)
-               .expect("Invalid timestamp");
-            // フォーマットして表示
-
-            //let style = PrimitiveStyleBuilder::new()
-            //.stroke_color(BinaryColor::On)
-            //.stroke_width(1)
-            //.build();
-            //Rectangle::new(Point::new(2, 2), Size::new(3, 3))
-            //.into_styled(style)
-            //.draw(&mut display)
-            //.map_err(|e| anyhow::anyhow!("Draw rectangle error: {:?}", e))?;
-            //Circle::new(Point::new(0, 0), diameter).into_styled(style).draw(display).map_err(|e| anyhow::anyhow!("Draw rectangle error: {:?}", e))?;
+        }
+
+        // 時刻と加速度センサーは CLOCK_TICK ごとに更新する
+        if last_tick.is_none_or(|t| t.elapsed() >= CLOCK_TICK) {
+            last_tick = Some(Instant::now());
+            redraw = true;
+            if sensor_ready {
+                if let Ok(accel) = sensor.get_acceleration() {
+                    screensaver.feed_acceleration(accel);
+                }
+                // 本体の向きに合わせて表示を回転する
+                if menu.toggle_value("rotate").unwrap_or(false) {
+                    if let Some(rotation) = sensor.get_tilt().ok().and_then(|tilt| orientation.update(tilt.orientation)) {
+                        display.set_rotation(rotation);
+                    }
+                }
+            }
+        }
+
+        if screensaver.update().is_some() || power_changed {
+            screensaver
+                .apply(&mut display)
+                .map_err(|e| anyhow::anyhow!("Display power error: {:?}", e))?;
+        }
 
+        if redraw && screensaver.state() != PowerState::Off {
             display.clear();
-            let text_style = MonoTextStyle::new(&FONT_5X7, BinaryColor::On);
-            Text::new(format!("{}", dt.format("%Y-%m-%d")).as_str(), Point::new(10, 25), text_style)
-            .draw(&mut display)
-            .map_err(|e| anyhow::anyhow!("Draw text error: {:?}", e))?;
-            Text::new(format!("{}", dt.format("%H:%M:%S %Z")).as_str(), Point::new(10, 39), text_style)
-            .draw(&mut display)
-            .map_err(|e| anyhow::anyhow!("Draw text error: {:?}", e))?;
+            if menu_open {
+                let area = display.bounding_box();
+                menu.draw(&mut display, area)
+                    .map_err(|e| anyhow::anyhow!("Draw menu error: {:?}", e))?;
+            } else {
+                draw_clock_face(&mut display, &local_now())
+                    .map_err(|e| anyhow::anyhow!("Draw text error: {:?}", e))?;
+            }
             display.flush().map_err(|e| anyhow::anyhow!("Display flush error: {:?}", e))?;
+        }
+
+        FreeRtos::delay_ms(20);
+    }
+}
+
+/// 設定メニューの項目
+///
+/// `networks` は保存されている Wi-Fi の接続先 (優先度の高い順)
+fn settings_menu(networks: &[KnownNetwork]) -> Menu {
+    Menu::new("Settings", vec![
+        MenuItem::choice(
+            "tz",
+            "Time zone",
+            TIME_ZONES.iter().map(|(name, _)| name.to_string()).collect(),
+            0,
+        ),
+        MenuItem::submenu("Display", vec![
+            MenuItem::number("brightness", "Brightness", DEFAULT_CONTRAST as i32, 0, 255, 16),
+            MenuItem::number("dim_after", "Dim after (s)", 30, 10, 600, 10),
+            MenuItem::toggle("invert", "Invert", false),
+            MenuItem::toggle("rotate", "Auto rotate", true),
+        ]),
+        MenuItem::submenu("Wi-Fi", vec![
+            wifi_choice(networks),
+            MenuItem::action("restart", "Restart"),
+        ]),
+        MenuItem::action("close", "Close"),
+    ])
+}
 
-            FreeRtos::delay_ms(500);
+/// 優先して接続する Wi-Fi の選択肢。先頭 (最も優先度が高いもの) を選んだ状態にする
+fn wifi_choice(networks: &[KnownNetwork]) -> MenuItem {
+    MenuItem::choice(
+        "wifi",
+        "Network",
+        networks.iter().map(|network| network.ssid().to_string()).collect(),
+        0,
+    )
+}
+
+/// メニューで選んだ Wi-Fi を最も高い優先度にする (次に接続するときから使われる)
+fn apply_wifi_selection(menu: &mut Menu, store: &mut CredentialStore) -> Result<()> {
+    let networks = store.networks()?;
+    let selected = menu.choice_index("wifi").unwrap_or(0);
+    let (Some(top), Some(network)) = (networks.first(), networks.get(selected)) else {
+        return Ok(());
+    };
+    // 優先度の高い順に並んでいるので、先頭以外を選んだときだけ変更する
+    if selected != 0 {
+        store.set_priority(network.ssid(), top.priority.saturating_add(1))?;
+        log::info!("Preferred Wi-Fi: {}", network.ssid());
+        if let Some(item) = menu.item_mut("wifi") {
+            *item = wifi_choice(&store.networks()?);
+        }
+    }
+    Ok(())
+}
+
+/// メニューで選んだタイムゾーンを設定する
+fn apply_time_zone(menu: &Menu) {
+    let index = menu.choice_index("tz").unwrap_or(0);
+    let (name, tz) = TIME_ZONES[index.min(TIME_ZONES.len() - 1)];
+    env::set_var("TZ", tz);
+    unsafe { tzset() }
+    log::info!("Time zone: {}", name);
+}
+
+/// メニューで選んだ明るさと減光までの時間を設定する
+fn apply_power_settings(menu: &Menu, screensaver: &mut Screensaver) {
+    let brightness = menu.number_value("brightness").unwrap_or(DEFAULT_CONTRAST as i32);
+    let dim_after = menu.number_value("dim_after").unwrap_or(30) as u64;
+    screensaver.set_contrast(brightness as u8, (brightness / 8) as u8);
+    screensaver.set_timeouts(Duration::from_secs(dim_after), Duration::from_secs(dim_after * 10));
+}
+
+/// 押すと LOW になるボタンとして GPIO を設定する
+fn input_button(pin: AnyIOPin) -> Result<Button<PinDriver<'static, AnyIOPin, Input>>> {
+    let mut driver = PinDriver::input(pin)?;
+    driver.set_pull(Pull::Up)?;
+    Ok(Button::active_low(driver))
+}
+
+/// 設定したタイムゾーンでの現在時刻
+fn local_now() -> chrono::NaiveDateTime {
+    // gettimeofdayを呼び出してUNIXタイムスタンプを取得
+    let mut tv = timeval{
+        tv_sec: 0,
+        tv_usec: 0,
+    };
+    // localtime_r は TZ 環境変数に従って変換する
+    let mut local: tm = unsafe { std::mem::zeroed() };
+    unsafe {
+        gettimeofday(&mut tv, std::ptr::null_mut());
+        localtime_r(&tv.tv_sec, &mut local);
     }
+    chrono::NaiveDate::from_ymd_opt(local.tm_year + 1900, (local.tm_mon + 1) as u32, local.tm_mday as u32)
+        .and_then(|date| date.and_hms_opt(local.tm_hour as u32, local.tm_min as u32, local.tm_sec as u32))
+        .expect("Invalid timestamp")
 }
 
 fn neopixel(rgb: Rgb, tx: &mut TxRmtDriver) -> anyhow::Result<()> {
@@ -233,33 +457,69 @@ impl From<Rgb> for u32 {
     }
 }
 
-fn connect_wifi(wifi_driver: &mut BlockingWifi<EspWifi<'static>>) -> Result<()>
+/// 時刻 (HH:MM) を大きな7セグメント表示で、日付と秒を下のステータス行に描画する
+///
+/// 画面が縦長 (90/270 度回転) のときは時と分を2段に分けて表示する
+fn draw_clock_face<D>(display: &mut D, dt: &chrono::NaiveDateTime) -> Result<(), D::Error>
+    where D: DrawTarget<Color = BinaryColor>
 {
-    let ssid = String::<32>::try_from(SSID_STR).unwrap();
-    let password = String::<64>::try_from(SSID_PASSWORD_STR).unwrap();
-
-    wifi_driver.set_configuration(&Configuration::Client(ClientConfiguration{
-        ssid,
-        password,
-        ..Default::default()
-    })).unwrap();
-
-    wifi_driver.start()?;
-    wifi_driver.connect()?;
-    while !wifi_driver.is_connected().unwrap(){
-        let config = wifi_driver.get_configuration().unwrap();
-        println!("Waiting for station {:?}", config);
+    let size = display.bounding_box().size;
+    let width = size.width as i32;
+    let style = SevenSegmentStyle::new(Size::new(24, 44), 5).with_spacing(4);
+    let text_style = MonoTextStyle::new(&FONT_5X7, BinaryColor::On);
+
+    if size.height > size.width {
+        for (i, part) in [dt.format("%H"), dt.format("%M")].iter().enumerate() {
+            let digits = format!("{}", part);
+            let x = (width - style.text_size(&digits).width as i32) / 2;
+            SevenSegment::new(&digits, Point::new(x, 4 + i as i32 * 50), style).draw(display)?;
+        }
+        let center = TextStyleBuilder::new().alignment(Alignment::Center).baseline(Baseline::Top).build();
+        Text::with_text_style(
+            format!("{}", dt.format("%m-%d %a")).as_str(),
+            Point::new(width / 2, 106),
+            text_style,
+            center,
+        )
+        .draw(display)?;
+        Text::with_text_style(
+            format!("{}", dt.format(":%S")).as_str(),
+            Point::new(width / 2, 116),
+            text_style,
+            center,
+        )
+        .draw(display)?;
+        return Ok(());
     }
-    wifi_driver.wait_netif_up()?;
+
+    let time = format!("{}", dt.format("%H:%M"));
+    let x = (width - style.text_size(&time).width as i32) / 2;
+    SevenSegment::new(&time, Point::new(x, 2), style).draw(display)?;
+
+    Text::with_baseline(
+        format!("{}", dt.format("%Y-%m-%d %a")).as_str(),
+        Point::new(2, 56),
+        text_style,
+        Baseline::Top,
+    )
+    .draw(display)?;
+    Text::with_text_style(
+        format!("{}", dt.format(":%S")).as_str(),
+        Point::new(width - 2, 56),
+        text_style,
+        TextStyleBuilder::new().alignment(Alignment::Right).baseline(Baseline::Top).build(),
+    )
+    .draw(display)?;
     Ok(())
 }
 
-fn show_msg_log<T>(display: &mut GraphicsMode<T>, msg :&str) -> Result<()>
+/// ログコンソールの内容を画面に表示する
+fn show_msg_log<T>(display: &mut BufferedDisplay<T>, console: &Mutex<TextConsole>) -> Result<()>
     where T: DisplayInterface, <T as DisplayInterface>::Error: std::fmt::Debug
 {
-    display.clear();
-    let text_style = MonoTextStyle::new(&FONT_5X7, BinaryColor::On);
-    Text::new(msg, Point::new(5, 25), text_style)
+    console
+        .lock()
+        .map_err(|_| anyhow::anyhow!("Console lock poisoned"))?
         .draw(display)
         .map_err(|e| anyhow::anyhow!("Draw text error: {:?}", e))?;
     display.flush().map_err(|e| anyhow::anyhow!("Display flush error: {:?}", e))?;
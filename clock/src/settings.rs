@@ -0,0 +1,70 @@
+//! 設定メニューの値を NVS に保存する
+//!
+//! `PERSISTED_KEYS` の項目をメニューのキーと同じ名前で `clock` 名前空間に保存し、起動時に読み込みます。
+//! 数値は `i32`、ON/OFF は `u8`、選択肢はインデックスを `u32` で保存します。
+
+use esp_idf_svc::nvs::{EspDefaultNvsPartition, EspNvs, NvsDefault};
+use esp_idf_svc::sys::EspError;
+
+use esp32s2_common_lib::menu::{Menu, MenuItem};
+
+/// NVS の名前空間
+const NAMESPACE: &str = "clock";
+
+/// 保存するメニュー項目のキー
+pub const PERSISTED_KEYS: &[&str] = &["tz", "brightness", "dim_after", "invert", "rotate"];
+
+/// NVS に保存した設定メニューの値
+pub struct SettingsStore {
+    nvs: EspNvs<NvsDefault>,
+}
+
+impl SettingsStore {
+    /// デフォルトの NVS パーティションの `clock` 名前空間を開きます。
+    pub fn new(partition: EspDefaultNvsPartition) -> Result<Self, EspError> {
+        Ok(Self {
+            nvs: EspNvs::new(partition, NAMESPACE, true)?,
+        })
+    }
+
+    /// 保存されている値をメニューに読み込みます。保存されていない項目や範囲外の値は初期値のままにします。
+    pub fn load(&self, menu: &mut Menu) -> Result<(), EspError> {
+        for &key in PERSISTED_KEYS {
+            match menu.item_mut(key) {
+                Some(MenuItem::Number { value, min, max, .. }) => {
+                    if let Some(saved) = self.nvs.get_i32(key)? {
+                        *value = saved.clamp(*min, *max);
+                    }
+                }
+                Some(MenuItem::Toggle { value, .. }) => {
+                    if let Some(saved) = self.nvs.get_u8(key)? {
+                        *value = saved != 0;
+                    }
+                }
+                Some(MenuItem::Choice { options, selected, .. }) => {
+                    if let Some(saved) = self.nvs.get_u32(key)? {
+                        if (saved as usize) < options.len() {
+                            *selected = saved as usize;
+                        }
+                    }
+                }
+                _ => {}
+            }
+        }
+        Ok(())
+    }
+
+    /// メニューの値を保存します。
+    pub fn save(&mut self, menu: &Menu) -> Result<(), EspError> {
+        for &key in PERSISTED_KEYS {
+            match menu.item(key) {
+                Some(MenuItem::Number { value, .. }) => self.nvs.set_i32(key, *value)?,
+                Some(MenuItem::Toggle { value, .. }) => self.nvs.set_u8(key, *value as u8)?,
+                Some(MenuItem::Choice { selected, .. }) => self.nvs.set_u32(key, *selected as u32)?,
+                _ => {}
+            }
+        }
+        log::info!("Settings saved");
+        Ok(())
+    }
+}
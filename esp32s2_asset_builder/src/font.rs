@@ -0,0 +1,439 @@
+//! BDF フォントを読み込み、使う文字だけを取り出す
+//!
+//! Unicode (`CHARSET_REGISTRY "ISO10646"`) でエンコードされた BDF に対応します。
+//! JIS X 0208 などでエンコードされたフォントは、あらかじめ Unicode に変換してください。
+//!
+//! 取り出す文字は `FontSubset` で指定します。ASCII・かなはまとめて、漢字は文字列か
+//! ソースコードの文字列リテラルから必要なものだけを選びます。
+
+use std::collections::{BTreeMap, BTreeSet};
+use std::fs;
+use std::path::{Path, PathBuf};
+
+use crate::AssetError;
+
+/// BDF の1文字
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct BdfGlyph {
+    /// 次の文字までの幅 (DWIDTH)
+    pub advance: i32,
+    /// ビットマップの大きさ (BBX)
+    pub width: u32,
+    pub height: u32,
+    /// ベースライン上の描画位置からビットマップの左下までのずれ (上が正)
+    pub x_offset: i32,
+    pub y_offset: i32,
+    /// 行ごとに上位ビットから詰めたビットマップ (1行 `ceil(width / 8)` バイト)
+    pub data: Vec<u8>,
+}
+
+/// BDF フォント
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct BdfFont {
+    /// ベースラインより上の高さ
+    pub ascent: i32,
+    /// ベースラインより下の高さ
+    pub descent: i32,
+    pub glyphs: BTreeMap<char, BdfGlyph>,
+}
+
+impl BdfFont {
+    /// ファイルを読み込みます。
+    pub fn load(path: impl AsRef<Path>) -> Result<Self, AssetError> {
+        let path = path.as_ref();
+        let text = fs::read(path).map_err(|e| AssetError::Io(path.display().to_string(), e))?;
+        // COMMENT などに UTF-8 以外の文字が含まれていても読めるようにする
+        Self::parse(&String::from_utf8_lossy(&text))
+            .map_err(|message| AssetError::Format(path.display().to_string(), message))
+    }
+
+    /// BDF の内容を解析します。
+    pub fn parse(text: &str) -> Result<Self, String> {
+        let mut lines = text.lines().enumerate();
+        let mut ascent = None;
+        let mut descent = None;
+        let mut bounding_box = None;
+        let mut glyphs = BTreeMap::new();
+
+        match lines.next() {
+            Some((_, line)) if line.starts_with("STARTFONT") => {}
+            _ => return Err("not a BDF file (missing STARTFONT)".to_string()),
+        }
+
+        while let Some((number, line)) = lines.next() {
+            let mut words = line.split_whitespace();
+            match words.next() {
+                Some("FONTBOUNDINGBOX") => bounding_box = Some(numbers::<4>(words, number)?),
+                Some("FONT_ASCENT") => ascent = Some(numbers::<1>(words, number)?[0]),
+                Some("FONT_DESCENT") => descent = Some(numbers::<1>(words, number)?[0]),
+                Some("CHARSET_REGISTRY") => {
+                    let registry = words.next().unwrap_or("").trim_matches('"');
+                    if !registry.eq_ignore_ascii_case("ISO10646") {
+                        return Err(format!(
+                            "unsupported CHARSET_REGISTRY {:?} (convert the font to ISO10646 first)",
+                            registry
+                        ));
+                    }
+                }
+                Some("STARTCHAR") => {
+                    let (ch, glyph) = parse_glyph(&mut lines, bounding_box)?;
+                    if let Some(ch) = ch {
+                        glyphs.insert(ch, glyph);
+                    }
+                }
+                _ => {}
+            }
+        }
+
+        // FONT_ASCENT / FONT_DESCENT がなければ FONTBOUNDINGBOX から求める
+        let [_, height, _, y_offset] = bounding_box.unwrap_or([0, 0, 0, 0]);
+        Ok(Self {
+            ascent: ascent.unwrap_or(height + y_offset),
+            descent: descent.unwrap_or(-y_offset),
+            glyphs,
+        })
+    }
+}
+
+/// STARTCHAR から ENDCHAR までを読み込みます。Unicode 以外の文字は `None` を返します。
+fn parse_glyph<'a>(
+    lines: &mut impl Iterator<Item = (usize, &'a str)>,
+    bounding_box: Option<[i32; 4]>,
+) -> Result<(Option<char>, BdfGlyph), String> {
+    let mut encoding = None;
+    let mut advance = None;
+    let [mut width, mut height, mut x_offset, mut y_offset] = bounding_box.unwrap_or([0, 0, 0, 0]);
+
+    for (number, line) in lines.by_ref() {
+        let mut words = line.split_whitespace();
+        match words.next() {
+            Some("ENCODING") => encoding = Some(numbers::<1>(words, number)?[0]),
+            Some("DWIDTH") => advance = Some(numbers::<2>(words, number)?[0]),
+            Some("BBX") => [width, height, x_offset, y_offset] = numbers::<4>(words, number)?,
+            Some("BITMAP") => break,
+            Some("ENDCHAR") => return Err(format!("line {}: ENDCHAR without BITMAP", number + 1)),
+            _ => {}
+        }
+    }
+
+    let width = width.max(0) as u32;
+    let height = height.max(0) as u32;
+    let bytes_per_row = (width as usize).div_ceil(8);
+    let mut data = Vec::with_capacity(bytes_per_row * height as usize);
+    for (number, line) in lines.by_ref() {
+        let line = line.trim();
+        if line == "ENDCHAR" {
+            break;
+        }
+        // 1行の16進数は幅より長い場合があるので、必要なバイト数だけ使う
+        for i in 0..bytes_per_row {
+            let byte = line
+                .get(i * 2..i * 2 + 2)
+                .and_then(|hex| u8::from_str_radix(hex, 16).ok())
+                .ok_or_else(|| format!("line {}: invalid bitmap row {:?}", number + 1, line))?;
+            data.push(byte);
+        }
+    }
+    if data.len() != bytes_per_row * height as usize {
+        return Err(format!("glyph U+{:04X}: bitmap has wrong number of rows", encoding.unwrap_or(-1)));
+    }
+
+    let ch = encoding
+        .filter(|&code| code >= 0)
+        .and_then(|code| char::from_u32(code as u32));
+    let glyph = BdfGlyph {
+        advance: advance.unwrap_or(width as i32),
+        width,
+        height,
+        x_offset,
+        y_offset,
+        data,
+    };
+    Ok((ch, glyph))
+}
+
+fn numbers<'a, const N: usize>(
+    mut words: impl Iterator<Item = &'a str>,
+    number: usize,
+) -> Result<[i32; N], String> {
+    let mut values = [0; N];
+    for value in &mut values {
+        *value = words
+            .next()
+            .and_then(|word| word.parse().ok())
+            .ok_or_else(|| format!("line {}: expected {} numbers", number + 1, N))?;
+    }
+    Ok(values)
+}
+
+/// フォントから取り出す文字の集合
+#[derive(Debug, Clone, Default)]
+pub struct FontSubset {
+    chars: BTreeSet<char>,
+    /// フォントになくても警告しない文字 (範囲でまとめて追加したもの)
+    optional: BTreeSet<char>,
+    sources: Vec<PathBuf>,
+}
+
+impl FontSubset {
+    /// 空の集合を作成します。
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// 表示できる ASCII 文字 (空白から `~` まで) を追加します。
+    pub fn ascii(self) -> Self {
+        self.range(' ', '~')
+    }
+
+    /// ひらがな・カタカナ・全角の句読点と長音記号を追加します。
+    pub fn kana(self) -> Self {
+        self.range('\u{3041}', '\u{3096}')
+            .range('\u{30A1}', '\u{30FA}')
+            .range('\u{3001}', '\u{3002}')
+            .range('\u{30FC}', '\u{30FC}')
+            .range('\u{300C}', '\u{300D}')
+    }
+
+    /// `first` から `last` までの文字を追加します。フォントにない文字は無視します。
+    pub fn range(mut self, first: char, last: char) -> Self {
+        self.optional.extend(first..=last);
+        self
+    }
+
+    /// `text` に含まれる文字を追加します。フォントにない文字はビルド時に警告します。
+    pub fn chars(mut self, text: &str) -> Self {
+        self.chars.extend(text.chars().filter(|ch| !ch.is_control()));
+        self
+    }
+
+    /// ファイル (ディレクトリなら中の `.rs` ファイルすべて) の文字列リテラルに含まれる文字を追加します。
+    ///
+    /// コメントの文字は含めません。ファイルが変更されたら再ビルドされます。
+    pub fn scan_sources(mut self, path: impl Into<PathBuf>) -> Self {
+        self.sources.push(path.into());
+        self
+    }
+
+    /// 追加したソースファイルのパス
+    pub(crate) fn source_paths(&self, base_dir: &Path) -> Vec<PathBuf> {
+        self.sources.iter().map(|path| base_dir.join(path)).collect()
+    }
+
+    /// 取り出す文字と、フォントにない場合に警告する文字を求めます。
+    pub(crate) fn resolve(&self, base_dir: &Path) -> Result<(BTreeSet<char>, BTreeSet<char>), AssetError> {
+        let mut required = self.chars.clone();
+        for path in self.source_paths(base_dir) {
+            for file in rust_files(&path)? {
+                let source = fs::read_to_string(&file).map_err(|e| AssetError::Io(file.display().to_string(), e))?;
+                required.extend(string_literal_chars(&source));
+            }
+        }
+        let mut all = required.clone();
+        all.extend(&self.optional);
+        // 見つからない文字の代わりに表示する
+        all.insert('?');
+        Ok((all, required))
+    }
+}
+
+/// `path` がディレクトリなら中の `.rs` ファイルを再帰的に集め、ファイルならそのまま返します。
+pub(crate) fn rust_files(path: &Path) -> Result<Vec<PathBuf>, AssetError> {
+    if !path.is_dir() {
+        return Ok(vec![path.to_path_buf()]);
+    }
+    let io_error = |e| AssetError::Io(path.display().to_string(), e);
+    let mut files = Vec::new();
+    for entry in fs::read_dir(path).map_err(io_error)? {
+        let entry_path = entry.map_err(io_error)?.path();
+        if entry_path.is_dir() {
+            files.extend(rust_files(&entry_path)?);
+        } else if entry_path.extension().is_some_and(|e| e == "rs") {
+            files.push(entry_path);
+        }
+    }
+    files.sort();
+    Ok(files)
+}
+
+/// Rust のソースコードから文字列リテラルの中の文字を取り出します。
+///
+/// コメントと文字リテラルは読み飛ばします。エスケープはそのまま文字として扱います。
+/// 生文字列リテラル (`r"..."`, `r#"..."#`, `br"..."` など) はエスケープを解釈せずに中身を取り出します。
+pub fn string_literal_chars(source: &str) -> BTreeSet<char> {
+    let mut result = BTreeSet::new();
+    let mut chars = source.chars().peekable();
+    while let Some(ch) = chars.next() {
+        match ch {
+            '/' if chars.peek() == Some(&'/') => {
+                for ch in chars.by_ref() {
+                    if ch == '\n' {
+                        break;
+                    }
+                }
+            }
+            '/' if chars.peek() == Some(&'*') => {
+                chars.next();
+                let mut previous = ' ';
+                for ch in chars.by_ref() {
+                    if previous == '*' && ch == '/' {
+                        break;
+                    }
+                    previous = ch;
+                }
+            }
+            '\'' => {
+                // 文字リテラル ('"' など) を読み飛ばす。ライフタイム ('a) は1文字進むだけ
+                let mut lookahead = chars.clone();
+                match (lookahead.next(), lookahead.next()) {
+                    (Some('\\'), _) => {
+                        chars.next();
+                        chars.next();
+                        for ch in chars.by_ref() {
+                            if ch == '\'' {
+                                break;
+                            }
+                        }
+                    }
+                    (Some(_), Some('\'')) => {
+                        chars.next();
+                        chars.next();
+                    }
+                    _ => {}
+                }
+            }
+            'r' => {
+                // 生文字列リテラルは `"` と開始時と同じ数の `#` で終わる。生識別子 (r#type) は読み飛ばさない
+                let mut lookahead = chars.clone();
+                let mut hashes = 0;
+                while lookahead.peek() == Some(&'#') {
+                    lookahead.next();
+                    hashes += 1;
+                }
+                if lookahead.next() != Some('"') {
+                    continue;
+                }
+                chars = lookahead;
+                let mut content = Vec::new();
+                for ch in chars.by_ref() {
+                    content.push(ch);
+                    let closing = content.len() > hashes
+                        && content[content.len() - hashes - 1] == '"'
+                        && content[content.len() - hashes..].iter().all(|&c| c == '#');
+                    if closing {
+                        content.truncate(content.len() - hashes - 1);
+                        break;
+                    }
+                }
+                result.extend(content.into_iter().filter(|ch| !ch.is_control()));
+            }
+            '"' => {
+                while let Some(ch) = chars.next() {
+                    match ch {
+                        '"' => break,
+                        '\\' => {
+                            chars.next();
+                        }
+                        ch if !ch.is_control() => {
+                            result.insert(ch);
+                        }
+                        _ => {}
+                    }
+                }
+            }
+            _ => {}
+        }
+    }
+    result
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn set(chars: &str) -> BTreeSet<char> {
+        chars.chars().collect()
+    }
+
+    const FONT: &str = "STARTFONT 2.1
+FONT test
+FONTBOUNDINGBOX 5 3 0 -1
+CHARSET_REGISTRY \"ISO10646\"
+CHARS 2
+STARTCHAR A
+ENCODING 65
+DWIDTH 6 0
+BITMAP
+70
+F8
+88
+ENDCHAR
+STARTCHAR uni5B57
+ENCODING 23383
+DWIDTH 10 0
+BBX 9 2 1 0
+BITMAP
+FF80AB
+80800000
+ENDCHAR
+ENDFONT
+";
+
+    #[test]
+    fn parses_bdf_with_bounding_box_fallback() {
+        let font = BdfFont::parse(FONT).unwrap();
+        // FONT_ASCENT / FONT_DESCENT がないので FONTBOUNDINGBOX から求める
+        assert_eq!((font.ascent, font.descent), (2, 1));
+
+        // BBX がない文字は FONTBOUNDINGBOX の大きさを使う
+        let a = &font.glyphs[&'A'];
+        assert_eq!((a.advance, a.width, a.height, a.x_offset, a.y_offset), (6, 5, 3, 0, -1));
+        assert_eq!(a.data, vec![0x70, 0xF8, 0x88]);
+
+        // 幅より長い16進数の行は、必要なバイト数だけ使う
+        let kanji = &font.glyphs[&'字'];
+        assert_eq!((kanji.advance, kanji.width, kanji.height, kanji.x_offset, kanji.y_offset), (10, 9, 2, 1, 0));
+        assert_eq!(kanji.data, vec![0xFF, 0x80, 0x80, 0x80]);
+    }
+
+    #[test]
+    fn rejects_invalid_bdf() {
+        assert!(BdfFont::parse("FONT test\n").is_err());
+        let short = FONT.replace("88\nENDCHAR", "ENDCHAR");
+        assert!(BdfFont::parse(&short).is_err());
+        let bad_row = FONT.replace("F8\n", "G8\n");
+        assert!(BdfFont::parse(&bad_row).is_err());
+        let jis = FONT.replace("\"ISO10646\"", "\"JISX0208.1983\"");
+        assert!(BdfFont::parse(&jis).is_err());
+    }
+
+    #[test]
+    fn skips_char_literals_and_lifetimes() {
+        assert_eq!(string_literal_chars("let c = 'a';"), set(""));
+        assert_eq!(string_literal_chars(r#"let c = '"'; let s = "字";"#), set("字"));
+        assert_eq!(string_literal_chars(r#"let c = '\''; let s = "字";"#), set("字"));
+        assert_eq!(string_literal_chars(r#"fn f<'a>(s: &'a str) -> &'a str { "時計" }"#), set("時計"));
+    }
+
+    #[test]
+    fn handles_escapes_in_string_literals() {
+        assert_eq!(string_literal_chars(r#"let s = "\"";"#), set(""));
+        assert_eq!(string_literal_chars(r#"let s = "\"字\\"; let t = "分";"#), set("字分"));
+    }
+
+    #[test]
+    fn skips_comments() {
+        assert_eq!(string_literal_chars("// \"無視\"\nlet s = \"表示\";"), set("表示"));
+        assert_eq!(string_literal_chars("/* \"無視\" */ let s = \"表示\";"), set("表示"));
+        assert_eq!(string_literal_chars("/** \"無視\" **/ let s = \"表\";"), set("表"));
+    }
+
+    #[test]
+    fn handles_raw_string_literals() {
+        assert_eq!(string_literal_chars(r##"let s = r"字\";"##), set("字\\"));
+        assert_eq!(string_literal_chars(r###"let s = r#"引用 "文" 字"#; let t = "分";"###), set("引用 \"文字分"));
+        assert_eq!(string_literal_chars(r###"let s = br##"a"#b"##;"###), set("a\"#b"));
+        // 生識別子は文字列ではない
+        assert_eq!(string_literal_chars("let r#type = 1; let s = \"型\";"), set("型"));
+    }
+}
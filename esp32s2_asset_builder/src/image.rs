@@ -0,0 +1,343 @@
+//! PBM / BMP 画像を1ビットのモノクロ画像に変換する
+//!
+//! 変換後のデータは embedded-graphics の `ImageRaw<BinaryColor>` と同じ形式です
+//! (行ごとに上位ビットから詰め、行の終わりはバイト境界まで 0 で埋める。1 が点灯)。
+//! 元画像の暗い画素 (PBM の 1、BMP の輝度 50% 未満) を点灯する画素として扱います。
+
+use std::fs;
+use std::path::Path;
+
+use crate::AssetError;
+
+/// 1ビットのモノクロ画像
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct MonoImage {
+    pub width: u32,
+    pub height: u32,
+    /// 行ごとに詰めた画素データ
+    pub data: Vec<u8>,
+}
+
+impl MonoImage {
+    /// すべて消灯した画像を作成します。
+    pub fn new(width: u32, height: u32) -> Self {
+        Self {
+            width,
+            height,
+            data: vec![0; Self::bytes_per_row(width) * height as usize],
+        }
+    }
+
+    /// 1行あたりのバイト数
+    pub fn bytes_per_row(width: u32) -> usize {
+        (width as usize).div_ceil(8)
+    }
+
+    /// 画素の値を取得します。範囲外は `false` を返します。
+    pub fn get_pixel(&self, x: u32, y: u32) -> bool {
+        if x >= self.width || y >= self.height {
+            return false;
+        }
+        let index = y as usize * Self::bytes_per_row(self.width) + x as usize / 8;
+        self.data[index] & (0x80 >> (x % 8)) != 0
+    }
+
+    /// 画素の値を設定します。範囲外は無視します。
+    pub fn set_pixel(&mut self, x: u32, y: u32, on: bool) {
+        if x >= self.width || y >= self.height {
+            return;
+        }
+        let index = y as usize * Self::bytes_per_row(self.width) + x as usize / 8;
+        let mask = 0x80 >> (x % 8);
+        if on {
+            self.data[index] |= mask;
+        } else {
+            self.data[index] &= !mask;
+        }
+    }
+
+    /// 点灯・消灯を反転します。
+    pub fn invert(&mut self) {
+        for y in 0..self.height {
+            for x in 0..self.width {
+                let on = self.get_pixel(x, y);
+                self.set_pixel(x, y, !on);
+            }
+        }
+    }
+
+    /// ファイルを読み込みます。形式は拡張子 (`.pbm` / `.bmp`) で判断します。
+    pub fn load(path: impl AsRef<Path>) -> Result<Self, AssetError> {
+        let path = path.as_ref();
+        let bytes = fs::read(path).map_err(|e| AssetError::Io(path.display().to_string(), e))?;
+        let extension = path
+            .extension()
+            .and_then(|e| e.to_str())
+            .map(|e| e.to_ascii_lowercase());
+        let result = match extension.as_deref() {
+            Some("pbm") => Self::from_pbm(&bytes),
+            Some("bmp") => Self::from_bmp(&bytes),
+            _ => Err("unsupported file extension (expected .pbm or .bmp)".to_string()),
+        };
+        result.map_err(|message| AssetError::Format(path.display().to_string(), message))
+    }
+
+    /// PBM (P1: テキスト形式, P4: バイナリ形式) を読み込みます。
+    pub fn from_pbm(bytes: &[u8]) -> Result<Self, String> {
+        let mut reader = PbmReader { bytes, position: 0 };
+        let magic = reader.token().ok_or("missing PBM header")?;
+        let width = reader.number().ok_or("missing PBM width")?;
+        let height = reader.number().ok_or("missing PBM height")?;
+        let mut image = Self::new(width, height);
+
+        match magic {
+            b"P1" => {
+                for y in 0..height {
+                    for x in 0..width {
+                        let on = reader.bit().ok_or("PBM pixel data is too short")?;
+                        image.set_pixel(x, y, on);
+                    }
+                }
+            }
+            b"P4" => {
+                // ヘッダーの後の空白1文字に続いて、ImageRaw と同じ形式のデータが並ぶ
+                let start = reader.position + 1;
+                let data = bytes
+                    .get(start..start + image.data.len())
+                    .ok_or("PBM pixel data is too short")?;
+                image.data.copy_from_slice(data);
+            }
+            _ => return Err("not a PBM file (expected P1 or P4)".to_string()),
+        }
+        Ok(image)
+    }
+
+    /// 無圧縮の BMP (1/4/8/24/32 ビット) を読み込みます。
+    pub fn from_bmp(bytes: &[u8]) -> Result<Self, String> {
+        if bytes.get(0..2) != Some(b"BM") {
+            return Err("not a BMP file".to_string());
+        }
+        let u16_at = |offset: usize| -> Result<u16, String> {
+            bytes
+                .get(offset..offset + 2)
+                .map(|b| u16::from_le_bytes([b[0], b[1]]))
+                .ok_or_else(|| "BMP header is too short".to_string())
+        };
+        let u32_at = |offset: usize| -> Result<u32, String> {
+            bytes
+                .get(offset..offset + 4)
+                .map(|b| u32::from_le_bytes([b[0], b[1], b[2], b[3]]))
+                .ok_or_else(|| "BMP header is too short".to_string())
+        };
+
+        let data_offset = u32_at(10)? as usize;
+        let header_size = u32_at(14)? as usize;
+        let width = u32_at(18)? as i32;
+        let height = u32_at(22)? as i32;
+        let bits_per_pixel = u16_at(28)?;
+        let compression = u32_at(30)?;
+        if width <= 0 || height == 0 {
+            return Err("invalid BMP size".to_string());
+        }
+        // 32ビットの BI_BITFIELDS は BGRA の並びとみなす
+        if compression != 0 && !(compression == 3 && bits_per_pixel == 32) {
+            return Err("compressed BMP is not supported".to_string());
+        }
+
+        // パレット (BGRA) を点灯するかどうかの表にする
+        let palette: Vec<bool> = if bits_per_pixel <= 8 {
+            let colors = match u32_at(46)? {
+                0 => 1 << bits_per_pixel,
+                n => n as usize,
+            };
+            let start = 14 + header_size;
+            (0..colors)
+                .map(|i| {
+                    let entry = bytes
+                        .get(start + i * 4..start + i * 4 + 3)
+                        .ok_or_else(|| "BMP palette is too short".to_string())?;
+                    Ok(is_dark(entry[2], entry[1], entry[0]))
+                })
+                .collect::<Result<_, String>>()?
+        } else {
+            Vec::new()
+        };
+
+        let width = width as u32;
+        // 高さが正なら下の行から、負なら上の行から並んでいる
+        let bottom_up = height > 0;
+        let height = height.unsigned_abs();
+        let row_size = (width as usize * bits_per_pixel as usize).div_ceil(32) * 4;
+        let mut image = Self::new(width, height);
+
+        for row in 0..height {
+            let start = data_offset + row as usize * row_size;
+            let pixels = bytes
+                .get(start..start + row_size)
+                .ok_or("BMP pixel data is too short")?;
+            let y = if bottom_up { height - 1 - row } else { row };
+            for x in 0..width {
+                let on = match bits_per_pixel {
+                    1 | 4 | 8 => {
+                        let bit = x as usize * bits_per_pixel as usize;
+                        let shift = 8 - bits_per_pixel as usize - bit % 8;
+                        let mask = (1u16 << bits_per_pixel) - 1;
+                        let index = (pixels[bit / 8] >> shift) as u16 & mask;
+                        palette.get(index as usize).copied().unwrap_or(false)
+                    }
+                    24 | 32 => {
+                        let i = x as usize * bits_per_pixel as usize / 8;
+                        is_dark(pixels[i + 2], pixels[i + 1], pixels[i])
+                    }
+                    _ => return Err(format!("{}-bit BMP is not supported", bits_per_pixel)),
+                };
+                image.set_pixel(x, y, on);
+            }
+        }
+        Ok(image)
+    }
+}
+
+/// 輝度が 50% 未満かどうか
+fn is_dark(r: u8, g: u8, b: u8) -> bool {
+    let luma = (r as u32 * 299 + g as u32 * 587 + b as u32 * 114) / 1000;
+    luma < 128
+}
+
+/// PBM のヘッダーと P1 の画素を読み取る
+struct PbmReader<'a> {
+    bytes: &'a [u8],
+    position: usize,
+}
+
+impl<'a> PbmReader<'a> {
+    /// 空白とコメント (`#` から行末まで) を読み飛ばします。
+    fn skip_whitespace(&mut self) {
+        while let Some(&c) = self.bytes.get(self.position) {
+            if c == b'#' {
+                while self.bytes.get(self.position).is_some_and(|&c| c != b'\n') {
+                    self.position += 1;
+                }
+            } else if c.is_ascii_whitespace() {
+                self.position += 1;
+            } else {
+                break;
+            }
+        }
+    }
+
+    fn token(&mut self) -> Option<&'a [u8]> {
+        self.skip_whitespace();
+        let start = self.position;
+        while self.bytes.get(self.position).is_some_and(|c| !c.is_ascii_whitespace()) {
+            self.position += 1;
+        }
+        (self.position > start).then(|| &self.bytes[start..self.position])
+    }
+
+    fn number(&mut self) -> Option<u32> {
+        std::str::from_utf8(self.token()?).ok()?.parse().ok()
+    }
+
+    /// P1 の画素を1つ読み取ります。数字の間に空白がなくても構いません。
+    fn bit(&mut self) -> Option<bool> {
+        self.skip_whitespace();
+        let c = *self.bytes.get(self.position)?;
+        self.position += 1;
+        match c {
+            b'0' => Some(false),
+            b'1' => Some(true),
+            _ => None,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// 1ビットの BMP を作る。`palette` は BGRA、`rows` はファイルに並ぶ順の行 (パディング込み)
+    fn bmp_1bit(width: i32, height: i32, palette: [[u8; 4]; 2], rows: &[[u8; 4]]) -> Vec<u8> {
+        let data_offset = 14 + 40 + 8;
+        let mut bytes = Vec::new();
+        bytes.extend_from_slice(b"BM");
+        bytes.extend_from_slice(&((data_offset + rows.len() * 4) as u32).to_le_bytes());
+        bytes.extend_from_slice(&[0; 4]);
+        bytes.extend_from_slice(&(data_offset as u32).to_le_bytes());
+        bytes.extend_from_slice(&40u32.to_le_bytes());
+        bytes.extend_from_slice(&width.to_le_bytes());
+        bytes.extend_from_slice(&height.to_le_bytes());
+        bytes.extend_from_slice(&1u16.to_le_bytes());
+        bytes.extend_from_slice(&1u16.to_le_bytes());
+        // 圧縮なし、画像サイズ、解像度 x/y、色数 (0 = 2色)、重要な色数
+        bytes.extend_from_slice(&[0; 24]);
+        for entry in palette {
+            bytes.extend_from_slice(&entry);
+        }
+        for row in rows {
+            bytes.extend_from_slice(row);
+        }
+        bytes
+    }
+
+    const WHITE: [u8; 4] = [0xFF, 0xFF, 0xFF, 0];
+    const BLACK: [u8; 4] = [0, 0, 0, 0];
+
+    #[test]
+    fn reads_p1() {
+        let image = MonoImage::from_pbm(b"P1\n# comment\n3 2\n1 0 1\n010\n").unwrap();
+        assert_eq!((image.width, image.height), (3, 2));
+        assert_eq!(image.data, vec![0b1010_0000, 0b0100_0000]);
+        assert!(image.get_pixel(0, 0) && !image.get_pixel(1, 0) && image.get_pixel(1, 1));
+    }
+
+    #[test]
+    fn reads_p4() {
+        let mut bytes = b"P4\n10 2\n".to_vec();
+        bytes.extend_from_slice(&[0xFF, 0xC0, 0x80, 0x40]);
+        let image = MonoImage::from_pbm(&bytes).unwrap();
+        assert_eq!((image.width, image.height), (10, 2));
+        assert_eq!(image.data, vec![0xFF, 0xC0, 0x80, 0x40]);
+        assert!(image.get_pixel(9, 0) && image.get_pixel(9, 1) && !image.get_pixel(8, 1));
+    }
+
+    #[test]
+    fn rejects_short_pbm() {
+        assert!(MonoImage::from_pbm(b"P1\n3 2\n1 0 1\n0").is_err());
+        assert!(MonoImage::from_pbm(b"P4\n10 2\n\xFF\xC0\x80").is_err());
+        assert!(MonoImage::from_pbm(b"P2\n1 1\n0\n").is_err());
+    }
+
+    #[test]
+    fn reads_bottom_up_bmp() {
+        // 下の行から並ぶ。パレットの 1 が黒 (点灯)
+        let rows = [[0b0100_0000, 0, 0, 0], [0b1010_0000, 0, 0, 0]];
+        let image = MonoImage::from_bmp(&bmp_1bit(3, 2, [WHITE, BLACK], &rows)).unwrap();
+        assert_eq!((image.width, image.height), (3, 2));
+        assert_eq!(image.data, vec![0b1010_0000, 0b0100_0000]);
+    }
+
+    #[test]
+    fn reads_top_down_bmp_with_inverted_palette() {
+        // 高さが負なら上の行から並ぶ。パレットの 0 が黒
+        let rows = [[0b1010_0000, 0, 0, 0], [0b0100_0000, 0, 0, 0]];
+        let image = MonoImage::from_bmp(&bmp_1bit(3, -2, [BLACK, WHITE], &rows)).unwrap();
+        assert_eq!(image.data, vec![0b0100_0000, 0b1010_0000]);
+    }
+
+    #[test]
+    fn ignores_bmp_row_padding() {
+        // 10 画素の行は 4 バイト境界まで埋められる。幅の外のビットとパディングは使わない
+        let rows = [[0xFF, 0xFF, 0xFF, 0xFF], [0x00, 0x7F, 0xFF, 0xFF]];
+        let image = MonoImage::from_bmp(&bmp_1bit(10, 2, [WHITE, BLACK], &rows)).unwrap();
+        assert_eq!((image.width, image.height), (10, 2));
+        assert_eq!(image.data, vec![0x00, 0x40, 0xFF, 0xC0]);
+    }
+
+    #[test]
+    fn rejects_short_bmp() {
+        let rows = [[0, 0, 0, 0]];
+        assert!(MonoImage::from_bmp(&bmp_1bit(3, 2, [WHITE, BLACK], &rows)).is_err());
+        assert!(MonoImage::from_bmp(b"PK\x03\x04").is_err());
+    }
+}
@@ -0,0 +1,276 @@
+//! ビルド時に画像・フォントファイルを Rust のコードに変換するライブラリ
+//!
+//! アプリケーションの `build.rs` から使い、PBM / BMP 画像を
+//! `ImageRaw<BinaryColor>` の定数や `esp32s2_common_lib::animation::Animation` の定数として、
+//! BDF フォントを使う文字だけの `esp32s2_common_lib::bitmap_font::BitmapFont` の定数として
+//! `OUT_DIR` に書き出します。
+//!
+//! ## 使用例
+//!
+//! ```no_run
+//! // build.rs
+//! use esp32s2_asset_builder::{AssetBuilder, FontSubset};
+//!
+//! AssetBuilder::new()
+//!     .image("LOGO", "assets/logo.pbm")
+//!     .animation("SPINNER", &[("assets/spinner_0.pbm", 100), ("assets/spinner_1.pbm", 100)])
+//!     .font("FONT_JA", &["assets/fonts/ja.bdf"], FontSubset::new().ascii().kana().scan_sources("src"))
+//!     .write("assets.rs")
+//!     .expect("Failed to convert assets");
+//! ```
+//!
+//! ```ignore
+//! // main.rs
+//! mod assets {
+//!     include!(concat!(env!("OUT_DIR"), "/assets.rs"));
+//! }
+//! ```
+
+use std::fmt::{self, Write as _};
+use std::fs;
+use std::path::PathBuf;
+
+pub mod font;
+pub mod image;
+
+pub use font::{BdfFont, FontSubset};
+pub use image::MonoImage;
+
+/// アセットの変換エラー
+#[derive(Debug)]
+pub enum AssetError {
+    /// ファイルの読み書きに失敗した (パス, エラー)
+    Io(String, std::io::Error),
+    /// ファイルの形式が正しくない (パス, 内容)
+    Format(String, String),
+}
+
+impl fmt::Display for AssetError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            AssetError::Io(path, e) => write!(f, "{}: {}", path, e),
+            AssetError::Format(path, message) => write!(f, "{}: {}", path, message),
+        }
+    }
+}
+
+impl std::error::Error for AssetError {}
+
+enum Asset {
+    Image { name: String, path: PathBuf },
+    Animation { name: String, frames: Vec<(PathBuf, u32)> },
+    Font { name: String, paths: Vec<PathBuf>, subset: FontSubset },
+}
+
+/// 画像・アニメーション・フォントの定数を生成するビルダー
+pub struct AssetBuilder {
+    base_dir: PathBuf,
+    assets: Vec<Asset>,
+}
+
+impl AssetBuilder {
+    /// パッケージのディレクトリ (`CARGO_MANIFEST_DIR`) を基準にファイルを探すビルダーを作成します。
+    pub fn new() -> Self {
+        let base_dir = std::env::var_os("CARGO_MANIFEST_DIR")
+            .map(PathBuf::from)
+            .unwrap_or_default();
+        Self::with_base_dir(base_dir)
+    }
+
+    /// `base_dir` を基準にファイルを探すビルダーを作成します。
+    pub fn with_base_dir(base_dir: impl Into<PathBuf>) -> Self {
+        Self {
+            base_dir: base_dir.into(),
+            assets: Vec::new(),
+        }
+    }
+
+    /// 画像を `name` という名前の `ImageRaw<BinaryColor>` 定数として追加します。
+    pub fn image(mut self, name: &str, path: &str) -> Self {
+        self.assets.push(Asset::Image {
+            name: name.to_string(),
+            path: self.base_dir.join(path),
+        });
+        self
+    }
+
+    /// (画像, 表示時間 [ms]) の列を `name` という名前の `Animation` 定数として追加します。
+    pub fn animation(mut self, name: &str, frames: &[(&str, u32)]) -> Self {
+        let frames = frames
+            .iter()
+            .map(|(path, duration_ms)| (self.base_dir.join(path), *duration_ms))
+            .collect();
+        self.assets.push(Asset::Animation {
+            name: name.to_string(),
+            frames,
+        });
+        self
+    }
+
+    /// BDF フォントから `subset` の文字だけを取り出し、`name` という名前の `BitmapFont` 定数として追加します。
+    ///
+    /// `paths` に複数のフォントを指定すると、前のフォントにない文字を後のフォントから探します
+    /// (例: 日本語のフォントと ASCII のフォントを組み合わせる)。
+    pub fn font(mut self, name: &str, paths: &[&str], subset: FontSubset) -> Self {
+        let paths = paths.iter().map(|path| self.base_dir.join(path)).collect();
+        self.assets.push(Asset::Font {
+            name: name.to_string(),
+            paths,
+            subset,
+        });
+        self
+    }
+
+    /// 追加したアセットを Rust のコードに変換します。
+    pub fn generate(&self) -> Result<String, AssetError> {
+        let mut code = String::from("// esp32s2_asset_builder が生成したファイル (編集しないこと)\n\n");
+        for asset in &self.assets {
+            match asset {
+                Asset::Image { name, path } => {
+                    let image = MonoImage::load(path)?;
+                    writeln!(code, "/// {} ({}x{})", file_name(path), image.width, image.height).unwrap();
+                    writeln!(
+                        code,
+                        "pub const {}: ::embedded_graphics::image::ImageRaw<'static, ::embedded_graphics::pixelcolor::BinaryColor> = {};\n",
+                        name,
+                        image_raw_expr(&image)
+                    )
+                    .unwrap();
+                }
+                Asset::Animation { name, frames } => {
+                    writeln!(code, "/// {} フレーム", frames.len()).unwrap();
+                    writeln!(
+                        code,
+                        "pub const {}: ::esp32s2_common_lib::animation::Animation<'static> = ::esp32s2_common_lib::animation::Animation::new(&[",
+                        name
+                    )
+                    .unwrap();
+                    for (path, duration_ms) in frames {
+                        let image = MonoImage::load(path)?;
+                        writeln!(
+                            code,
+                            "    ::esp32s2_common_lib::animation::Frame::new({}, {}),",
+                            image_raw_expr(&image),
+                            duration_ms
+                        )
+                        .unwrap();
+                    }
+                    writeln!(code, "]);\n").unwrap();
+                }
+                Asset::Font { name, paths, subset } => {
+                    code.push_str(&self.font_code(name, paths, subset)?);
+                }
+            }
+        }
+        Ok(code)
+    }
+
+    /// フォントの定数のコード
+    fn font_code(&self, name: &str, paths: &[PathBuf], subset: &FontSubset) -> Result<String, AssetError> {
+        let fonts = paths.iter().map(BdfFont::load).collect::<Result<Vec<_>, _>>()?;
+        let (chars, required) = subset.resolve(&self.base_dir)?;
+
+        let mut glyphs = String::new();
+        let mut data = Vec::new();
+        let (mut ascent, mut descent) = (0, 0);
+        for ch in chars {
+            let Some((font, glyph)) = fonts
+                .iter()
+                .find_map(|font| font.glyphs.get(&ch).map(|glyph| (font, glyph)))
+            else {
+                if required.contains(&ch) {
+                    println!("cargo:warning={}: no glyph for {:?} (U+{:04X})", name, ch, ch as u32);
+                }
+                continue;
+            };
+            let fits_i8 = |value: i32| i8::try_from(value).is_ok();
+            if glyph.width > 255
+                || glyph.height > 255
+                || ![glyph.advance, glyph.x_offset, glyph.y_offset].into_iter().all(fits_i8)
+            {
+                return Err(AssetError::Format(
+                    name.to_string(),
+                    format!("glyph {:?} is too large", ch),
+                ));
+            }
+            ascent = ascent.max(font.ascent);
+            descent = descent.max(font.descent);
+            writeln!(
+                glyphs,
+                "        ::esp32s2_common_lib::bitmap_font::Glyph {{ ch: {:?}, advance: {}, width: {}, height: {}, x_offset: {}, y_offset: {}, offset: {} }},",
+                ch, glyph.advance, glyph.width, glyph.height, glyph.x_offset, glyph.y_offset, data.len()
+            )
+            .unwrap();
+            data.extend_from_slice(&glyph.data);
+        }
+
+        let bytes: Vec<String> = data.iter().map(|b| format!("0x{:02x}", b)).collect();
+        let mut code = String::new();
+        let sources: Vec<String> = paths.iter().map(|path| file_name(path)).collect();
+        writeln!(
+            code,
+            "/// {} ({} 文字, {} バイト)",
+            sources.join(" + "),
+            glyphs.lines().count(),
+            data.len()
+        )
+        .unwrap();
+        writeln!(
+            code,
+            "pub const {}: ::esp32s2_common_lib::bitmap_font::BitmapFont<'static> = ::esp32s2_common_lib::bitmap_font::BitmapFont::new(\n    &[\n{}    ],\n    &[{}],\n    {},\n    {},\n);\n",
+            name,
+            glyphs,
+            bytes.join(", "),
+            ascent,
+            descent
+        )
+        .unwrap();
+        Ok(code)
+    }
+
+    /// 生成したコードを `OUT_DIR/file_name` に書き出し、画像が変更されたら再ビルドするよう Cargo に伝えます。
+    pub fn write(&self, file_name: &str) -> Result<(), AssetError> {
+        for asset in &self.assets {
+            match asset {
+                Asset::Image { path, .. } => println!("cargo:rerun-if-changed={}", path.display()),
+                Asset::Animation { frames, .. } => {
+                    for (path, _) in frames {
+                        println!("cargo:rerun-if-changed={}", path.display());
+                    }
+                }
+                Asset::Font { paths, subset, .. } => {
+                    for path in paths.iter().chain(&subset.source_paths(&self.base_dir)) {
+                        println!("cargo:rerun-if-changed={}", path.display());
+                    }
+                }
+            }
+        }
+
+        let out_dir = std::env::var_os("OUT_DIR").map(PathBuf::from).unwrap_or_default();
+        let path = out_dir.join(file_name);
+        let code = self.generate()?;
+        fs::write(&path, code).map_err(|e| AssetError::Io(path.display().to_string(), e))
+    }
+}
+
+impl Default for AssetBuilder {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// `ImageRaw::new(&[...], width)` の式
+fn image_raw_expr(image: &MonoImage) -> String {
+    let bytes: Vec<String> = image.data.iter().map(|b| format!("0x{:02x}", b)).collect();
+    format!(
+        "::embedded_graphics::image::ImageRaw::new(&[{}], {})",
+        bytes.join(", "),
+        image.width
+    )
+}
+
+fn file_name(path: &std::path::Path) -> String {
+    path.file_name()
+        .map(|name| name.to_string_lossy().into_owned())
+        .unwrap_or_default()
+}
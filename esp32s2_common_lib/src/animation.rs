@@ -0,0 +1,253 @@
+//! 1ビット画像のスプライトとフレームアニメーション
+//!
+//! 画像は `esp32s2_asset_builder` で PBM / BMP からビルド時に変換した
+//! `ImageRaw<BinaryColor>` の定数を使います。
+//!
+//! ## 使用例
+//!
+//! ```no_run
+//! use embedded_graphics::prelude::*;
+//! use esp32s2_common_lib::animation::{AnimationPlayer, Animation, Frame};
+//! # use embedded_graphics::image::ImageRaw;
+//! # const SPINNER: Animation<'static> = Animation::new(&[Frame::new(ImageRaw::new(&[0xff], 8), 100)]);
+//!
+//! let mut player = AnimationPlayer::new(&SPINNER, Point::new(56, 24)).with_loop(true);
+//! loop {
+//!     if player.update() {
+//!         // player.draw(&mut display)?;
+//!         // display.flush()?;
+//!     }
+//! }
+//! ```
+
+use std::thread;
+use std::time::{Duration, Instant};
+
+use embedded_graphics::{
+    image::{GetPixel, Image, ImageRaw},
+    pixelcolor::BinaryColor,
+    prelude::*,
+    primitives::Rectangle,
+};
+use crate::mono_display::MonoDisplay;
+
+/// 1ビットのモノクロ画像
+pub type MonoImage<'a> = ImageRaw<'a, BinaryColor>;
+
+/// 背景を透過して描画する画像
+///
+/// 点灯する画素だけを描画するので、ほかの描画の上に重ねられます。
+/// 背景ごと上書きする場合は embedded-graphics の `Image` を使ってください。
+pub struct Sprite<'a> {
+    image: &'a MonoImage<'a>,
+    position: Point,
+    invert: bool,
+}
+
+impl<'a> Sprite<'a> {
+    pub fn new(image: &'a MonoImage<'a>, position: Point) -> Self {
+        Self {
+            image,
+            position,
+            invert: false,
+        }
+    }
+
+    /// 消灯している画素を点灯させて描画します (反転表示)。
+    pub fn with_invert(mut self, invert: bool) -> Self {
+        self.invert = invert;
+        self
+    }
+
+    /// 描画範囲
+    pub fn bounding_box(&self) -> Rectangle {
+        Rectangle::new(self.position, self.image.size())
+    }
+}
+
+impl Drawable for Sprite<'_> {
+    type Color = BinaryColor;
+    type Output = ();
+
+    fn draw<D>(&self, target: &mut D) -> Result<(), D::Error>
+    where
+        D: DrawTarget<Color = BinaryColor>,
+    {
+        let lit = if self.invert { BinaryColor::Off } else { BinaryColor::On };
+        let pixels = Rectangle::new(Point::zero(), self.image.size())
+            .points()
+            .filter(|&point| self.image.pixel(point) == Some(lit))
+            .map(|point| Pixel(point + self.position, BinaryColor::On));
+        target.draw_iter(pixels)
+    }
+}
+
+/// アニメーションの1フレーム
+#[derive(Debug, Clone, Copy)]
+pub struct Frame<'a> {
+    pub image: MonoImage<'a>,
+    pub duration: Duration,
+}
+
+impl<'a> Frame<'a> {
+    /// `duration_ms` ミリ秒表示するフレームを作成します。
+    pub const fn new(image: MonoImage<'a>, duration_ms: u32) -> Self {
+        Self {
+            image,
+            duration: Duration::from_millis(duration_ms as u64),
+        }
+    }
+}
+
+/// フレームの列
+#[derive(Debug, Clone, Copy)]
+pub struct Animation<'a> {
+    frames: &'a [Frame<'a>],
+}
+
+impl<'a> Animation<'a> {
+    pub const fn new(frames: &'a [Frame<'a>]) -> Self {
+        Self { frames }
+    }
+
+    pub fn frames(&self) -> &'a [Frame<'a>] {
+        self.frames
+    }
+
+    /// 1回再生するのにかかる時間
+    pub fn total_duration(&self) -> Duration {
+        self.frames.iter().map(|frame| frame.duration).sum()
+    }
+
+    /// すべてのフレームを含む大きさ
+    pub fn size(&self) -> Size {
+        self.frames
+            .iter()
+            .map(|frame| frame.image.size())
+            .fold(Size::zero(), |a, b| a.component_max(b))
+    }
+}
+
+/// 経過時間に合わせてフレームを進める再生器
+///
+/// 描画するときはアニメーションの範囲を消去してから現在のフレームを描画します。
+pub struct AnimationPlayer<'a> {
+    animation: &'a Animation<'a>,
+    position: Point,
+    looping: bool,
+    current: usize,
+    frame_started: Instant,
+    finished: bool,
+}
+
+impl<'a> AnimationPlayer<'a> {
+    /// `position` を左上として1回だけ再生する再生器を作成します。
+    pub fn new(animation: &'a Animation<'a>, position: Point) -> Self {
+        Self {
+            animation,
+            position,
+            looping: false,
+            current: 0,
+            frame_started: Instant::now(),
+            finished: animation.frames.is_empty(),
+        }
+    }
+
+    /// 最後のフレームの後に最初に戻って繰り返すかどうかを設定します。
+    pub fn with_loop(mut self, looping: bool) -> Self {
+        self.looping = looping;
+        self
+    }
+
+    /// 最初のフレームから再生し直します。
+    pub fn reset(&mut self) {
+        self.current = 0;
+        self.frame_started = Instant::now();
+        self.finished = self.animation.frames.is_empty();
+    }
+
+    /// 最後まで再生したかどうか (繰り返し再生では常に `false`)
+    pub fn is_finished(&self) -> bool {
+        self.finished
+    }
+
+    /// 現在のフレームの番号
+    pub fn current_index(&self) -> usize {
+        self.current
+    }
+
+    /// 現在のフレーム
+    pub fn current_frame(&self) -> Option<&'a Frame<'a>> {
+        self.animation.frames.get(self.current)
+    }
+
+    /// 描画範囲
+    pub fn bounding_box(&self) -> Rectangle {
+        Rectangle::new(self.position, self.animation.size())
+    }
+
+    /// 経過時間からフレームを進めます。フレームが変わったら `true` を返します。
+    pub fn update(&mut self) -> bool {
+        self.update_at(Instant::now())
+    }
+
+    /// `now` を現在時刻としてフレームを進めます。
+    pub fn update_at(&mut self, now: Instant) -> bool {
+        let mut changed = false;
+        // 更新が遅れた場合は、表示時間を過ぎたフレームをまとめて飛ばす
+        while !self.finished {
+            let duration = self.animation.frames[self.current].duration;
+            if now.saturating_duration_since(self.frame_started) < duration {
+                break;
+            }
+            if self.current + 1 < self.animation.frames.len() {
+                self.current += 1;
+            } else if self.looping {
+                self.current = 0;
+            } else {
+                self.finished = true;
+                break;
+            }
+            self.frame_started += duration;
+            changed = true;
+            if duration.is_zero() {
+                break;
+            }
+        }
+        changed
+    }
+
+    /// 最初のフレームから最後まで、フレームごとの表示時間だけ待ちながら順に表示します。
+    ///
+    /// ブロックするので、起動時のロゴ表示などに使います。繰り返し再生の設定は無視します。
+    pub fn play_blocking<D>(&mut self, display: &mut D) -> Result<(), D::InterfaceError>
+    where
+        D: MonoDisplay,
+    {
+        self.reset();
+        for (index, frame) in self.animation.frames.iter().enumerate() {
+            self.current = index;
+            self.draw(display).unwrap_or_else(|never| match never {});
+            display.flush()?;
+            thread::sleep(frame.duration);
+        }
+        self.finished = true;
+        Ok(())
+    }
+}
+
+impl Drawable for AnimationPlayer<'_> {
+    type Color = BinaryColor;
+    type Output = ();
+
+    fn draw<D>(&self, target: &mut D) -> Result<(), D::Error>
+    where
+        D: DrawTarget<Color = BinaryColor>,
+    {
+        target.fill_solid(&self.bounding_box(), BinaryColor::Off)?;
+        if let Some(frame) = self.current_frame() {
+            Image::new(&frame.image, self.position).draw(target)?;
+        }
+        Ok(())
+    }
+}
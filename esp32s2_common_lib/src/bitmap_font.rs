@@ -0,0 +1,244 @@
+//! 日本語などを表示するためのビットマップフォント
+//!
+//! embedded-graphics の `mono_font` は ASCII などの固定幅の文字しか扱えないため、
+//! BDF フォントから使う文字だけを取り出した `BitmapFont` で描画します。
+//! フォントの定数は `esp32s2_asset_builder::AssetBuilder::font` でビルド時に生成します。
+//!
+//! `BitmapTextStyle` は `TextRenderer` を実装しているので、`Text` と組み合わせて
+//! 位置揃えや複数行の表示ができます。フォントにない文字は `?` で表示します。
+//!
+//! ## 使用例
+//!
+//! ```ignore
+//! use embedded_graphics::{pixelcolor::BinaryColor, prelude::*, text::Text};
+//! use esp32s2_common_lib::bitmap_font::BitmapTextStyle;
+//!
+//! let style = BitmapTextStyle::new(&assets::FONT_JA, BinaryColor::On);
+//! Text::new("こんにちは", Point::new(0, 10), style).draw(&mut display)?;
+//! ```
+
+use embedded_graphics::{
+    pixelcolor::BinaryColor,
+    prelude::*,
+    primitives::Rectangle,
+    text::{
+        renderer::{CharacterStyle, TextMetrics, TextRenderer},
+        Baseline,
+    },
+};
+
+/// フォントの1文字
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Glyph {
+    pub ch: char,
+    /// 次の文字までの幅
+    pub advance: i8,
+    /// ビットマップの大きさ
+    pub width: u8,
+    pub height: u8,
+    /// 描画位置 (ベースライン上) からビットマップの左下までのずれ (上が正)
+    pub x_offset: i8,
+    pub y_offset: i8,
+    /// `BitmapFont` のデータの中の開始位置 (1行 `ceil(width / 8)` バイト、上位ビットが左)
+    pub offset: u32,
+}
+
+/// 使う文字だけを含むビットマップフォント
+#[derive(Debug, Clone, Copy)]
+pub struct BitmapFont<'a> {
+    /// 文字コード順に並んだ文字
+    glyphs: &'a [Glyph],
+    data: &'a [u8],
+    ascent: u8,
+    descent: u8,
+}
+
+impl<'a> BitmapFont<'a> {
+    /// `glyphs` は文字コード順に並べてください。
+    pub const fn new(glyphs: &'a [Glyph], data: &'a [u8], ascent: u8, descent: u8) -> Self {
+        Self {
+            glyphs,
+            data,
+            ascent,
+            descent,
+        }
+    }
+
+    /// 文字を探します。
+    pub fn glyph(&self, ch: char) -> Option<&'a Glyph> {
+        self.glyphs
+            .binary_search_by_key(&ch, |glyph| glyph.ch)
+            .ok()
+            .map(|index| &self.glyphs[index])
+    }
+
+    /// 文字を探し、なければ `?` を返します。
+    fn glyph_or_replacement(&self, ch: char) -> Option<&'a Glyph> {
+        self.glyph(ch).or_else(|| self.glyph('?'))
+    }
+
+    /// 含まれる文字の数
+    pub fn len(&self) -> usize {
+        self.glyphs.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.glyphs.is_empty()
+    }
+
+    /// ベースラインより上の高さ
+    pub fn ascent(&self) -> u32 {
+        self.ascent as u32
+    }
+
+    /// ベースラインより下の高さ
+    pub fn descent(&self) -> u32 {
+        self.descent as u32
+    }
+
+    /// 1行の高さ
+    pub fn line_height(&self) -> u32 {
+        self.ascent() + self.descent()
+    }
+
+    /// 文字列の幅 (ピクセル)
+    pub fn text_width(&self, text: &str) -> u32 {
+        text.chars()
+            .filter_map(|ch| self.glyph_or_replacement(ch))
+            .map(|glyph| glyph.advance.max(0) as u32)
+            .sum()
+    }
+
+    /// 文字のビットマップの画素
+    fn pixel(&self, glyph: &Glyph, x: u32, y: u32) -> bool {
+        let bytes_per_row = (glyph.width as u32).div_ceil(8);
+        let index = (glyph.offset + y * bytes_per_row + x / 8) as usize;
+        self.data.get(index).is_some_and(|byte| byte & (0x80 >> (x % 8)) != 0)
+    }
+}
+
+/// `BitmapFont` で文字列を描画するスタイル
+#[derive(Debug, Clone, Copy)]
+pub struct BitmapTextStyle<'a> {
+    font: &'a BitmapFont<'a>,
+    text_color: Option<BinaryColor>,
+    background_color: Option<BinaryColor>,
+}
+
+impl<'a> BitmapTextStyle<'a> {
+    pub fn new(font: &'a BitmapFont<'a>, text_color: BinaryColor) -> Self {
+        Self {
+            font,
+            text_color: Some(text_color),
+            background_color: None,
+        }
+    }
+
+    /// 背景色を設定します。設定すると1行の高さ分の背景を塗りつぶしてから描画します。
+    pub fn with_background(mut self, color: BinaryColor) -> Self {
+        self.background_color = Some(color);
+        self
+    }
+
+    /// `baseline` で指定した位置からベースラインまでの距離
+    fn baseline_offset(&self, baseline: Baseline) -> i32 {
+        let ascent = self.font.ascent() as i32;
+        let bottom = self.font.line_height() as i32 - 1;
+        match baseline {
+            Baseline::Top => ascent,
+            Baseline::Bottom => ascent - bottom,
+            Baseline::Middle => ascent - bottom / 2,
+            Baseline::Alphabetic => 0,
+        }
+    }
+
+    /// 1行分の背景の範囲
+    fn line_box(&self, position: Point, width: u32, baseline: Baseline) -> Rectangle {
+        let top = position.y + self.baseline_offset(baseline) - self.font.ascent() as i32;
+        Rectangle::new(Point::new(position.x, top), Size::new(width, self.font.line_height()))
+    }
+}
+
+impl CharacterStyle for BitmapTextStyle<'_> {
+    type Color = BinaryColor;
+
+    fn set_text_color(&mut self, text_color: Option<Self::Color>) {
+        self.text_color = text_color;
+    }
+
+    fn set_background_color(&mut self, background_color: Option<Self::Color>) {
+        self.background_color = background_color;
+    }
+}
+
+impl TextRenderer for BitmapTextStyle<'_> {
+    type Color = BinaryColor;
+
+    fn draw_string<D>(
+        &self,
+        text: &str,
+        position: Point,
+        baseline: Baseline,
+        target: &mut D,
+    ) -> Result<Point, D::Error>
+    where
+        D: DrawTarget<Color = Self::Color>,
+    {
+        if let Some(background) = self.background_color {
+            let width = self.font.text_width(text);
+            target.fill_solid(&self.line_box(position, width, baseline), background)?;
+        }
+
+        let baseline_y = position.y + self.baseline_offset(baseline);
+        let mut x = position.x;
+        for ch in text.chars() {
+            let Some(glyph) = self.font.glyph_or_replacement(ch) else {
+                continue;
+            };
+            if let Some(color) = self.text_color {
+                let left = x + glyph.x_offset as i32;
+                let top = baseline_y - glyph.y_offset as i32 - glyph.height as i32;
+                let pixels = (0..glyph.height as u32)
+                    .flat_map(|y| (0..glyph.width as u32).map(move |x| (x, y)))
+                    .filter(|&(gx, gy)| self.font.pixel(glyph, gx, gy))
+                    .map(|(gx, gy)| Pixel(Point::new(left + gx as i32, top + gy as i32), color));
+                target.draw_iter(pixels)?;
+            }
+            x += glyph.advance as i32;
+        }
+        Ok(Point::new(x, position.y))
+    }
+
+    fn draw_whitespace<D>(
+        &self,
+        width: u32,
+        position: Point,
+        baseline: Baseline,
+        target: &mut D,
+    ) -> Result<Point, D::Error>
+    where
+        D: DrawTarget<Color = Self::Color>,
+    {
+        if let Some(background) = self.background_color {
+            target.fill_solid(&self.line_box(position, width, baseline), background)?;
+        }
+        Ok(position + Point::new(width as i32, 0))
+    }
+
+    fn measure_string(&self, text: &str, position: Point, baseline: Baseline) -> TextMetrics {
+        let width = self.font.text_width(text);
+        let bounding_box = if width == 0 {
+            Rectangle::new(position, Size::zero())
+        } else {
+            self.line_box(position, width, baseline)
+        };
+        TextMetrics {
+            bounding_box,
+            next_position: position + Point::new(width as i32, 0),
+        }
+    }
+
+    fn line_height(&self) -> u32 {
+        self.font.line_height()
+    }
+}
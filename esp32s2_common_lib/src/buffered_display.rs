@@ -0,0 +1,482 @@
+//! 差分転送に対応した SH1106 / SSD1306 用のダブルバッファ
+//!
+//! `sh1106::GraphicsMode::flush` は毎回 8 ページ分すべてを SPI で送信します。
+//! `BufferedDisplay` は描画用のバッファとパネルに送信済みのバッファを持ち、
+//! `flush` では内容が変わったページ・列の範囲だけを送信します。
+//! 描画内容の回転 (`set_rotation`) はバッファへの書き込み時にソフトウェアで行います。
+//!
+//! コントローラによる違いは初期化コマンドと列アドレスのずれだけなので、
+//! `with_controller` で切り替えます (デフォルトは SH1106)。
+//!
+//! ## 使用例
+//!
+//! ```no_run
+//! // let (mut display, _rst) = set_sh1106_buffered_display(...)?;
+//! loop {
+//!     display.clear();
+//!     // embedded-graphics で描画
+//!     display.flush()?;
+//! }
+//! ```
+
+use core::convert::Infallible;
+use core::fmt;
+use core::str::FromStr;
+
+use embedded_graphics::{
+    pixelcolor::BinaryColor,
+    prelude::*,
+};
+use sh1106::{displayrotation::DisplayRotation, interface::DisplayInterface};
+
+/// パネルの幅 (ピクセル)
+pub const WIDTH: u32 = 128;
+/// パネルの高さ (ピクセル)
+pub const HEIGHT: u32 = 64;
+/// ページ数 (1ページ = 縦8ピクセル)
+pub const PAGES: usize = (HEIGHT / 8) as usize;
+
+const BUFFER_SIZE: usize = WIDTH as usize * PAGES;
+
+/// 変更箇所の間がこの列数以下なら、コマンドを分けずに続けて送信する
+///
+/// ページ・列アドレスの設定に 3 バイト必要なので、短い隙間はまとめたほうが速い
+const MERGE_GAP: usize = 4;
+
+/// 初期化直後のコントラスト
+pub const DEFAULT_CONTRAST: u8 = 0x80;
+
+/// SH1106 の初期化コマンド (128x64, `DisplayRotation::Rotate0` 相当)
+const SH1106_INIT_COMMANDS: &[u8] = &[
+    0xAE,       // 表示 OFF
+    0xD5, 0x80, // クロック分周
+    0xA8, 0x3F, // マルチプレクス比 (64)
+    0xD3, 0x00, // 表示オフセット
+    0x40,       // 開始ライン 0
+    0xAD, 0x8B, // DC-DC ON
+    0xA1,       // セグメントリマップ
+    0xC8,       // COM スキャン方向を反転
+    0xDA, 0x12, // COM ピン設定
+    0x81, 0x80, // コントラスト
+    0xD9, 0xF1, // プリチャージ期間
+    0xDB, 0x40, // VCOMH
+    0xA4,       // RAM の内容を表示
+    0xA6,       // 通常表示 (反転なし)
+    0xAF,       // 表示 ON
+];
+
+/// SSD1306 の初期化コマンド (128x64, `DisplayRotation::Rotate0` 相当)
+///
+/// SH1106 と違い、内蔵のチャージポンプを有効にしないと表示されない
+const SSD1306_INIT_COMMANDS: &[u8] = &[
+    0xAE,       // 表示 OFF
+    0xD5, 0x80, // クロック分周
+    0xA8, 0x3F, // マルチプレクス比 (64)
+    0xD3, 0x00, // 表示オフセット
+    0x40,       // 開始ライン 0
+    0x8D, 0x14, // チャージポンプ ON
+    0x20, 0x02, // ページアドレッシングモード
+    0xA1,       // セグメントリマップ
+    0xC8,       // COM スキャン方向を反転
+    0xDA, 0x12, // COM ピン設定
+    0x81, 0x80, // コントラスト
+    0xD9, 0xF1, // プリチャージ期間
+    0xDB, 0x40, // VCOMH
+    0xA4,       // RAM の内容を表示
+    0xA6,       // 通常表示 (反転なし)
+    0xAF,       // 表示 ON
+];
+
+/// ディスプレイのコントローラ
+///
+/// 設定ファイルや環境変数の文字列 (`"sh1106"`, `"ssd1306"`) から `parse` で変換できます。
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum Controller {
+    /// SH1106 (132 列の RAM のうち 2 列目から表示)
+    #[default]
+    Sh1106,
+    /// SSD1306
+    Ssd1306,
+}
+
+impl Controller {
+    /// ビルド時の値 (アプリケーションの `option_env!("DISPLAY_CONTROLLER")` など) から作成します。
+    ///
+    /// 指定がない場合は SH1106 を返します。
+    pub fn from_build_env(name: Option<&str>) -> Result<Self, ParseControllerError> {
+        name.map_or(Ok(Self::default()), str::parse)
+    }
+
+    /// 設定で使う名前
+    pub fn name(&self) -> &'static str {
+        match self {
+            Controller::Sh1106 => "sh1106",
+            Controller::Ssd1306 => "ssd1306",
+        }
+    }
+
+    fn init_commands(&self) -> &'static [u8] {
+        match self {
+            Controller::Sh1106 => SH1106_INIT_COMMANDS,
+            Controller::Ssd1306 => SSD1306_INIT_COMMANDS,
+        }
+    }
+
+    /// パネルの左端に対応する RAM の列アドレス
+    fn column_offset(&self) -> u8 {
+        match self {
+            Controller::Sh1106 => 2,
+            Controller::Ssd1306 => 0,
+        }
+    }
+}
+
+impl FromStr for Controller {
+    type Err = ParseControllerError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.trim().to_ascii_lowercase().as_str() {
+            "sh1106" => Ok(Controller::Sh1106),
+            "ssd1306" => Ok(Controller::Ssd1306),
+            _ => Err(ParseControllerError(s.to_string())),
+        }
+    }
+}
+
+impl fmt::Display for Controller {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.name())
+    }
+}
+
+/// 対応していないコントローラ名
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ParseControllerError(pub String);
+
+impl fmt::Display for ParseControllerError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "unknown display controller: {:?} (sh1106 / ssd1306)", self.0)
+    }
+}
+
+impl std::error::Error for ParseControllerError {}
+
+/// 直前の `flush` で送信した量
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct FlushStats {
+    /// 送信したデータのバイト数
+    pub bytes: usize,
+    /// 送信した範囲 (ページ・列アドレスの設定回数)
+    pub regions: usize,
+}
+
+/// 差分転送を行う SH1106 / SSD1306 ディスプレイ
+pub struct BufferedDisplay<DI> {
+    iface: DI,
+    controller: Controller,
+    /// 描画用のバッファ
+    back: [u8; BUFFER_SIZE],
+    /// パネルに送信済みの内容
+    front: [u8; BUFFER_SIZE],
+    /// 次の `flush` で全体を送信する
+    full_refresh: bool,
+    last_flush: FlushStats,
+    contrast: u8,
+    display_on: bool,
+    inverted: bool,
+    flip_horizontal: bool,
+    flip_vertical: bool,
+    rotation: DisplayRotation,
+}
+
+impl<DI> BufferedDisplay<DI>
+where
+    DI: DisplayInterface,
+{
+    /// 初期化前の SH1106 ディスプレイを作成します。使用前に `init` を呼び出してください。
+    pub fn new(iface: DI) -> Self {
+        Self {
+            iface,
+            controller: Controller::Sh1106,
+            back: [0; BUFFER_SIZE],
+            front: [0; BUFFER_SIZE],
+            full_refresh: true,
+            last_flush: FlushStats::default(),
+            contrast: DEFAULT_CONTRAST,
+            display_on: true,
+            inverted: false,
+            flip_horizontal: false,
+            flip_vertical: false,
+            rotation: DisplayRotation::Rotate0,
+        }
+    }
+
+    /// コントローラの種類を設定します。`init` の前に呼び出してください。
+    pub fn with_controller(mut self, controller: Controller) -> Self {
+        self.controller = controller;
+        self
+    }
+
+    /// コントローラの種類
+    pub fn controller(&self) -> Controller {
+        self.controller
+    }
+
+    /// コントローラを初期化し、画面全体を消去します。
+    pub fn init(&mut self) -> Result<(), DI::Error> {
+        self.iface.init()?;
+        self.iface.send_commands(self.controller.init_commands())?;
+        self.clear();
+        self.full_refresh = true;
+        self.flush()
+    }
+
+    /// 描画用のバッファを消去します。パネルへの反映は `flush` で行います。
+    pub fn clear(&mut self) {
+        self.back.fill(0);
+    }
+
+    /// 次の `flush` で画面全体を送信するようにします。
+    ///
+    /// パネルの RAM が外部要因で変わった可能性がある場合に使います。
+    pub fn invalidate(&mut self) {
+        self.full_refresh = true;
+    }
+
+    /// 1ピクセルを設定します。座標は回転後のもので、範囲外の座標は無視されます。
+    pub fn set_pixel(&mut self, x: u32, y: u32, on: bool) {
+        let Some((x, y)) = self.to_panel(x, y) else {
+            return;
+        };
+        let index = (y / 8) as usize * WIDTH as usize + x as usize;
+        let mask = 1 << (y % 8);
+        if on {
+            self.back[index] |= mask;
+        } else {
+            self.back[index] &= !mask;
+        }
+    }
+
+    /// 描画用のバッファのピクセルを取得します。座標は回転後のものです。
+    pub fn get_pixel(&self, x: u32, y: u32) -> bool {
+        let Some((x, y)) = self.to_panel(x, y) else {
+            return false;
+        };
+        let index = (y / 8) as usize * WIDTH as usize + x as usize;
+        self.back[index] & (1 << (y % 8)) != 0
+    }
+
+    /// 前回の送信内容から変わった範囲だけをパネルに送信します。
+    pub fn flush(&mut self) -> Result<(), DI::Error> {
+        let mut stats = FlushStats::default();
+
+        for page in 0..PAGES {
+            let start = page * WIDTH as usize;
+            let back = &self.back[start..start + WIDTH as usize];
+            let front = &self.front[start..start + WIDTH as usize];
+
+            if self.full_refresh {
+                Self::send_region(&mut self.iface, self.controller, page, 0, back)?;
+                stats.bytes += back.len();
+                stats.regions += 1;
+                continue;
+            }
+
+            for (first, last) in dirty_spans(back, front) {
+                Self::send_region(&mut self.iface, self.controller, page, first, &back[first..=last])?;
+                stats.bytes += last - first + 1;
+                stats.regions += 1;
+            }
+        }
+
+        self.front.copy_from_slice(&self.back);
+        self.full_refresh = false;
+        self.last_flush = stats;
+        Ok(())
+    }
+
+    /// 直前の `flush` で送信した量を返します。
+    pub fn last_flush(&self) -> FlushStats {
+        self.last_flush
+    }
+
+    /// コントラスト (明るさ) を設定します。
+    pub fn set_contrast(&mut self, contrast: u8) -> Result<(), DI::Error> {
+        self.iface.send_commands(&[0x81, contrast])?;
+        self.contrast = contrast;
+        Ok(())
+    }
+
+    /// 現在のコントラスト
+    pub fn contrast(&self) -> u8 {
+        self.contrast
+    }
+
+    /// 表示の ON/OFF を切り替えます。
+    ///
+    /// OFF の間はパネルが消灯し (スリープ)、RAM の内容は保持されます。
+    pub fn set_display_on(&mut self, on: bool) -> Result<(), DI::Error> {
+        self.iface.send_commands(&[if on { 0xAF } else { 0xAE }])?;
+        self.display_on = on;
+        Ok(())
+    }
+
+    /// 表示が ON かどうか
+    pub fn is_display_on(&self) -> bool {
+        self.display_on
+    }
+
+    /// 白黒反転表示を切り替えます。
+    pub fn set_invert(&mut self, invert: bool) -> Result<(), DI::Error> {
+        self.iface.send_commands(&[if invert { 0xA7 } else { 0xA6 }])?;
+        self.inverted = invert;
+        Ok(())
+    }
+
+    /// 反転表示中かどうか
+    pub fn is_inverted(&self) -> bool {
+        self.inverted
+    }
+
+    /// 左右・上下の反転を設定します。
+    ///
+    /// セグメントリマップは以降に書き込んだデータにだけ反映されるため、
+    /// 次の `flush` で画面全体を送り直します。
+    pub fn set_flip(&mut self, horizontal: bool, vertical: bool) -> Result<(), DI::Error> {
+        self.iface.send_commands(&[
+            if horizontal { 0xA0 } else { 0xA1 },
+            if vertical { 0xC0 } else { 0xC8 },
+        ])?;
+        self.flip_horizontal = horizontal;
+        self.flip_vertical = vertical;
+        self.invalidate();
+        Ok(())
+    }
+
+    /// 現在の反転設定 (左右, 上下)
+    pub fn flip(&self) -> (bool, bool) {
+        (self.flip_horizontal, self.flip_vertical)
+    }
+
+    /// 描画内容を 0/90/180/270 度 (時計回り) 回転させます。
+    ///
+    /// 90/270 度では幅と高さが入れ替わり、`size()` が 64x128 になります。
+    /// 座標の対応が変わるため描画用のバッファを消去します。設定後に描画し直してください。
+    pub fn set_rotation(&mut self, rotation: DisplayRotation) {
+        self.rotation = rotation;
+        self.clear();
+    }
+
+    /// 現在の回転
+    pub fn rotation(&self) -> DisplayRotation {
+        self.rotation
+    }
+
+    /// パネルに送信済みの内容を PBM (P4) 形式の画像にします。
+    ///
+    /// 描画時の向き (回転後の座標) で、点灯している画素を白、消灯している画素を黒として出力します。
+    /// 反転表示 (`set_invert`) 中は画面の見た目どおりに白黒を反転します。
+    pub fn screenshot_pbm(&self) -> Vec<u8> {
+        let size = self.size();
+        let bytes_per_row = size.width.div_ceil(8) as usize;
+        let mut pbm = format!("P4\n{} {}\n", size.width, size.height).into_bytes();
+        let header_len = pbm.len();
+        pbm.resize(header_len + bytes_per_row * size.height as usize, 0);
+
+        for y in 0..size.height {
+            for x in 0..size.width {
+                let Some((px, py)) = self.to_panel(x, y) else {
+                    continue;
+                };
+                let lit = self.front[(py / 8) as usize * WIDTH as usize + px as usize] & (1 << (py % 8)) != 0;
+                // PBM は 1 が黒
+                if lit == self.inverted {
+                    pbm[header_len + y as usize * bytes_per_row + x as usize / 8] |= 0x80 >> (x % 8);
+                }
+            }
+        }
+        pbm
+    }
+
+    /// 回転後の座標をパネルの座標に変換します。範囲外なら `None` を返します。
+    fn to_panel(&self, x: u32, y: u32) -> Option<(u32, u32)> {
+        let size = self.size();
+        if x >= size.width || y >= size.height {
+            return None;
+        }
+        Some(match self.rotation {
+            DisplayRotation::Rotate0 => (x, y),
+            DisplayRotation::Rotate90 => (WIDTH - 1 - y, x),
+            DisplayRotation::Rotate180 => (WIDTH - 1 - x, HEIGHT - 1 - y),
+            DisplayRotation::Rotate270 => (y, HEIGHT - 1 - x),
+        })
+    }
+
+    /// インターフェースを取り出します。
+    pub fn release(self) -> DI {
+        self.iface
+    }
+
+    fn send_region(
+        iface: &mut DI,
+        controller: Controller,
+        page: usize,
+        column: usize,
+        data: &[u8],
+    ) -> Result<(), DI::Error> {
+        let column = column as u8 + controller.column_offset();
+        iface.send_commands(&[
+            0xB0 | page as u8,
+            column & 0x0F,
+            0x10 | (column >> 4),
+        ])?;
+        iface.send_data(data)
+    }
+}
+
+/// 1ページ分のバッファを比較し、変更された列の範囲 (両端を含む) を返します。
+///
+/// 間隔が `MERGE_GAP` 以下の範囲は1つにまとめます。
+fn dirty_spans(back: &[u8], front: &[u8]) -> Vec<(usize, usize)> {
+    let mut spans: Vec<(usize, usize)> = Vec::new();
+    for (column, (b, f)) in back.iter().zip(front).enumerate() {
+        if b == f {
+            continue;
+        }
+        match spans.last_mut() {
+            Some((_, last)) if column - *last <= MERGE_GAP + 1 => *last = column,
+            _ => spans.push((column, column)),
+        }
+    }
+    spans
+}
+
+impl<DI> OriginDimensions for BufferedDisplay<DI> {
+    /// 回転を反映した大きさ
+    fn size(&self) -> Size {
+        match self.rotation {
+            DisplayRotation::Rotate0 | DisplayRotation::Rotate180 => Size::new(WIDTH, HEIGHT),
+            DisplayRotation::Rotate90 | DisplayRotation::Rotate270 => Size::new(HEIGHT, WIDTH),
+        }
+    }
+}
+
+impl<DI> DrawTarget for BufferedDisplay<DI>
+where
+    DI: DisplayInterface,
+{
+    type Color = BinaryColor;
+    type Error = Infallible;
+
+    fn draw_iter<I>(&mut self, pixels: I) -> Result<(), Self::Error>
+    where
+        I: IntoIterator<Item = Pixel<Self::Color>>,
+    {
+        for Pixel(point, color) in pixels {
+            if point.x >= 0 && point.y >= 0 {
+                self.set_pixel(point.x as u32, point.y as u32, color.is_on());
+            }
+        }
+        Ok(())
+    }
+}
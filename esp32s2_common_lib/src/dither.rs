@@ -0,0 +1,315 @@
+//! グレースケール (`Gray8`) の画像や図形を 1 ビットの表示に変換するディザリング
+//!
+//! SH1106 は画素ごとに点灯/消灯しか表現できないため、明るさを点灯する画素の密度で表します。
+//!
+//! - `OrderedDither`: Bayer 行列による組織的ディザ。`DrawTarget<Color = Gray8>` として
+//!   既存の `BinaryColor` の描画先を包むので、`Gray8` で塗った図形をそのまま描画できます。
+//!   画素ごとに独立して決まるため、アニメーションでもちらつきません。
+//! - `GrayCanvas::dither`: 一度 `Gray8` で描画した内容をまとめて変換します。
+//!   Floyd–Steinberg 法 (誤差拡散) を選ぶと写真などの階調がなめらかになります。
+//!
+//! 明るさ 255 (白) が点灯、0 (黒) が消灯に対応します。
+//!
+//! ## 使用例
+//!
+//! ```no_run
+//! use embedded_graphics::{pixelcolor::Gray8, prelude::*, primitives::*};
+//! use esp32s2_common_lib::dither::{BayerMatrix, OrderedDither};
+//!
+//! // let mut target = OrderedDither::new(&mut display, BayerMatrix::Bayer4);
+//! // Circle::new(Point::new(10, 10), 20)
+//! //     .into_styled(PrimitiveStyle::with_fill(Gray8::new(96)))
+//! //     .draw(&mut target)?;
+//! ```
+
+use embedded_graphics::{
+    image::ImageRaw,
+    pixelcolor::{BinaryColor, Gray8},
+    prelude::*,
+    primitives::Rectangle,
+};
+
+use crate::animation::MonoImage;
+
+/// 2x2 の Bayer 行列
+const BAYER2: [[u8; 2]; 2] = [
+    [0, 2],
+    [3, 1],
+];
+
+/// 4x4 の Bayer 行列
+const BAYER4: [[u8; 4]; 4] = [
+    [0, 8, 2, 10],
+    [12, 4, 14, 6],
+    [3, 11, 1, 9],
+    [15, 7, 13, 5],
+];
+
+/// 8x8 の Bayer 行列
+const BAYER8: [[u8; 8]; 8] = [
+    [0, 32, 8, 40, 2, 34, 10, 42],
+    [48, 16, 56, 24, 50, 18, 58, 26],
+    [12, 44, 4, 36, 14, 46, 6, 38],
+    [60, 28, 52, 20, 62, 30, 54, 22],
+    [3, 35, 11, 43, 1, 33, 9, 41],
+    [51, 19, 59, 27, 49, 17, 57, 25],
+    [15, 47, 7, 39, 13, 45, 5, 37],
+    [63, 31, 55, 23, 61, 29, 53, 21],
+];
+
+/// 組織的ディザで使う Bayer 行列の大きさ
+///
+/// 大きいほど表現できる明るさの段階が増え (n×n+1 段階)、模様は細かく目立たなくなります。
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum BayerMatrix {
+    /// 2x2 (5 段階)
+    Bayer2,
+    /// 4x4 (17 段階)
+    #[default]
+    Bayer4,
+    /// 8x8 (65 段階)
+    Bayer8,
+}
+
+impl BayerMatrix {
+    /// 行列の1辺の大きさ
+    pub fn size(&self) -> u32 {
+        match self {
+            BayerMatrix::Bayer2 => 2,
+            BayerMatrix::Bayer4 => 4,
+            BayerMatrix::Bayer8 => 8,
+        }
+    }
+
+    /// 座標 (`x`, `y`) の画素を明るさ `luma` で点灯させるかどうか
+    ///
+    /// 座標は行列の大きさで折り返すので、負の座標も使えます。
+    pub fn is_on(&self, x: i32, y: i32, luma: u8) -> bool {
+        let n = self.size() as i32;
+        let (col, row) = (x.rem_euclid(n) as usize, y.rem_euclid(n) as usize);
+        let index = match self {
+            BayerMatrix::Bayer2 => BAYER2[row][col],
+            BayerMatrix::Bayer4 => BAYER4[row][col],
+            BayerMatrix::Bayer8 => BAYER8[row][col],
+        } as u32;
+        // 0 では全消灯、255 では全点灯になるよう n×n+1 段階に分ける
+        let level = luma as u32 * (self.size() * self.size() + 1) / 256;
+        level > index
+    }
+}
+
+/// ディザリングの方法
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum DitherMethod {
+    /// しきい値より明るい画素を点灯 (ディザなし)
+    Threshold(u8),
+    /// Bayer 行列による組織的ディザ
+    Ordered(BayerMatrix),
+    /// Floyd–Steinberg 法による誤差拡散
+    #[default]
+    FloydSteinberg,
+}
+
+/// `Gray8` の描画を組織的ディザで `BinaryColor` の描画先に描く
+///
+/// 描画先の座標をそのまま使うので、隣り合う図形の模様がずれません。
+pub struct OrderedDither<'a, D> {
+    target: &'a mut D,
+    matrix: BayerMatrix,
+}
+
+impl<'a, D> OrderedDither<'a, D>
+where
+    D: DrawTarget<Color = BinaryColor>,
+{
+    pub fn new(target: &'a mut D, matrix: BayerMatrix) -> Self {
+        Self { target, matrix }
+    }
+}
+
+impl<D> Dimensions for OrderedDither<'_, D>
+where
+    D: DrawTarget<Color = BinaryColor>,
+{
+    fn bounding_box(&self) -> Rectangle {
+        self.target.bounding_box()
+    }
+}
+
+impl<D> DrawTarget for OrderedDither<'_, D>
+where
+    D: DrawTarget<Color = BinaryColor>,
+{
+    type Color = Gray8;
+    type Error = D::Error;
+
+    fn draw_iter<I>(&mut self, pixels: I) -> Result<(), Self::Error>
+    where
+        I: IntoIterator<Item = Pixel<Self::Color>>,
+    {
+        let matrix = self.matrix;
+        self.target.draw_iter(pixels.into_iter().map(|Pixel(point, color)| {
+            Pixel(point, BinaryColor::from(matrix.is_on(point.x, point.y, color.luma())))
+        }))
+    }
+}
+
+/// `Gray8` で描画できるメモリ上の画像
+///
+/// 画像や図形を描画してから `dither` で 1 ビットの画像に変換します。
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct GrayCanvas {
+    width: u32,
+    height: u32,
+    data: Vec<u8>,
+}
+
+impl GrayCanvas {
+    /// 黒 (明るさ 0) で塗りつぶした画像を作成します。
+    pub fn new(size: Size) -> Self {
+        Self {
+            width: size.width,
+            height: size.height,
+            data: vec![0; (size.width * size.height) as usize],
+        }
+    }
+
+    /// 1 画素 1 バイト、左上から行ごとに並んだ明るさのデータから作成します。
+    ///
+    /// 高さはデータの長さから決まります (端数の画素は捨てます)。
+    pub fn from_luma(data: &[u8], width: u32) -> Self {
+        let width = width.max(1);
+        let height = data.len() as u32 / width;
+        Self {
+            width,
+            height,
+            data: data[..(width * height) as usize].to_vec(),
+        }
+    }
+
+    /// 画素の明るさ。範囲外なら `None` を返します。
+    pub fn luma(&self, x: u32, y: u32) -> Option<u8> {
+        if x >= self.width || y >= self.height {
+            return None;
+        }
+        Some(self.data[(y * self.width + x) as usize])
+    }
+
+    /// 1 ビットの画像に変換します。
+    pub fn dither(&self, method: DitherMethod) -> DitheredImage {
+        let mut image = DitheredImage::new(self.width, self.height);
+        match method {
+            DitherMethod::Threshold(threshold) => {
+                self.for_each_pixel(|x, y, luma| image.set(x, y, luma > threshold));
+            }
+            DitherMethod::Ordered(matrix) => {
+                self.for_each_pixel(|x, y, luma| image.set(x, y, matrix.is_on(x as i32, y as i32, luma)));
+            }
+            DitherMethod::FloydSteinberg => self.floyd_steinberg(&mut image),
+        }
+        image
+    }
+
+    fn for_each_pixel(&self, mut f: impl FnMut(u32, u32, u8)) {
+        for y in 0..self.height {
+            for x in 0..self.width {
+                f(x, y, self.data[(y * self.width + x) as usize]);
+            }
+        }
+    }
+
+    /// 誤差を右 7/16、左下 3/16、下 5/16、右下 1/16 に拡散します。
+    ///
+    /// 行ごとに走査方向を反転 (蛇行走査) して、斜めの筋が出にくいようにしています。
+    fn floyd_steinberg(&self, image: &mut DitheredImage) {
+        let width = self.width as usize;
+        // 両端に1画素ずつ余白を持たせ、範囲外への拡散を分岐なしで捨てる
+        let mut current = vec![0i16; width + 2];
+        let mut next = vec![0i16; width + 2];
+
+        for y in 0..self.height as usize {
+            let row = &self.data[y * width..(y + 1) * width];
+            for (x, &luma) in row.iter().enumerate() {
+                current[x + 1] += luma as i16;
+            }
+
+            let reverse = y % 2 == 1;
+            for i in 0..width {
+                let x = if reverse { width - 1 - i } else { i };
+                let value = current[x + 1];
+                let on = value >= 128;
+                image.set(x as u32, y as u32, on);
+
+                let error = value - if on { 255 } else { 0 };
+                let (ahead, behind) = if reverse { (x, x + 2) } else { (x + 2, x) };
+                current[ahead] += error * 7 / 16;
+                next[behind] += error * 3 / 16;
+                next[x + 1] += error * 5 / 16;
+                next[ahead] += error / 16;
+            }
+
+            core::mem::swap(&mut current, &mut next);
+            next.fill(0);
+        }
+    }
+}
+
+impl OriginDimensions for GrayCanvas {
+    fn size(&self) -> Size {
+        Size::new(self.width, self.height)
+    }
+}
+
+impl DrawTarget for GrayCanvas {
+    type Color = Gray8;
+    type Error = core::convert::Infallible;
+
+    fn draw_iter<I>(&mut self, pixels: I) -> Result<(), Self::Error>
+    where
+        I: IntoIterator<Item = Pixel<Self::Color>>,
+    {
+        for Pixel(point, color) in pixels {
+            if point.x >= 0 && point.y >= 0 && (point.x as u32) < self.width && (point.y as u32) < self.height {
+                self.data[(point.y as u32 * self.width + point.x as u32) as usize] = color.luma();
+            }
+        }
+        Ok(())
+    }
+}
+
+/// ディザリングした 1 ビットの画像
+///
+/// `ImageRaw<BinaryColor>` と同じ並び (1 行ごとにバイト境界で揃え、上位ビットが左) で保持します。
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct DitheredImage {
+    width: u32,
+    height: u32,
+    data: Vec<u8>,
+}
+
+impl DitheredImage {
+    fn new(width: u32, height: u32) -> Self {
+        Self {
+            width,
+            height,
+            data: vec![0; (width.div_ceil(8) * height) as usize],
+        }
+    }
+
+    fn set(&mut self, x: u32, y: u32, on: bool) {
+        if on {
+            let index = (y * self.width.div_ceil(8) + x / 8) as usize;
+            self.data[index] |= 0x80 >> (x % 8);
+        }
+    }
+
+    /// `Image` や `animation::Sprite` で描画できる画像として参照します。
+    pub fn as_image(&self) -> MonoImage<'_> {
+        ImageRaw::new(&self.data, self.width)
+    }
+
+    /// 画像の大きさ
+    pub fn size(&self) -> Size {
+        Size::new(self.width, self.height)
+    }
+}
@@ -0,0 +1,374 @@
+//! ボタン・ロータリーエンコーダの入力を抽象化したイベント
+//!
+//! メニューなどの UI は `InputSource` からイベントを受け取るだけなので、
+//! 入力デバイスの種類 (押しボタン、エンコーダ、ネットワーク経由など) に依存しません。
+//!
+//! ## 使用例
+//!
+//! ```no_run
+//! use esp32s2_common_lib::input::{Button, ButtonInput, InputEvent, InputSource};
+//! # use esp_idf_svc::hal::{gpio::{PinDriver, Pull}, prelude::Peripherals};
+//! # let peripherals = Peripherals::take().unwrap();
+//!
+//! // BOOT ボタン (GPIO0) は押すと LOW になる
+//! let mut pin = PinDriver::input(peripherals.pins.gpio0).unwrap();
+//! pin.set_pull(Pull::Up).unwrap();
+//!
+//! // 短押しで次の項目、長押しで決定
+//! let mut input = ButtonInput::new()
+//!     .with_long_press(Button::active_low(pin), InputEvent::Down, InputEvent::Select);
+//! loop {
+//!     if let Some(event) = input.poll() {
+//!         println!("{:?}", event);
+//!     }
+//! }
+//! ```
+
+use std::time::{Duration, Instant};
+
+use embedded_hal::digital::InputPin;
+
+/// チャタリングとみなす時間のデフォルト値
+pub const DEFAULT_DEBOUNCE: Duration = Duration::from_millis(20);
+/// 長押しとみなす時間のデフォルト値
+pub const DEFAULT_LONG_PRESS: Duration = Duration::from_millis(600);
+
+/// UI の操作イベント
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum InputEvent {
+    Up,
+    Down,
+    Select,
+    Back,
+}
+
+/// 操作イベントの発生源
+pub trait InputSource {
+    /// 新しいイベントがあれば返します。メインループから定期的に呼び出してください。
+    fn poll(&mut self) -> Option<InputEvent>;
+}
+
+/// 押しボタンの状態変化
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ButtonEvent {
+    /// 押された
+    Pressed,
+    /// 長押しの時間に達した (押している間に1回だけ)
+    LongPress,
+    /// 離された。`long` は長押しの後だったかどうか
+    Released { long: bool },
+}
+
+/// 入力値のチャタリングを取り除く
+///
+/// 値が `debounce` の間変化しなかったときだけ、安定した値として採用します。
+#[derive(Debug, Clone)]
+pub struct Debouncer {
+    debounce: Duration,
+    stable: bool,
+    raw: bool,
+    raw_since: Instant,
+}
+
+impl Debouncer {
+    pub fn new(debounce: Duration) -> Self {
+        Self {
+            debounce,
+            stable: false,
+            raw: false,
+            raw_since: Instant::now(),
+        }
+    }
+
+    /// 安定している値
+    pub fn value(&self) -> bool {
+        self.stable
+    }
+
+    /// 読み取った値を渡し、安定した値が変化したら新しい値を返します。
+    pub fn update(&mut self, raw: bool) -> Option<bool> {
+        self.update_at(raw, Instant::now())
+    }
+
+    /// `now` を現在時刻として値を更新します。
+    pub fn update_at(&mut self, raw: bool, now: Instant) -> Option<bool> {
+        if raw != self.raw {
+            self.raw = raw;
+            self.raw_since = now;
+            return None;
+        }
+        if raw != self.stable && now.saturating_duration_since(self.raw_since) >= self.debounce {
+            self.stable = raw;
+            return Some(raw);
+        }
+        None
+    }
+}
+
+/// チャタリング除去と長押し検出を行う GPIO 押しボタン
+pub struct Button<P> {
+    pin: P,
+    active_low: bool,
+    debouncer: Debouncer,
+    long_press: Duration,
+    pressed_at: Option<Instant>,
+    long_reported: bool,
+}
+
+impl<P> Button<P>
+where
+    P: InputPin,
+{
+    /// 押すと HIGH になるボタン
+    pub fn active_high(pin: P) -> Self {
+        Self::new(pin, false)
+    }
+
+    /// 押すと LOW になるボタン (プルアップ接続)
+    pub fn active_low(pin: P) -> Self {
+        Self::new(pin, true)
+    }
+
+    fn new(pin: P, active_low: bool) -> Self {
+        Self {
+            pin,
+            active_low,
+            debouncer: Debouncer::new(DEFAULT_DEBOUNCE),
+            long_press: DEFAULT_LONG_PRESS,
+            pressed_at: None,
+            long_reported: false,
+        }
+    }
+
+    /// チャタリングとみなす時間を変更します。
+    pub fn with_debounce(mut self, debounce: Duration) -> Self {
+        self.debouncer = Debouncer::new(debounce);
+        self
+    }
+
+    /// 長押しとみなす時間を変更します。
+    pub fn with_long_press(mut self, long_press: Duration) -> Self {
+        self.long_press = long_press;
+        self
+    }
+
+    /// 押されているかどうか (チャタリング除去後)
+    pub fn is_pressed(&self) -> bool {
+        self.debouncer.value()
+    }
+
+    /// ピンを読み取り、状態が変化していればイベントを返します。
+    ///
+    /// ピンの読み取りに失敗した場合は、前回の値のままとして扱います。
+    pub fn poll(&mut self) -> Option<ButtonEvent> {
+        let now = Instant::now();
+        let raw = match self.pin.is_high() {
+            Ok(high) => high != self.active_low,
+            Err(_) => self.debouncer.value(),
+        };
+
+        match self.debouncer.update_at(raw, now) {
+            Some(true) => {
+                self.pressed_at = Some(now);
+                self.long_reported = false;
+                Some(ButtonEvent::Pressed)
+            }
+            Some(false) => {
+                self.pressed_at = None;
+                Some(ButtonEvent::Released { long: self.long_reported })
+            }
+            None => match self.pressed_at {
+                Some(at) if !self.long_reported && now.saturating_duration_since(at) >= self.long_press => {
+                    self.long_reported = true;
+                    Some(ButtonEvent::LongPress)
+                }
+                _ => None,
+            },
+        }
+    }
+}
+
+struct ButtonBinding<P> {
+    button: Button<P>,
+    press: InputEvent,
+    long_press: Option<InputEvent>,
+}
+
+/// 複数の押しボタンを `InputEvent` に割り当てる `InputSource`
+pub struct ButtonInput<P> {
+    bindings: Vec<ButtonBinding<P>>,
+}
+
+impl<P> ButtonInput<P>
+where
+    P: InputPin,
+{
+    pub fn new() -> Self {
+        Self { bindings: Vec::new() }
+    }
+
+    /// 押したときに `press` を発生させるボタンを追加します。
+    pub fn with_button(mut self, button: Button<P>, press: InputEvent) -> Self {
+        self.bindings.push(ButtonBinding {
+            button,
+            press,
+            long_press: None,
+        });
+        self
+    }
+
+    /// 短押しで `press`、長押しで `long_press` を発生させるボタンを追加します。
+    ///
+    /// 短押しか長押しかを区別するため、短押しのイベントは離したときに発生します。
+    pub fn with_long_press(mut self, button: Button<P>, press: InputEvent, long_press: InputEvent) -> Self {
+        self.bindings.push(ButtonBinding {
+            button,
+            press,
+            long_press: Some(long_press),
+        });
+        self
+    }
+}
+
+impl<P> Default for ButtonInput<P>
+where
+    P: InputPin,
+{
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl<P> InputSource for ButtonInput<P>
+where
+    P: InputPin,
+{
+    fn poll(&mut self) -> Option<InputEvent> {
+        // 同時に複数のイベントが起きた場合は先に登録したボタンを優先し、残りは次回に回す
+        for binding in &mut self.bindings {
+            let event = binding.button.poll();
+            let mapped = match (event, binding.long_press) {
+                (Some(ButtonEvent::Pressed), None) => Some(binding.press),
+                (Some(ButtonEvent::LongPress), Some(long_press)) => Some(long_press),
+                (Some(ButtonEvent::Released { long: false }), Some(_)) => Some(binding.press),
+                _ => None,
+            };
+            if mapped.is_some() {
+                return mapped;
+            }
+        }
+        None
+    }
+}
+
+/// 2相 (A/B) のロータリーエンコーダ
+///
+/// ポーリングで位相の変化を読み取り、1クリック (4回の状態変化) ごとに
+/// `Down` (時計回り) または `Up` (反時計回り) を発生させます。
+pub struct RotaryEncoder<A, B> {
+    a: A,
+    b: B,
+    state: u8,
+    steps: i8,
+}
+
+impl<A, B> RotaryEncoder<A, B>
+where
+    A: InputPin,
+    B: InputPin,
+{
+    pub fn new(a: A, b: B) -> Self {
+        let mut encoder = Self { a, b, state: 0, steps: 0 };
+        encoder.state = encoder.read().unwrap_or(0);
+        encoder
+    }
+
+    fn read(&mut self) -> Option<u8> {
+        let a = self.a.is_high().ok()? as u8;
+        let b = self.b.is_high().ok()? as u8;
+        Some((a << 1) | b)
+    }
+}
+
+impl<A, B> InputSource for RotaryEncoder<A, B>
+where
+    A: InputPin,
+    B: InputPin,
+{
+    fn poll(&mut self) -> Option<InputEvent> {
+        // 前回と今回の状態 (各2ビット) から回転方向を求める表 (不正な遷移は 0)
+        const TRANSITIONS: [i8; 16] = [0, -1, 1, 0, 1, 0, 0, -1, -1, 0, 0, 1, 0, 1, -1, 0];
+
+        let state = self.read()?;
+        if state == self.state {
+            return None;
+        }
+        self.steps += TRANSITIONS[((self.state << 2) | state) as usize];
+        self.state = state;
+
+        if self.steps >= 4 {
+            self.steps = 0;
+            Some(InputEvent::Down)
+        } else if self.steps <= -4 {
+            self.steps = 0;
+            Some(InputEvent::Up)
+        } else {
+            None
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const DEBOUNCE: Duration = Duration::from_millis(20);
+
+    #[test]
+    fn debouncer_reports_stable_changes() {
+        let start = Instant::now();
+        let at = |ms| start + Duration::from_millis(ms);
+        let mut debouncer = Debouncer::new(DEBOUNCE);
+        assert!(!debouncer.value());
+
+        assert_eq!(debouncer.update_at(true, at(0)), None);
+        assert_eq!(debouncer.update_at(true, at(10)), None);
+        assert_eq!(debouncer.update_at(true, at(20)), Some(true));
+        assert!(debouncer.value());
+        // 安定した後は同じ値を返さない
+        assert_eq!(debouncer.update_at(true, at(30)), None);
+
+        assert_eq!(debouncer.update_at(false, at(40)), None);
+        assert_eq!(debouncer.update_at(false, at(60)), Some(false));
+        assert!(!debouncer.value());
+    }
+
+    #[test]
+    fn debouncer_ignores_bounces() {
+        let start = Instant::now();
+        let at = |ms| start + Duration::from_millis(ms);
+        let mut debouncer = Debouncer::new(DEBOUNCE);
+
+        // 変化するたびに時間を数え直す
+        for (ms, raw) in [(0, true), (5, false), (10, true), (15, false), (20, true)] {
+            assert_eq!(debouncer.update_at(raw, at(ms)), None);
+        }
+        assert_eq!(debouncer.update_at(true, at(35)), None);
+        assert_eq!(debouncer.update_at(true, at(40)), Some(true));
+
+        // 短い間だけ戻っても安定した値は変わらない
+        assert_eq!(debouncer.update_at(false, at(50)), None);
+        assert_eq!(debouncer.update_at(true, at(55)), None);
+        assert_eq!(debouncer.update_at(true, at(100)), None);
+        assert!(debouncer.value());
+    }
+
+    #[test]
+    fn debouncer_handles_time_going_backwards() {
+        let start = Instant::now() + Duration::from_secs(1);
+        let mut debouncer = Debouncer::new(DEBOUNCE);
+        assert_eq!(debouncer.update_at(true, start), None);
+        assert_eq!(debouncer.update_at(true, start - Duration::from_millis(50)), None);
+        assert!(!debouncer.value());
+    }
+}
@@ -1,5 +1,19 @@
 pub mod sh1106_display;
+pub mod animation;
+pub mod bitmap_font;
+pub mod buffered_display;
+pub mod dither;
+pub mod input;
+pub mod menu;
 pub mod mma7660fc;
+pub mod mono_display;
+pub mod orientation;
+pub mod provisioning;
+pub mod render_loop;
+pub mod screensaver;
+pub mod widgets;
+pub mod wifi_credentials;
+pub mod wifi_manager;
 
 #[cfg(test)]
 mod tests {
@@ -0,0 +1,533 @@
+//! OLED 用の階層メニュー
+//!
+//! サブメニュー、アクション、数値・ON/OFF・選択肢の編集項目を持つメニューを
+//! `InputEvent` で操作し、128x64 の画面に描画します。
+//! 各項目はキーで識別するので、アプリケーションは値が変わったときにキーで値を読み出せます。
+//!
+//! ## 使用例
+//!
+//! ```no_run
+//! use esp32s2_common_lib::menu::{Menu, MenuEvent, MenuItem};
+//!
+//! let mut menu = Menu::new("Settings", vec![
+//!     MenuItem::number("brightness", "Brightness", 128, 0, 255, 16),
+//!     MenuItem::toggle("invert", "Invert", false),
+//!     MenuItem::submenu("Time", vec![
+//!         MenuItem::choice("tz", "Zone", vec!["JST".into(), "UTC".into()], 0),
+//!     ]),
+//! ]);
+//!
+//! // match menu.handle(event) {
+//! //     MenuEvent::Changed("brightness") => display.set_contrast(menu.number_value("brightness").unwrap() as u8)?,
+//! //     MenuEvent::Exit => { /* メニューを閉じる */ }
+//! //     _ => {}
+//! // }
+//! ```
+
+use embedded_graphics::{
+    mono_font::{ascii::FONT_5X7, MonoTextStyle},
+    pixelcolor::BinaryColor,
+    prelude::*,
+    primitives::Rectangle,
+    text::{Alignment, Baseline, Text, TextStyleBuilder},
+};
+
+use crate::input::InputEvent;
+
+/// 1行の高さ (FONT_5X7 + 余白)
+const ROW_HEIGHT: u32 = 9;
+
+/// メニューの項目
+pub enum MenuItem {
+    /// 選択するとアプリケーションに通知する項目
+    Action { key: &'static str, label: String },
+    /// 子メニューを開く項目
+    Submenu { label: String, items: Vec<MenuItem> },
+    /// 数値の設定
+    Number {
+        key: &'static str,
+        label: String,
+        value: i32,
+        min: i32,
+        max: i32,
+        step: i32,
+    },
+    /// ON/OFF の設定
+    Toggle { key: &'static str, label: String, value: bool },
+    /// 選択肢から1つを選ぶ設定
+    Choice {
+        key: &'static str,
+        label: String,
+        options: Vec<String>,
+        selected: usize,
+    },
+}
+
+impl MenuItem {
+    pub fn action(key: &'static str, label: impl Into<String>) -> Self {
+        MenuItem::Action { key, label: label.into() }
+    }
+
+    pub fn submenu(label: impl Into<String>, items: Vec<MenuItem>) -> Self {
+        MenuItem::Submenu { label: label.into(), items }
+    }
+
+    pub fn number(key: &'static str, label: impl Into<String>, value: i32, min: i32, max: i32, step: i32) -> Self {
+        MenuItem::Number {
+            key,
+            label: label.into(),
+            value: value.clamp(min, max),
+            min,
+            max,
+            step: step.max(1),
+        }
+    }
+
+    pub fn toggle(key: &'static str, label: impl Into<String>, value: bool) -> Self {
+        MenuItem::Toggle { key, label: label.into(), value }
+    }
+
+    pub fn choice(key: &'static str, label: impl Into<String>, options: Vec<String>, selected: usize) -> Self {
+        let selected = selected.min(options.len().saturating_sub(1));
+        MenuItem::Choice { key, label: label.into(), options, selected }
+    }
+
+    pub fn label(&self) -> &str {
+        match self {
+            MenuItem::Action { label, .. }
+            | MenuItem::Submenu { label, .. }
+            | MenuItem::Number { label, .. }
+            | MenuItem::Toggle { label, .. }
+            | MenuItem::Choice { label, .. } => label,
+        }
+    }
+
+    fn key(&self) -> Option<&'static str> {
+        match self {
+            MenuItem::Action { key, .. }
+            | MenuItem::Number { key, .. }
+            | MenuItem::Toggle { key, .. }
+            | MenuItem::Choice { key, .. } => Some(key),
+            MenuItem::Submenu { .. } => None,
+        }
+    }
+
+    /// 行の右側に表示する値
+    fn value_text(&self) -> Option<String> {
+        match self {
+            MenuItem::Action { .. } => None,
+            MenuItem::Submenu { .. } => Some(">".to_string()),
+            MenuItem::Number { value, .. } => Some(value.to_string()),
+            MenuItem::Toggle { value, .. } => Some(if *value { "ON" } else { "OFF" }.to_string()),
+            MenuItem::Choice { options, selected, .. } => options.get(*selected).cloned(),
+        }
+    }
+
+    /// 編集中の Up/Down で値を変更します。
+    fn adjust(&mut self, up: bool) {
+        match self {
+            MenuItem::Number { value, min, max, step, .. } => {
+                let next = if up { value.saturating_add(*step) } else { value.saturating_sub(*step) };
+                *value = next.clamp(*min, *max);
+            }
+            MenuItem::Choice { options, selected, .. } if !options.is_empty() => {
+                // 選択肢は端で反対側に戻る
+                let len = options.len();
+                *selected = if up { (*selected + 1) % len } else { (*selected + len - 1) % len };
+            }
+            _ => {}
+        }
+    }
+}
+
+/// `Menu::handle` の結果
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum MenuEvent {
+    /// アプリケーションが対応する必要のない操作 (カーソル移動など)
+    None,
+    /// アクション項目が選択された
+    Action(&'static str),
+    /// 設定値が変更された
+    Changed(&'static str),
+    /// 最上位のメニューで戻る操作をした
+    Exit,
+}
+
+/// 階層メニュー
+pub struct Menu {
+    title: String,
+    items: Vec<MenuItem>,
+    /// 開いているサブメニューのインデックス (上位から順に)
+    path: Vec<usize>,
+    /// 各階層のカーソル位置 (`path.len() + 1` 個)
+    cursors: Vec<usize>,
+    /// 表示の先頭行
+    scroll: usize,
+    editing: bool,
+}
+
+impl Menu {
+    pub fn new(title: impl Into<String>, items: Vec<MenuItem>) -> Self {
+        Self {
+            title: title.into(),
+            items,
+            path: Vec::new(),
+            cursors: vec![0],
+            scroll: 0,
+            editing: false,
+        }
+    }
+
+    /// 最上位の先頭の項目に戻します。
+    pub fn reset(&mut self) {
+        self.path.clear();
+        self.cursors = vec![0];
+        self.scroll = 0;
+        self.editing = false;
+    }
+
+    /// 値を編集中かどうか
+    pub fn is_editing(&self) -> bool {
+        self.editing
+    }
+
+    /// 操作イベントを処理します。
+    pub fn handle(&mut self, event: InputEvent) -> MenuEvent {
+        if self.editing {
+            return self.handle_editing(event);
+        }
+
+        let len = self.current_items().len();
+        let cursor = self.cursor();
+        match event {
+            InputEvent::Up => {
+                if len > 0 {
+                    *self.cursor_mut() = (cursor + len - 1) % len;
+                }
+                MenuEvent::None
+            }
+            InputEvent::Down => {
+                if len > 0 {
+                    *self.cursor_mut() = (cursor + 1) % len;
+                }
+                MenuEvent::None
+            }
+            InputEvent::Back => {
+                if self.path.pop().is_some() {
+                    self.cursors.pop();
+                    self.scroll = 0;
+                    MenuEvent::None
+                } else {
+                    MenuEvent::Exit
+                }
+            }
+            InputEvent::Select => {
+                let Some(item) = self.current_items_mut().get_mut(cursor) else {
+                    return MenuEvent::None;
+                };
+                match item {
+                    MenuItem::Action { key, .. } => MenuEvent::Action(key),
+                    MenuItem::Submenu { .. } => {
+                        self.path.push(cursor);
+                        self.cursors.push(0);
+                        self.scroll = 0;
+                        MenuEvent::None
+                    }
+                    MenuItem::Toggle { key, value, .. } => {
+                        *value = !*value;
+                        MenuEvent::Changed(key)
+                    }
+                    MenuItem::Number { .. } | MenuItem::Choice { .. } => {
+                        self.editing = true;
+                        MenuEvent::None
+                    }
+                }
+            }
+        }
+    }
+
+    fn handle_editing(&mut self, event: InputEvent) -> MenuEvent {
+        let cursor = self.cursor();
+        let Some(item) = self.current_items_mut().get_mut(cursor) else {
+            self.editing = false;
+            return MenuEvent::None;
+        };
+        match event {
+            InputEvent::Up | InputEvent::Down => {
+                item.adjust(event == InputEvent::Up);
+                item.key().map_or(MenuEvent::None, MenuEvent::Changed)
+            }
+            InputEvent::Select | InputEvent::Back => {
+                self.editing = false;
+                MenuEvent::None
+            }
+        }
+    }
+
+    /// キーで項目を探します (サブメニューの中も含む)。
+    pub fn item(&self, key: &str) -> Option<&MenuItem> {
+        fn find<'a>(items: &'a [MenuItem], key: &str) -> Option<&'a MenuItem> {
+            items.iter().find_map(|item| match item {
+                MenuItem::Submenu { items, .. } => find(items, key),
+                _ if item.key() == Some(key) => Some(item),
+                _ => None,
+            })
+        }
+        find(&self.items, key)
+    }
+
+    /// キーで項目を探し、変更可能な参照を返します。
+    pub fn item_mut(&mut self, key: &str) -> Option<&mut MenuItem> {
+        fn find<'a>(items: &'a mut [MenuItem], key: &str) -> Option<&'a mut MenuItem> {
+            items.iter_mut().find_map(|item| {
+                if item.key() == Some(key) {
+                    return Some(item);
+                }
+                match item {
+                    MenuItem::Submenu { items, .. } => find(items, key),
+                    _ => None,
+                }
+            })
+        }
+        find(&mut self.items, key)
+    }
+
+    /// 数値項目の値
+    pub fn number_value(&self, key: &str) -> Option<i32> {
+        match self.item(key)? {
+            MenuItem::Number { value, .. } => Some(*value),
+            _ => None,
+        }
+    }
+
+    /// ON/OFF 項目の値
+    pub fn toggle_value(&self, key: &str) -> Option<bool> {
+        match self.item(key)? {
+            MenuItem::Toggle { value, .. } => Some(*value),
+            _ => None,
+        }
+    }
+
+    /// 選択肢項目で選ばれているインデックス
+    pub fn choice_index(&self, key: &str) -> Option<usize> {
+        match self.item(key)? {
+            MenuItem::Choice { selected, .. } => Some(*selected),
+            _ => None,
+        }
+    }
+
+    fn cursor(&self) -> usize {
+        *self.cursors.last().unwrap_or(&0)
+    }
+
+    fn cursor_mut(&mut self) -> &mut usize {
+        self.cursors.last_mut().expect("cursor stack is never empty")
+    }
+
+    fn current_title(&self) -> &str {
+        let mut title = self.title.as_str();
+        let mut items = &self.items;
+        for &index in &self.path {
+            if let Some(MenuItem::Submenu { label, items: children }) = items.get(index) {
+                title = label;
+                items = children;
+            }
+        }
+        title
+    }
+
+    /// `path` のうち、実際にサブメニューを指している階層の数
+    fn valid_depth(&self) -> usize {
+        let mut items = &self.items;
+        for (depth, &index) in self.path.iter().enumerate() {
+            match items.get(index) {
+                Some(MenuItem::Submenu { items: children, .. }) => items = children,
+                _ => return depth,
+            }
+        }
+        self.path.len()
+    }
+
+    fn current_items(&self) -> &[MenuItem] {
+        let mut items = &self.items;
+        for &index in &self.path[..self.valid_depth()] {
+            if let Some(MenuItem::Submenu { items: children, .. }) = items.get(index) {
+                items = children;
+            }
+        }
+        items
+    }
+
+    fn current_items_mut(&mut self) -> &mut Vec<MenuItem> {
+        let depth = self.valid_depth();
+        let mut items = &mut self.items;
+        for &index in &self.path[..depth] {
+            items = match &mut items[index] {
+                MenuItem::Submenu { items: children, .. } => children,
+                _ => unreachable!("valid_depth only covers submenus"),
+            };
+        }
+        items
+    }
+
+    /// 描画します。`area` にはメニューを表示する領域 (通常は画面全体) を渡します。
+    ///
+    /// カーソルが画面外に出ないように表示位置を調整するため `&mut self` を取ります。
+    pub fn draw<D>(&mut self, target: &mut D, area: Rectangle) -> Result<(), D::Error>
+    where
+        D: DrawTarget<Color = BinaryColor>,
+    {
+        let rows = ((area.size.height / ROW_HEIGHT) as usize).saturating_sub(1).max(1);
+        let cursor = self.cursor();
+        if cursor < self.scroll {
+            self.scroll = cursor;
+        } else if cursor >= self.scroll + rows {
+            self.scroll = cursor + 1 - rows;
+        }
+
+        target.fill_solid(&area, BinaryColor::Off)?;
+
+        let normal = MonoTextStyle::new(&FONT_5X7, BinaryColor::On);
+        let inverted = MonoTextStyle::new(&FONT_5X7, BinaryColor::Off);
+        let right = TextStyleBuilder::new()
+            .alignment(Alignment::Right)
+            .baseline(Baseline::Top)
+            .build();
+        let width = area.size.width;
+
+        // タイトル (反転表示、選択行と区別するため下に 1 ピクセル空ける)
+        let title_row = Rectangle::new(area.top_left, Size::new(width, ROW_HEIGHT - 1));
+        target.fill_solid(&title_row, BinaryColor::On)?;
+        Text::with_baseline(self.current_title(), area.top_left + Point::new(2, 1), inverted, Baseline::Top)
+            .draw(target)?;
+
+        let editing = self.editing;
+        for (row, (index, item)) in self
+            .current_items()
+            .iter()
+            .enumerate()
+            .skip(self.scroll)
+            .take(rows)
+            .enumerate()
+        {
+            let top_left = area.top_left + Point::new(0, ((row + 1) as u32 * ROW_HEIGHT) as i32);
+            let selected = index == cursor;
+            let style = if selected {
+                target.fill_solid(&Rectangle::new(top_left, Size::new(width, ROW_HEIGHT)), BinaryColor::On)?;
+                inverted
+            } else {
+                normal
+            };
+
+            Text::with_baseline(item.label(), top_left + Point::new(2, 1), style, Baseline::Top).draw(target)?;
+            if let Some(value) = item.value_text() {
+                // 編集中の値は < > で囲む
+                let value = if selected && editing { format!("<{}>", value) } else { value };
+                Text::with_text_style(&value, top_left + Point::new(width as i32 - 2, 1), style, right)
+                    .draw(target)?;
+            }
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn menu() -> Menu {
+        Menu::new("Settings", vec![
+            MenuItem::number("brightness", "Brightness", 128, 0, 255, 100),
+            MenuItem::submenu("Display", vec![
+                MenuItem::toggle("invert", "Invert", false),
+                MenuItem::choice("tz", "Zone", vec!["JST".into(), "UTC".into(), "CET".into()], 0),
+            ]),
+            MenuItem::action("close", "Close"),
+        ])
+    }
+
+    #[test]
+    fn cursor_wraps_around() {
+        let mut menu = menu();
+        assert_eq!(menu.handle(InputEvent::Up), MenuEvent::None);
+        assert_eq!(menu.handle(InputEvent::Select), MenuEvent::Action("close"));
+        assert_eq!(menu.handle(InputEvent::Down), MenuEvent::None);
+        assert_eq!(menu.handle(InputEvent::Select), MenuEvent::None);
+        assert!(menu.is_editing());
+    }
+
+    #[test]
+    fn edits_number_within_range() {
+        let mut menu = menu();
+        menu.handle(InputEvent::Select);
+        assert_eq!(menu.handle(InputEvent::Up), MenuEvent::Changed("brightness"));
+        assert_eq!(menu.handle(InputEvent::Up), MenuEvent::Changed("brightness"));
+        assert_eq!(menu.number_value("brightness"), Some(255));
+        for _ in 0..4 {
+            menu.handle(InputEvent::Down);
+        }
+        assert_eq!(menu.number_value("brightness"), Some(0));
+
+        // 編集中の Back は編集を終えるだけで、メニューは閉じない
+        assert_eq!(menu.handle(InputEvent::Back), MenuEvent::None);
+        assert!(!menu.is_editing());
+        assert_eq!(menu.handle(InputEvent::Back), MenuEvent::Exit);
+    }
+
+    #[test]
+    fn edits_items_in_submenu() {
+        let mut menu = menu();
+        menu.handle(InputEvent::Down);
+        menu.handle(InputEvent::Select);
+        assert_eq!(menu.handle(InputEvent::Select), MenuEvent::Changed("invert"));
+        assert_eq!(menu.toggle_value("invert"), Some(true));
+
+        menu.handle(InputEvent::Down);
+        menu.handle(InputEvent::Select);
+        assert_eq!(menu.handle(InputEvent::Down), MenuEvent::Changed("tz"));
+        assert_eq!(menu.choice_index("tz"), Some(2));
+        assert_eq!(menu.handle(InputEvent::Up), MenuEvent::Changed("tz"));
+        assert_eq!(menu.choice_index("tz"), Some(0));
+        menu.handle(InputEvent::Select);
+
+        // サブメニューから戻ると、元の位置 (Display) にカーソルがある
+        assert_eq!(menu.handle(InputEvent::Back), MenuEvent::None);
+        menu.handle(InputEvent::Select);
+        menu.handle(InputEvent::Select);
+        assert_eq!(menu.toggle_value("invert"), Some(false));
+    }
+
+    #[test]
+    fn reset_returns_to_top() {
+        let mut menu = menu();
+        menu.handle(InputEvent::Down);
+        menu.handle(InputEvent::Select);
+        menu.handle(InputEvent::Down);
+        menu.handle(InputEvent::Select);
+        menu.reset();
+        assert!(!menu.is_editing());
+        assert_eq!(menu.handle(InputEvent::Back), MenuEvent::Exit);
+    }
+
+    #[test]
+    fn finds_items_by_key() {
+        let mut menu = menu();
+        assert_eq!(menu.number_value("invert"), None);
+        assert_eq!(menu.toggle_value("missing"), None);
+        if let Some(MenuItem::Toggle { value, .. }) = menu.item_mut("invert") {
+            *value = true;
+        }
+        assert_eq!(menu.toggle_value("invert"), Some(true));
+        assert_eq!(menu.item("tz").map(MenuItem::label), Some("Zone"));
+    }
+
+    #[test]
+    fn constructors_clamp_values() {
+        let menu = Menu::new("Settings", vec![
+            MenuItem::number("n", "N", 500, 0, 255, 0),
+            MenuItem::choice("c", "C", vec!["A".into(), "B".into()], 5),
+        ]);
+        assert_eq!(menu.number_value("n"), Some(255));
+        assert_eq!(menu.choice_index("c"), Some(1));
+    }
+}
@@ -39,7 +39,11 @@ use embedded_hal::i2c::I2c;
 const REG_XOUT: u8 = 0x00;
 const REG_YOUT: u8 = 0x01;
 const REG_ZOUT: u8 = 0x02;
+const REG_TILT: u8 = 0x03;
+const REG_INTSU: u8 = 0x06;
 const REG_MODE: u8 = 0x07;
+const REG_PDET: u8 = 0x09;
+const REG_PD: u8 = 0x0A;
 
 /// デフォルトのI2Cスレーブアドレス
 pub const DEFAULT_I2C_ADDRESS: u8 = 0x4C;
@@ -62,6 +66,68 @@ pub struct Acceleration {
     pub z: i8,
 }
 
+/// 縦横の向き (TILT レジスタの PoLa)
+///
+/// センサーのパッケージを基準にした向きです。
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Orientation {
+    /// 判定できない
+    Unknown,
+    /// 左に倒した横向き
+    Left,
+    /// 右に倒した横向き
+    Right,
+    /// 逆さまの縦向き
+    Down,
+    /// 通常の縦向き
+    Up,
+}
+
+/// 表裏 (TILT レジスタの BaFro)
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Facing {
+    /// 判定できない
+    Unknown,
+    /// 表が上
+    Front,
+    /// 裏が上
+    Back,
+}
+
+/// TILT レジスタの内容
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Tilt {
+    pub orientation: Orientation,
+    pub facing: Facing,
+    /// タップを検出した
+    pub tap: bool,
+    /// 揺れを検出した
+    pub shake: bool,
+}
+
+impl Tilt {
+    fn from_register(value: u8) -> Self {
+        let orientation = match (value >> 2) & 0b111 {
+            0b001 => Orientation::Left,
+            0b010 => Orientation::Right,
+            0b101 => Orientation::Down,
+            0b110 => Orientation::Up,
+            _ => Orientation::Unknown,
+        };
+        let facing = match value & 0b11 {
+            0b01 => Facing::Front,
+            0b10 => Facing::Back,
+            _ => Facing::Unknown,
+        };
+        Self {
+            orientation,
+            facing,
+            tap: value & 0x20 != 0,
+            shake: value & 0x80 != 0,
+        }
+    }
+}
+
 /// MMA7660FC ドライバ
 pub struct Mma7660fc<I2C> {
     i2c: I2C,
@@ -115,6 +181,34 @@ where
         Ok(())
     }
 
+    /// タップの検出を設定します。検出結果は `get_tilt` の `tap` で読み取ります。
+    ///
+    /// * `threshold` - 検出するしきい値 (1-31, 加速度と同じ単位)。0 にすると検出しません
+    /// * `debounce` - しきい値を超えたと判定するまでのサンプル数 (0-255)
+    ///
+    /// 設定はスタンバイモードで行う必要があるため、`set_mode(Mode::Active)` の前に呼び出してください。
+    pub fn set_tap_detection(&mut self, threshold: u8, debounce: u8) -> Result<(), E> {
+        // bit 7-5 (ZDA, YDA, XDA) が 1 の軸は検出しない
+        let pdet = match threshold.min(0x1F) {
+            0 => 0b1110_0000,
+            threshold => threshold,
+        };
+        self.write_register(REG_PDET, pdet)?;
+        self.write_register(REG_PD, debounce)
+    }
+
+    /// 揺れの検出を設定します。検出結果は `get_tilt` の `shake` で読み取ります。
+    ///
+    /// `true` にすると X, Y, Z のすべての軸で検出します (INTSU の SHINTX/Y/Z)。
+    /// INTSU のほかの割り込みの設定は変えません。
+    /// 設定はスタンバイモードで行う必要があるため、`set_mode(Mode::Active)` の前に呼び出してください。
+    pub fn set_shake_detection(&mut self, enabled: bool) -> Result<(), E> {
+        const SHAKE_BITS: u8 = 0b1110_0000;
+        let intsu = self.read_register(REG_INTSU)?;
+        let intsu = if enabled { intsu | SHAKE_BITS } else { intsu & !SHAKE_BITS };
+        self.write_register(REG_INTSU, intsu)
+    }
+
     /// X, Y, Z軸の加速度データを取得します。
     ///
     /// データは6ビットの符号付き整数として返されます。
@@ -132,6 +226,20 @@ where
         Ok(Acceleration { x, y, z })
     }
 
+    /// 向き・表裏・タップ・揺れの検出結果 (TILT レジスタ) を取得します。
+    ///
+    /// 更新中に読み取ったことを示す Alert ビットが立っていた場合は読み直します。
+    pub fn get_tilt(&mut self) -> Result<Tilt, E> {
+        let mut value = self.read_register(REG_TILT)?;
+        for _ in 0..3 {
+            if value & 0x40 == 0 {
+                break;
+            }
+            value = self.read_register(REG_TILT)?;
+        }
+        Ok(Tilt::from_register(value))
+    }
+
     /// センサーから読み取った6ビットの値をi8の符号付き整数に変換します。
     ///
     /// MMA7660FCのデータは6ビットの2の補数で表現されます。
@@ -0,0 +1,106 @@
+//! モノクロ OLED ディスプレイの共通インターフェース
+//!
+//! コントローラ (SH1106 / SSD1306) に依存しない処理は `MonoDisplay` を受け取るように書くと、
+//! アプリケーション側の設定だけでパネルを差し替えられます。
+//! 描画は embedded-graphics の `DrawTarget` で行い、`flush` でパネルに反映します。
+//!
+//! ## 使用例
+//!
+//! ```no_run
+//! use embedded_graphics::{pixelcolor::BinaryColor, prelude::*, primitives::*};
+//! use esp32s2_common_lib::mono_display::MonoDisplay;
+//!
+//! fn draw_frame<D: MonoDisplay>(display: &mut D) -> Result<(), D::InterfaceError> {
+//!     MonoDisplay::clear(display);
+//!     let bounds = display.bounding_box();
+//!     bounds
+//!         .into_styled(PrimitiveStyle::with_stroke(BinaryColor::On, 1))
+//!         .draw(display)
+//!         .unwrap_or_else(|never| match never {});
+//!     display.flush()
+//! }
+//! ```
+
+use core::convert::Infallible;
+use core::fmt::Debug;
+
+use embedded_graphics::{
+    pixelcolor::BinaryColor,
+    prelude::*,
+    text::{Baseline, Text},
+};
+use sh1106::interface::DisplayInterface;
+
+use crate::bitmap_font::{BitmapFont, BitmapTextStyle};
+use crate::buffered_display::{BufferedDisplay, Controller};
+
+/// バッファに描画してからまとめて送信するモノクロディスプレイ
+///
+/// `DrawTarget::clear(color)` と名前が重なるため、ジェネリックな関数の中でバッファを消去するときは
+/// `MonoDisplay::clear(display)` と書いてください。
+pub trait MonoDisplay: DrawTarget<Color = BinaryColor, Error = Infallible> + OriginDimensions {
+    /// 通信エラーの型
+    type InterfaceError: Debug;
+
+    /// コントローラの種類
+    fn controller(&self) -> Controller;
+
+    /// コントローラを初期化し、画面全体を消去します。
+    fn init(&mut self) -> Result<(), Self::InterfaceError>;
+
+    /// 描画用のバッファを消去します。パネルへの反映は `flush` で行います。
+    fn clear(&mut self);
+
+    /// 描画用のバッファの内容をパネルに送信します。
+    fn flush(&mut self) -> Result<(), Self::InterfaceError>;
+
+    /// 表示の ON/OFF を切り替えます。
+    fn set_display_on(&mut self, on: bool) -> Result<(), Self::InterfaceError>;
+
+    /// コントラスト (明るさ) を設定します。
+    fn set_contrast(&mut self, contrast: u8) -> Result<(), Self::InterfaceError>;
+
+    /// `font` で文字列を描画し、最後の文字の次の位置を返します。
+    ///
+    /// `position` は1行目の左上です。改行 (`\n`) で次の行に移ります。
+    fn draw_text(&mut self, text: &str, position: Point, font: &BitmapFont<'_>) -> Point
+    where
+        Self: Sized,
+    {
+        Text::with_baseline(text, position, BitmapTextStyle::new(font, BinaryColor::On), Baseline::Top)
+            .draw(self)
+            .unwrap_or_else(|never| match never {})
+    }
+}
+
+impl<DI> MonoDisplay for BufferedDisplay<DI>
+where
+    DI: DisplayInterface,
+    DI::Error: Debug,
+{
+    type InterfaceError = DI::Error;
+
+    fn controller(&self) -> Controller {
+        BufferedDisplay::controller(self)
+    }
+
+    fn init(&mut self) -> Result<(), Self::InterfaceError> {
+        BufferedDisplay::init(self)
+    }
+
+    fn clear(&mut self) {
+        BufferedDisplay::clear(self)
+    }
+
+    fn flush(&mut self) -> Result<(), Self::InterfaceError> {
+        BufferedDisplay::flush(self)
+    }
+
+    fn set_display_on(&mut self, on: bool) -> Result<(), Self::InterfaceError> {
+        BufferedDisplay::set_display_on(self, on)
+    }
+
+    fn set_contrast(&mut self, contrast: u8) -> Result<(), Self::InterfaceError> {
+        BufferedDisplay::set_contrast(self, contrast)
+    }
+}
@@ -0,0 +1,159 @@
+//! 加速度センサーで検出した向きから画面の回転を決める
+//!
+//! MMA7660FC の TILT レジスタ (`Mma7660fc::get_tilt`) の向きを `DisplayRotation` に変換し、
+//! 一定時間同じ向きが続いたときだけ回転を切り替えます。
+//! 向きと回転の対応は、センサーとディスプレイの取り付け方に合わせて `with_offset` で調整します。
+//!
+//! ## 使用例
+//!
+//! ```no_run
+//! use esp32s2_common_lib::orientation::OrientationTracker;
+//!
+//! let mut tracker = OrientationTracker::new();
+//! loop {
+//!     // if let Ok(tilt) = sensor.get_tilt() {
+//!     //     if let Some(rotation) = tracker.update(tilt.orientation) {
+//!     //         display.set_rotation(rotation);
+//!     //         // 新しい大きさ (display.size()) に合わせて描画し直す
+//!     //     }
+//!     // }
+//! }
+//! ```
+
+use std::time::{Duration, Instant};
+
+use sh1106::displayrotation::DisplayRotation;
+
+use crate::mma7660fc::Orientation;
+
+/// 回転を切り替えるまでに同じ向きが続く必要がある時間のデフォルト値
+pub const DEFAULT_STABLE_TIME: Duration = Duration::from_millis(500);
+
+/// 回転を時計回りの 90 度単位の回数 (0-3) に変換します。
+pub fn rotation_to_quarters(rotation: DisplayRotation) -> u8 {
+    match rotation {
+        DisplayRotation::Rotate0 => 0,
+        DisplayRotation::Rotate90 => 1,
+        DisplayRotation::Rotate180 => 2,
+        DisplayRotation::Rotate270 => 3,
+    }
+}
+
+/// 時計回りの 90 度単位の回数を回転に変換します。4 以上は 4 で割った余りを使います。
+pub fn quarters_to_rotation(quarters: u8) -> DisplayRotation {
+    match quarters % 4 {
+        0 => DisplayRotation::Rotate0,
+        1 => DisplayRotation::Rotate90,
+        2 => DisplayRotation::Rotate180,
+        _ => DisplayRotation::Rotate270,
+    }
+}
+
+/// 回転によって幅と高さが入れ替わる (縦長になる) かどうか
+pub fn is_portrait(rotation: DisplayRotation) -> bool {
+    rotation_to_quarters(rotation) % 2 == 1
+}
+
+/// センサーの向きから画面の回転を決める
+pub struct OrientationTracker {
+    offset: u8,
+    allow_portrait: bool,
+    stable_time: Duration,
+    current: u8,
+    candidate: Option<(u8, Instant)>,
+}
+
+impl OrientationTracker {
+    /// 回転 0 度から始まるトラッカーを作成します。
+    pub fn new() -> Self {
+        Self {
+            offset: 0,
+            allow_portrait: true,
+            stable_time: DEFAULT_STABLE_TIME,
+            current: 0,
+            candidate: None,
+        }
+    }
+
+    /// センサーが `Orientation::Up` のときの画面の回転を設定します。
+    ///
+    /// ほかの向きはここから 90 度ずつずらした回転になります。
+    pub fn with_offset(mut self, rotation: DisplayRotation) -> Self {
+        self.offset = rotation_to_quarters(rotation);
+        self
+    }
+
+    /// 縦長 (90/270 度) の回転を使うかどうかを設定します。
+    ///
+    /// 使わない場合、縦長になる向きは無視して直前の回転を保ちます。
+    pub fn with_portrait(mut self, allow: bool) -> Self {
+        self.allow_portrait = allow;
+        self
+    }
+
+    /// 回転を切り替えるまでに同じ向きが続く必要がある時間を設定します。
+    pub fn with_stable_time(mut self, stable_time: Duration) -> Self {
+        self.stable_time = stable_time;
+        self
+    }
+
+    /// 現在の回転
+    pub fn rotation(&self) -> DisplayRotation {
+        quarters_to_rotation(self.current)
+    }
+
+    /// センサーの向きに対応する回転 (時計回りの 90 度単位の回数)
+    fn quarters_for(&self, orientation: Orientation) -> Option<u8> {
+        let quarters = match orientation {
+            Orientation::Up => 0,
+            Orientation::Right => 1,
+            Orientation::Down => 2,
+            Orientation::Left => 3,
+            Orientation::Unknown => return None,
+        };
+        let quarters = (quarters + self.offset) % 4;
+        if !self.allow_portrait && quarters % 2 == 1 {
+            return None;
+        }
+        Some(quarters)
+    }
+
+    /// センサーの向きを渡し、回転が変わったら新しい回転を返します。
+    pub fn update(&mut self, orientation: Orientation) -> Option<DisplayRotation> {
+        self.update_at(orientation, Instant::now())
+    }
+
+    /// `now` を現在時刻としてセンサーの向きを渡します。
+    pub fn update_at(&mut self, orientation: Orientation, now: Instant) -> Option<DisplayRotation> {
+        let Some(quarters) = self.quarters_for(orientation) else {
+            // 判定できない向きや使わない向きでは、切り替え待ちもやり直す
+            self.candidate = None;
+            return None;
+        };
+        if quarters == self.current {
+            self.candidate = None;
+            return None;
+        }
+
+        match self.candidate {
+            Some((candidate, since)) if candidate == quarters => {
+                if now.saturating_duration_since(since) < self.stable_time {
+                    return None;
+                }
+                self.current = quarters;
+                self.candidate = None;
+                Some(self.rotation())
+            }
+            _ => {
+                self.candidate = Some((quarters, now));
+                None
+            }
+        }
+    }
+}
+
+impl Default for OrientationTracker {
+    fn default() -> Self {
+        Self::new()
+    }
+}
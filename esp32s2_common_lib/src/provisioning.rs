@@ -0,0 +1,546 @@
+//! SoftAP とキャプティブポータルによる Wi-Fi の接続先の設定
+//!
+//! 接続先が保存されていないときや接続に失敗したときに、本体をアクセスポイントにして
+//! 接続先を入力する Web ページを表示します。スマートフォンなどでアクセスポイントに接続すると、
+//! DNS の問い合わせにすべて本体の IP アドレスを返すので、OS のログイン画面としてページが開きます。
+//!
+//! 1. 周囲のネットワークをスキャンする
+//! 2. アクセスポイント (パスワードなし) を開始し、HTTP サーバーと DNS サーバーを起動する
+//! 3. フォームで送信された SSID とパスワードを `CredentialStore` に保存して戻る
+//!
+//! 保存後はステーションモードで接続し直すため、呼び出し側で再起動してください。
+//!
+//! ## 使用例
+//!
+//! ```no_run
+//! # fn example(
+//! #     wifi: &mut esp_idf_svc::wifi::BlockingWifi<esp_idf_svc::wifi::EspWifi<'static>>,
+//! #     store: &mut esp32s2_common_lib::wifi_credentials::CredentialStore,
+//! # ) -> anyhow::Result<()> {
+//! use esp32s2_common_lib::provisioning::{ProvisioningPortal, DEFAULT_AP_SSID};
+//!
+//! let portal = ProvisioningPortal::new(DEFAULT_AP_SSID);
+//! let credentials = portal.run(wifi, store)?;
+//! log::info!("saved {:?}, restarting", credentials);
+//! esp_idf_svc::hal::reset::restart();
+//! # }
+//! ```
+
+use std::fmt::Write as _;
+use std::net::{Ipv4Addr, UdpSocket};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{mpsc, Arc};
+use std::thread;
+use std::time::Duration;
+
+use esp_idf_svc::http::server::{Configuration as HttpConfiguration, EspHttpServer};
+use esp_idf_svc::http::Method;
+use esp_idf_svc::io::{Read, Write};
+use esp_idf_svc::wifi::{
+    AccessPointConfiguration, AuthMethod, BlockingWifi, ClientConfiguration, Configuration, EspWifi,
+};
+
+use crate::wifi_credentials::{CredentialStore, Credentials, InvalidCredentials};
+
+/// 設定用のアクセスポイントの SSID のデフォルト値
+pub const DEFAULT_AP_SSID: &str = "esp32s2-setup";
+
+/// 受け付けるフォームの最大の大きさ (バイト)
+const MAX_FORM_LEN: usize = 512;
+
+/// DNS サーバーのスレッドのスタックサイズ (バイト)。512 バイトの受信バッファをスタックに置く
+const DNS_STACK_SIZE: usize = 6144;
+
+/// 周囲のネットワーク
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Network {
+    pub ssid: String,
+    /// 受信強度 (dBm)
+    pub rssi: i8,
+    /// パスワードが必要かどうか
+    pub secured: bool,
+}
+
+/// 接続先を設定する Web ページを提供するポータル
+pub struct ProvisioningPortal {
+    ap_ssid: String,
+    channel: u8,
+}
+
+impl ProvisioningPortal {
+    /// `ap_ssid` という名前のアクセスポイントでポータルを開くよう設定します。
+    pub fn new(ap_ssid: &str) -> Self {
+        Self {
+            ap_ssid: ap_ssid.to_string(),
+            channel: 1,
+        }
+    }
+
+    /// アクセスポイントのチャンネルを設定します (デフォルトは 1)。
+    pub fn with_channel(mut self, channel: u8) -> Self {
+        self.channel = channel;
+        self
+    }
+
+    /// アクセスポイントの SSID
+    pub fn ap_ssid(&self) -> &str {
+        &self.ap_ssid
+    }
+
+    /// スマートフォンのカメラで読み取るとアクセスポイントに接続できる QR コードの文字列
+    pub fn join_qr_text(&self) -> String {
+        format!("WIFI:S:{};T:nopass;;", escape_wifi_qr(&self.ap_ssid))
+    }
+
+    /// ポータルを開き、接続先が送信されるまで待ちます。
+    ///
+    /// 受け取った接続先は `store` に保存してから返します。
+    pub fn run(
+        &self,
+        wifi: &mut BlockingWifi<EspWifi<'static>>,
+        store: &mut CredentialStore,
+    ) -> anyhow::Result<Credentials> {
+        // スキャンできるよう、ステーションとアクセスポイントを同時に動かす
+        if wifi.is_started()? {
+            wifi.stop()?;
+        }
+        wifi.set_configuration(&Configuration::Mixed(
+            ClientConfiguration::default(),
+            AccessPointConfiguration {
+                ssid: self
+                    .ap_ssid
+                    .as_str()
+                    .try_into()
+                    .map_err(|_| anyhow::anyhow!("AP SSID is too long: {}", self.ap_ssid))?,
+                auth_method: AuthMethod::None,
+                channel: self.channel,
+                ..Default::default()
+            },
+        ))?;
+        wifi.start()?;
+
+        let networks = scan_networks(wifi);
+        log::info!("Found {} networks", networks.len());
+
+        let ip = wifi.wifi().ap_netif().get_ip_info()?.ip;
+        log::info!("Provisioning portal: connect to \"{}\" and open http://{}/", self.ap_ssid, ip);
+
+        let stop = Arc::new(AtomicBool::new(false));
+        let dns = spawn_dns_server(ip, stop.clone())?;
+
+        let (sender, receiver) = mpsc::channel();
+        let server = start_http_server(ip, networks, sender)?;
+
+        let credentials = receiver.recv()?;
+        store.set(&credentials)?;
+
+        // 完了のページを返し終わるまで待ってから止める
+        thread::sleep(Duration::from_secs(1));
+        drop(server);
+        stop.store(true, Ordering::Relaxed);
+        let _ = dns.join();
+        Ok(credentials)
+    }
+}
+
+/// 周囲のネットワークを受信強度の強い順に返します。同じ SSID は最も強いものだけ残します。
+fn scan_networks(wifi: &mut BlockingWifi<EspWifi<'static>>) -> Vec<Network> {
+    let found = match wifi.scan() {
+        Ok(found) => found,
+        Err(e) => {
+            log::warn!("Wi-Fi scan failed: {:?}", e);
+            return Vec::new();
+        }
+    };
+    let mut networks: Vec<Network> = Vec::new();
+    for ap in found {
+        if ap.ssid.is_empty() {
+            continue;
+        }
+        let secured = !matches!(ap.auth_method, None | Some(AuthMethod::None));
+        match networks.iter_mut().find(|n| n.ssid == ap.ssid.as_str()) {
+            Some(existing) if existing.rssi >= ap.signal_strength => {}
+            Some(existing) => existing.rssi = ap.signal_strength,
+            None => networks.push(Network {
+                ssid: ap.ssid.to_string(),
+                rssi: ap.signal_strength,
+                secured,
+            }),
+        }
+    }
+    networks.sort_by(|a, b| b.rssi.cmp(&a.rssi));
+    networks
+}
+
+fn start_http_server(
+    ip: Ipv4Addr,
+    networks: Vec<Network>,
+    sender: mpsc::Sender<Credentials>,
+) -> anyhow::Result<EspHttpServer<'static>> {
+    let mut server = EspHttpServer::new(&HttpConfiguration {
+        uri_match_wildcard: true,
+        ..Default::default()
+    })?;
+
+    let form = form_page(&networks);
+    server.fn_handler("/", Method::Get, move |req| -> anyhow::Result<()> {
+        req.into_response(200, Some("OK"), &[("Content-Type", "text/html; charset=utf-8")])?
+            .write_all(form.as_bytes())?;
+        Ok(())
+    })?;
+
+    server.fn_handler("/save", Method::Post, move |mut req| -> anyhow::Result<()> {
+        let len = req.content_len().unwrap_or(0) as usize;
+        if len > MAX_FORM_LEN {
+            req.into_status_response(413)?.write_all(b"Request too large")?;
+            return Ok(());
+        }
+        let mut body = vec![0; len];
+        let mut read = 0;
+        while read < len {
+            let n = req.read(&mut body[read..])?;
+            if n == 0 {
+                break;
+            }
+            read += n;
+        }
+        body.truncate(read);
+
+        let page = match form_credentials(&String::from_utf8_lossy(&body)) {
+            Ok(credentials) => {
+                log::info!("Received credentials for {}", credentials.ssid);
+                let page = message_page(&format!(
+                    "「{}」を保存しました。再起動して接続します。",
+                    html_escape(&credentials.ssid)
+                ));
+                sender.send(credentials)?;
+                page
+            }
+            Err(e) => message_page(&format!("入力が正しくありません: {}", html_escape(&e.to_string()))),
+        };
+        req.into_response(200, Some("OK"), &[("Content-Type", "text/html; charset=utf-8")])?
+            .write_all(page.as_bytes())?;
+        Ok(())
+    })?;
+
+    // OS の接続確認 (/generate_204, /hotspot-detect.html など) をすべてフォームに転送し、
+    // ログイン画面としてポータルを開かせる
+    let location = format!("http://{}/", ip);
+    server.fn_handler("/*", Method::Get, move |req| -> anyhow::Result<()> {
+        req.into_response(302, Some("Found"), &[("Location", location.as_str())])?;
+        Ok(())
+    })?;
+
+    Ok(server)
+}
+
+/// すべての名前の問い合わせに `ip` を返す DNS サーバーを起動します。`stop` で止まります。
+fn spawn_dns_server(ip: Ipv4Addr, stop: Arc<AtomicBool>) -> anyhow::Result<thread::JoinHandle<()>> {
+    let socket = UdpSocket::bind(("0.0.0.0", 53))?;
+    socket.set_read_timeout(Some(Duration::from_secs(1)))?;
+    let handle = thread::Builder::new()
+        .name("dns".to_string())
+        .stack_size(DNS_STACK_SIZE)
+        .spawn(move || {
+            let mut buffer = [0u8; 512];
+            while !stop.load(Ordering::Relaxed) {
+                let Ok((len, peer)) = socket.recv_from(&mut buffer) else {
+                    continue;
+                };
+                if let Some(response) = dns_response(&buffer[..len], ip) {
+                    if let Err(e) = socket.send_to(&response, peer) {
+                        log::warn!("DNS response failed: {}", e);
+                    }
+                }
+            }
+        })?;
+    Ok(handle)
+}
+
+/// DNS の問い合わせに対する応答を作成します。
+///
+/// A レコードの問い合わせには `ip` を返し、それ以外 (AAAA など) には回答なしで応答します。
+/// 問い合わせでないものや形式が正しくないものには `None` を返します。
+pub fn dns_response(query: &[u8], ip: Ipv4Addr) -> Option<Vec<u8>> {
+    // ヘッダー 12 バイト: ID, フラグ, QDCOUNT, ANCOUNT, NSCOUNT, ARCOUNT
+    if query.len() < 12 || query[2] & 0x80 != 0 || u16::from_be_bytes([query[4], query[5]]) != 1 {
+        return None;
+    }
+    // 質問の名前 (長さ付きのラベルの列) を読み飛ばす
+    let mut end = 12;
+    loop {
+        let len = *query.get(end)? as usize;
+        end += 1;
+        if len == 0 {
+            break;
+        }
+        if len & 0xC0 != 0 {
+            return None;
+        }
+        end += len;
+    }
+    let question = query.get(12..end + 4)?;
+    let qtype = u16::from_be_bytes([question[question.len() - 4], question[question.len() - 3]]);
+    let answer = qtype == 1;
+
+    let mut response = Vec::with_capacity(12 + question.len() + 16);
+    response.extend_from_slice(&query[0..2]);
+    // 応答、再帰可能、エラーなし (問い合わせの RD ビットはそのまま返す)
+    response.extend_from_slice(&[0x80 | (query[2] & 0x01), 0x80]);
+    response.extend_from_slice(&[0, 1, 0, answer as u8, 0, 0, 0, 0]);
+    response.extend_from_slice(question);
+    if answer {
+        response.extend_from_slice(&[
+            0xC0, 0x0C, // 名前は質問の名前を参照
+            0, 1, // A
+            0, 1, // IN
+            0, 0, 0, 60, // TTL 60 秒
+            0, 4,
+        ]);
+        response.extend_from_slice(&ip.octets());
+    }
+    Some(response)
+}
+
+/// フォームの本文から接続先を作成します。
+///
+/// 一覧から選んだ `ssid` より直接入力した `ssid_manual` を優先します。
+/// `password` がなければオープンなネットワークとして扱います。
+fn form_credentials(body: &str) -> Result<Credentials, InvalidCredentials> {
+    let fields = parse_form(body);
+    let field = |name: &str| {
+        fields
+            .iter()
+            .find(|(key, _)| key == name)
+            .map(|(_, value)| value.as_str())
+            .unwrap_or("")
+    };
+    // 一覧から選ぶか、直接入力された SSID を使う
+    let ssid = match field("ssid_manual").trim() {
+        "" => field("ssid"),
+        manual => manual,
+    };
+    Credentials::new(ssid, field("password"))
+}
+
+/// `application/x-www-form-urlencoded` の本文を (名前, 値) の列にします。
+pub fn parse_form(body: &str) -> Vec<(String, String)> {
+    body.split('&')
+        .filter(|pair| !pair.is_empty())
+        .map(|pair| {
+            let (key, value) = pair.split_once('=').unwrap_or((pair, ""));
+            (url_decode(key), url_decode(value))
+        })
+        .collect()
+}
+
+fn url_decode(text: &str) -> String {
+    let bytes = text.as_bytes();
+    let mut decoded = Vec::with_capacity(bytes.len());
+    let mut i = 0;
+    while i < bytes.len() {
+        match bytes[i] {
+            b'+' => decoded.push(b' '),
+            b'%' if i + 2 < bytes.len() => match hex_byte(bytes[i + 1], bytes[i + 2]) {
+                Some(byte) => {
+                    decoded.push(byte);
+                    i += 2;
+                }
+                None => decoded.push(b'%'),
+            },
+            byte => decoded.push(byte),
+        }
+        i += 1;
+    }
+    String::from_utf8_lossy(&decoded).into_owned()
+}
+
+fn hex_byte(high: u8, low: u8) -> Option<u8> {
+    let digit = |c: u8| (c as char).to_digit(16);
+    Some((digit(high)? * 16 + digit(low)?) as u8)
+}
+
+fn html_escape(text: &str) -> String {
+    let mut escaped = String::with_capacity(text.len());
+    for ch in text.chars() {
+        match ch {
+            '&' => escaped.push_str("&amp;"),
+            '<' => escaped.push_str("&lt;"),
+            '>' => escaped.push_str("&gt;"),
+            '"' => escaped.push_str("&quot;"),
+            '\'' => escaped.push_str("&#39;"),
+            ch => escaped.push(ch),
+        }
+    }
+    escaped
+}
+
+/// Wi-Fi の QR コードの文字列で特別な意味を持つ文字をエスケープします。
+fn escape_wifi_qr(text: &str) -> String {
+    let mut escaped = String::with_capacity(text.len());
+    for ch in text.chars() {
+        if matches!(ch, '\\' | ';' | ',' | ':' | '"') {
+            escaped.push('\\');
+        }
+        escaped.push(ch);
+    }
+    escaped
+}
+
+const PAGE_HEAD: &str = "<!DOCTYPE html><html lang=\"ja\"><head><meta charset=\"utf-8\">\
+<meta name=\"viewport\" content=\"width=device-width,initial-scale=1\"><title>Wi-Fi 設定</title>\
+<style>body{font-family:sans-serif;margin:1.5em;max-width:28em}label{display:block;margin-top:1em}\
+input,select,button{width:100%;padding:.5em;margin-top:.3em;box-sizing:border-box}</style></head><body>";
+
+/// 接続先を入力するページ
+fn form_page(networks: &[Network]) -> String {
+    let mut page = String::from(PAGE_HEAD);
+    page.push_str("<h1>Wi-Fi 設定</h1><form method=\"post\" action=\"/save\"><label>ネットワーク<select name=\"ssid\">");
+    for network in networks {
+        let ssid = html_escape(&network.ssid);
+        write!(
+            page,
+            "<option value=\"{}\">{} ({} dBm{})</option>",
+            ssid,
+            ssid,
+            network.rssi,
+            if network.secured { ", 🔒" } else { "" }
+        )
+        .unwrap();
+    }
+    page.push_str(
+        "</select></label>\
+<label>SSID を直接入力 (一覧にない場合)<input name=\"ssid_manual\" maxlength=\"32\"></label>\
+<label>パスワード<input name=\"password\" type=\"password\" maxlength=\"64\"></label>\
+<button type=\"submit\">保存して接続</button></form></body></html>",
+    );
+    page
+}
+
+/// メッセージだけのページ
+fn message_page(message: &str) -> String {
+    format!("{}<h1>Wi-Fi 設定</h1><p>{}</p><p><a href=\"/\">戻る</a></p></body></html>", PAGE_HEAD, message)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const IP: Ipv4Addr = Ipv4Addr::new(192, 168, 71, 1);
+
+    /// `example.com` の問い合わせ (ID 0x1234, RD ビットあり)
+    fn query(qtype: u16) -> Vec<u8> {
+        let mut query = vec![0x12, 0x34, 0x01, 0x00, 0, 1, 0, 0, 0, 0, 0, 0];
+        query.extend_from_slice(b"\x07example\x03com\x00");
+        query.extend_from_slice(&qtype.to_be_bytes());
+        query.extend_from_slice(&[0, 1]);
+        query
+    }
+
+    #[test]
+    fn answers_a_query_with_portal_address() {
+        let query = query(1);
+        let response = dns_response(&query, IP).unwrap();
+        assert_eq!(&response[0..4], &[0x12, 0x34, 0x81, 0x80]);
+        assert_eq!(&response[4..12], &[0, 1, 0, 1, 0, 0, 0, 0]);
+        assert_eq!(&response[12..query.len()], &query[12..]);
+        assert_eq!(response.len(), query.len() + 16);
+        assert_eq!(&response[response.len() - 4..], &IP.octets());
+    }
+
+    #[test]
+    fn answers_other_types_without_records() {
+        let query = query(28);
+        let response = dns_response(&query, IP).unwrap();
+        assert_eq!(&response[4..12], &[0, 1, 0, 0, 0, 0, 0, 0]);
+        assert_eq!(response.len(), query.len());
+    }
+
+    #[test]
+    fn ignores_records_after_the_question() {
+        // EDNS の OPT レコード (ARCOUNT 1) は応答に含めない
+        let mut query = query(1);
+        query[11] = 1;
+        let question_end = query.len();
+        query.extend_from_slice(&[0, 0, 41, 0x10, 0, 0, 0, 0, 0, 0, 0]);
+        let response = dns_response(&query, IP).unwrap();
+        assert_eq!(&response[10..12], &[0, 0]);
+        assert_eq!(response.len(), question_end + 16);
+    }
+
+    #[test]
+    fn rejects_truncated_queries() {
+        let query = query(1);
+        assert_eq!(dns_response(&query[..11], IP), None);
+        // 名前の途中で終わっている
+        assert_eq!(dns_response(&query[..16], IP), None);
+        // 種類とクラスがない
+        assert_eq!(dns_response(&query[..query.len() - 1], IP), None);
+        assert_eq!(dns_response(&[], IP), None);
+    }
+
+    #[test]
+    fn rejects_invalid_queries() {
+        // 応答
+        let mut response = query(1);
+        response[2] |= 0x80;
+        assert_eq!(dns_response(&response, IP), None);
+        // 質問が2つ
+        let mut two_questions = query(1);
+        two_questions[5] = 2;
+        assert_eq!(dns_response(&two_questions, IP), None);
+        // ラベルが 63 バイトを超えている (圧縮ポインタ)
+        let mut oversized_label = query(1);
+        oversized_label[12] = 0xC0;
+        assert_eq!(dns_response(&oversized_label, IP), None);
+        // ラベルの長さがパケットより長い
+        let mut oversized = query(1);
+        oversized[12] = 0x3F;
+        assert_eq!(dns_response(&oversized, IP), None);
+    }
+
+    #[test]
+    fn decodes_form_values() {
+        assert_eq!(
+            parse_form("ssid=My+Home&password=p%40ss%26word%3D1"),
+            vec![
+                ("ssid".to_string(), "My Home".to_string()),
+                ("password".to_string(), "p@ss&word=1".to_string()),
+            ]
+        );
+        assert_eq!(parse_form("ssid=%E5%AE%B6"), vec![("ssid".to_string(), "家".to_string())]);
+        assert_eq!(parse_form("a&&b="), vec![("a".to_string(), String::new()), ("b".to_string(), String::new())]);
+    }
+
+    #[test]
+    fn keeps_invalid_percent_sequences() {
+        assert_eq!(url_decode("100%"), "100%");
+        assert_eq!(url_decode("%4"), "%4");
+        assert_eq!(url_decode("%zz%41"), "%zzA");
+        assert_eq!(url_decode("%%41"), "%A");
+        // UTF-8 として正しくないバイトは置き換える
+        assert_eq!(url_decode("%FF"), "\u{FFFD}");
+    }
+
+    #[test]
+    fn reads_credentials_from_form() {
+        let credentials = form_credentials("ssid=home&ssid_manual=&password=secret").unwrap();
+        assert_eq!((credentials.ssid.as_str(), credentials.password.as_str()), ("home", "secret"));
+        // 直接入力した SSID を優先する
+        let credentials = form_credentials("ssid=home&ssid_manual=+office+&password=secret").unwrap();
+        assert_eq!(credentials.ssid, "office");
+    }
+
+    #[test]
+    fn handles_missing_form_fields() {
+        // パスワードがなければオープンなネットワーク
+        let credentials = form_credentials("ssid=guest").unwrap();
+        assert_eq!(credentials.password, "");
+        assert_eq!(form_credentials("password=secret"), Err(InvalidCredentials::Ssid));
+        assert_eq!(form_credentials(""), Err(InvalidCredentials::Ssid));
+        assert_eq!(
+            form_credentials(&format!("ssid=home&password={}", "a".repeat(65))),
+            Err(InvalidCredentials::Password)
+        );
+    }
+}
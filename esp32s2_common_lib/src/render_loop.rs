@@ -0,0 +1,231 @@
+//! 一定のフレームレートで描画ループを回すためのタイマー
+//!
+//! 描画と `flush` にかかった時間を差し引いて次のフレームまで待つので、
+//! SPI の転送量が変わってもフレームの間隔が一定になります。
+//! 間に合わなかったフレームは詰めて描画せずに飛ばし (ドロップ)、統計に記録します。
+//!
+//! スリープの精度は FreeRTOS のティック (`CONFIG_FREERTOS_HZ`) に依存します。
+//! デフォルトの 100Hz では 10ms 単位になるため、sdkconfig.defaults で 1000Hz にしておくと正確です。
+//!
+//! ## 使用例
+//!
+//! ```no_run
+//! use esp32s2_common_lib::render_loop::{FpsOverlay, FrameTimer};
+//!
+//! let mut timer = FrameTimer::new(30);
+//! loop {
+//!     let dt = timer.begin_frame();
+//!     // display.clear();
+//!     // dt (秒) を使ってシミュレーションを進め、描画する
+//!     // FpsOverlay::new(timer.stats()).draw(&mut display)?;
+//!     // timer.flush(&mut display)?;
+//!     timer.end_frame();
+//! }
+//! ```
+
+use std::thread;
+use std::time::{Duration, Instant};
+
+use embedded_graphics::{
+    mono_font::{ascii::FONT_4X6, MonoTextStyle},
+    pixelcolor::BinaryColor,
+    prelude::*,
+    primitives::Rectangle,
+    text::{Baseline, Text},
+};
+use crate::mono_display::MonoDisplay;
+
+/// 統計を更新する間隔
+const STATS_WINDOW: Duration = Duration::from_secs(1);
+
+/// フレームの統計 (`STATS_WINDOW` ごとに更新)
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+pub struct FrameStats {
+    /// 実際のフレームレート
+    pub fps: f32,
+    /// 1フレームの処理時間の平均 (描画と flush を含み、待ち時間を含まない)
+    pub frame_time: Duration,
+    /// `flush` 1回あたりにかかった時間の平均
+    pub flush_time: Duration,
+    /// 間に合わずに飛ばしたフレーム数 (累計)
+    pub dropped: u32,
+    /// 描画したフレーム数 (累計)
+    pub frames: u64,
+}
+
+/// 目標のフレームレートに合わせて待ち時間を調整するタイマー
+pub struct FrameTimer {
+    frame_duration: Duration,
+    /// 次のフレームを始める時刻
+    next_frame: Instant,
+    frame_started: Instant,
+    last_frame: Option<Instant>,
+    window_started: Instant,
+    window_frames: u32,
+    window_work: Duration,
+    window_flush: Duration,
+    window_flushes: u32,
+    stats: FrameStats,
+}
+
+impl FrameTimer {
+    /// `fps` フレーム/秒を目標にするタイマーを作成します。
+    pub fn new(fps: u32) -> Self {
+        let now = Instant::now();
+        Self {
+            frame_duration: frame_duration(fps),
+            next_frame: now,
+            frame_started: now,
+            last_frame: None,
+            window_started: now,
+            window_frames: 0,
+            window_work: Duration::ZERO,
+            window_flush: Duration::ZERO,
+            window_flushes: 0,
+            stats: FrameStats::default(),
+        }
+    }
+
+    /// 目標のフレームレートを変更します。
+    pub fn set_target_fps(&mut self, fps: u32) {
+        self.frame_duration = frame_duration(fps);
+    }
+
+    /// 目標のフレームレート
+    pub fn target_fps(&self) -> f32 {
+        1.0 / self.frame_duration.as_secs_f32()
+    }
+
+    /// 1フレームの目標時間
+    pub fn frame_duration(&self) -> Duration {
+        self.frame_duration
+    }
+
+    /// フレームの処理を始めます。前のフレームからの経過時間 (秒) を返します。
+    ///
+    /// 最初のフレームでは目標の1フレーム分の時間を返します。
+    pub fn begin_frame(&mut self) -> f32 {
+        let now = Instant::now();
+        let dt = match self.last_frame {
+            Some(last) => now.saturating_duration_since(last),
+            None => self.frame_duration,
+        };
+        self.last_frame = Some(now);
+        self.frame_started = now;
+        dt.as_secs_f32()
+    }
+
+    /// ディスプレイに送信し、かかった時間を記録します。
+    pub fn flush<D>(&mut self, display: &mut D) -> Result<(), D::InterfaceError>
+    where
+        D: MonoDisplay,
+    {
+        let started = Instant::now();
+        let result = display.flush();
+        self.window_flush += started.elapsed();
+        self.window_flushes += 1;
+        result
+    }
+
+    /// フレームの処理を終え、次のフレームの時刻まで待ちます。
+    pub fn end_frame(&mut self) {
+        let now = Instant::now();
+        self.window_work += now.saturating_duration_since(self.frame_started);
+        self.window_frames += 1;
+        self.stats.frames += 1;
+
+        self.next_frame += self.frame_duration;
+        if now < self.next_frame {
+            thread::sleep(self.next_frame - now);
+        } else {
+            // 間に合わなかった分のフレームは飛ばし、今から次のフレームを数え直す
+            let late = now - self.next_frame;
+            let skipped = (late.as_nanos() / self.frame_duration.as_nanos().max(1)) as u32;
+            self.stats.dropped += skipped;
+            self.next_frame = now;
+        }
+
+        self.update_stats();
+    }
+
+    /// 直近の統計
+    pub fn stats(&self) -> &FrameStats {
+        &self.stats
+    }
+
+    fn update_stats(&mut self) {
+        let elapsed = self.window_started.elapsed();
+        if elapsed < STATS_WINDOW || self.window_frames == 0 {
+            return;
+        }
+        self.stats.fps = self.window_frames as f32 / elapsed.as_secs_f32();
+        self.stats.frame_time = self.window_work / self.window_frames;
+        self.stats.flush_time = self.window_flush / self.window_flushes.max(1);
+
+        self.window_started = Instant::now();
+        self.window_frames = 0;
+        self.window_work = Duration::ZERO;
+        self.window_flush = Duration::ZERO;
+        self.window_flushes = 0;
+    }
+}
+
+fn frame_duration(fps: u32) -> Duration {
+    Duration::from_secs(1) / fps.max(1)
+}
+
+/// 画面の隅に表示するフレームレートと処理時間
+///
+/// 例: `30fps 12ms`。背景を消去してから描画します。
+pub struct FpsOverlay<'a> {
+    stats: &'a FrameStats,
+    position: Option<Point>,
+}
+
+impl<'a> FpsOverlay<'a> {
+    /// 画面の右上に表示するオーバーレイを作成します。
+    pub fn new(stats: &'a FrameStats) -> Self {
+        Self { stats, position: None }
+    }
+
+    /// 左上の位置を指定します。
+    pub fn with_position(mut self, position: Point) -> Self {
+        self.position = Some(position);
+        self
+    }
+}
+
+impl Drawable for FpsOverlay<'_> {
+    type Color = BinaryColor;
+    type Output = ();
+
+    fn draw<D>(&self, target: &mut D) -> Result<(), D::Error>
+    where
+        D: DrawTarget<Color = BinaryColor>,
+    {
+        let text = format!(
+            "{:.0}fps {}ms",
+            self.stats.fps,
+            self.stats.frame_time.as_millis()
+        );
+        let font = &FONT_4X6;
+        let size = Size::new(
+            text.len() as u32 * (font.character_size.width + font.character_spacing) + 1,
+            font.character_size.height + 1,
+        );
+        let position = self.position.unwrap_or_else(|| {
+            let screen = target.bounding_box();
+            Point::new(screen.top_left.x + screen.size.width as i32 - size.width as i32, screen.top_left.y)
+        });
+
+        target.fill_solid(&Rectangle::new(position, size), BinaryColor::Off)?;
+        Text::with_baseline(
+            &text,
+            position + Point::new(1, 1),
+            MonoTextStyle::new(font, BinaryColor::On),
+            Baseline::Top,
+        )
+        .draw(target)?;
+        Ok(())
+    }
+}
@@ -0,0 +1,168 @@
+//! 一定時間操作がないときに画面を暗くし、さらに消灯するためのタイマー
+//!
+//! OLED は同じ内容を表示し続けると焼き付くため、時計のように常時表示する
+//! アプリケーションでは一定時間後に暗く (dim) し、さらに時間が経つと消灯します。
+//! 加速度センサーで動きを検知したらタイマーをリセットして通常表示に戻します。
+//!
+//! ## 使用例
+//!
+//! ```no_run
+//! use std::time::Duration;
+//! use esp32s2_common_lib::screensaver::Screensaver;
+//!
+//! let mut saver = Screensaver::new(Duration::from_secs(30), Duration::from_secs(120));
+//! loop {
+//!     // if let Ok(accel) = sensor.get_acceleration() { saver.feed_acceleration(accel); }
+//!     if let Some(state) = saver.update() {
+//!         // saver.apply(&mut display)?;
+//!     }
+//! }
+//! ```
+
+use std::time::{Duration, Instant};
+
+use crate::buffered_display::DEFAULT_CONTRAST;
+use crate::mma7660fc::Acceleration;
+use crate::mono_display::MonoDisplay;
+
+/// 動きとみなす加速度の変化量のデフォルト値 (各軸の差の絶対値の合計)
+///
+/// MMA7660FC は 1 カウントが約 0.047g
+pub const DEFAULT_ACTIVITY_THRESHOLD: u8 = 3;
+
+/// 画面の電源状態
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum PowerState {
+    /// 通常の明るさで表示
+    Normal,
+    /// 暗く表示
+    Dimmed,
+    /// 消灯
+    Off,
+}
+
+/// 自動減光・消灯タイマー
+pub struct Screensaver {
+    dim_after: Duration,
+    off_after: Duration,
+    normal_contrast: u8,
+    dim_contrast: u8,
+    activity_threshold: u8,
+    last_activity: Instant,
+    last_accel: Option<Acceleration>,
+    state: PowerState,
+}
+
+impl Screensaver {
+    /// 最後の操作から `dim_after` で減光、`off_after` で消灯するタイマーを作成します。
+    pub fn new(dim_after: Duration, off_after: Duration) -> Self {
+        Self {
+            dim_after,
+            off_after: off_after.max(dim_after),
+            normal_contrast: DEFAULT_CONTRAST,
+            dim_contrast: 0x01,
+            activity_threshold: DEFAULT_ACTIVITY_THRESHOLD,
+            last_activity: Instant::now(),
+            last_accel: None,
+            state: PowerState::Normal,
+        }
+    }
+
+    /// 通常時と減光時のコントラストを設定します。
+    pub fn with_contrast(mut self, normal: u8, dimmed: u8) -> Self {
+        self.normal_contrast = normal;
+        self.dim_contrast = dimmed;
+        self
+    }
+
+    /// 通常時と減光時のコントラストを変更します。反映は次の `apply` で行われます。
+    pub fn set_contrast(&mut self, normal: u8, dimmed: u8) {
+        self.normal_contrast = normal;
+        self.dim_contrast = dimmed;
+    }
+
+    /// 減光・消灯までの時間を変更します。
+    pub fn set_timeouts(&mut self, dim_after: Duration, off_after: Duration) {
+        self.dim_after = dim_after;
+        self.off_after = off_after.max(dim_after);
+    }
+
+    /// 動きとみなす加速度の変化量を設定します。
+    pub fn with_activity_threshold(mut self, threshold: u8) -> Self {
+        self.activity_threshold = threshold;
+        self
+    }
+
+    /// 現在の状態
+    pub fn state(&self) -> PowerState {
+        self.state
+    }
+
+    /// 操作があったことを通知し、タイマーをリセットします。
+    pub fn reset(&mut self) {
+        self.last_activity = Instant::now();
+    }
+
+    /// 加速度センサーの値を渡し、前回からの変化が大きければタイマーをリセットします。
+    ///
+    /// 動きを検知した場合は `true` を返します。
+    pub fn feed_acceleration(&mut self, accel: Acceleration) -> bool {
+        let moved = match self.last_accel {
+            Some(last) => {
+                let delta = accel.x.abs_diff(last.x) as u16
+                    + accel.y.abs_diff(last.y) as u16
+                    + accel.z.abs_diff(last.z) as u16;
+                delta >= self.activity_threshold as u16
+            }
+            None => false,
+        };
+        self.last_accel = Some(accel);
+        if moved {
+            self.reset();
+        }
+        moved
+    }
+
+    /// 経過時間から状態を更新します。
+    ///
+    /// 状態が変わったときだけ新しい状態を返します。
+    pub fn update(&mut self) -> Option<PowerState> {
+        self.update_at(Instant::now())
+    }
+
+    /// `now` を現在時刻として状態を更新します。
+    pub fn update_at(&mut self, now: Instant) -> Option<PowerState> {
+        let idle = now.saturating_duration_since(self.last_activity);
+        let state = if idle >= self.off_after {
+            PowerState::Off
+        } else if idle >= self.dim_after {
+            PowerState::Dimmed
+        } else {
+            PowerState::Normal
+        };
+
+        if state == self.state {
+            return None;
+        }
+        self.state = state;
+        Some(state)
+    }
+
+    /// 現在の状態をディスプレイに反映します。
+    pub fn apply<D>(&self, display: &mut D) -> Result<(), D::InterfaceError>
+    where
+        D: MonoDisplay,
+    {
+        match self.state {
+            PowerState::Normal => {
+                display.set_contrast(self.normal_contrast)?;
+                display.set_display_on(true)
+            }
+            PowerState::Dimmed => {
+                display.set_contrast(self.dim_contrast)?;
+                display.set_display_on(true)
+            }
+            PowerState::Off => display.set_display_on(false),
+        }
+    }
+}
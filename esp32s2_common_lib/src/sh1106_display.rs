@@ -19,7 +19,21 @@ use esp_idf_hal::gpio::Output;
 use esp_idf_svc::hal::peripheral::Peripheral;
 use esp_idf_svc::hal::spi;
 
+use crate::buffered_display::{BufferedDisplay, Controller};
+
+/// ディスプレイを接続した SPI インターフェースの型
+///
+/// 4線式 SPI (D/C ピンあり) の送信方法は SH1106 と SSD1306 で同じなので、どちらのコントローラでも使う
+pub type DisplaySpiInterface<'d, DC, CS> = SpiInterface<
+    SpiDeviceDriver<'d, SpiDriver<'d>>,
+    // PinDriverにOutputモードを指定
+    PinDriver<'d, DC, Output>,
+    PinDriver<'d, CS, Output>
+>;
+
 /// 任意のGPIOピンとSPIペリフェラルを受け取り、ディスプレイの初期化を行う
+///
+/// `rotation` は初期化時の表示の向き。実行中は `GraphicsMode::set_rotation` で変更できる
 pub fn set_sh1106_display<'d, RST, DC, SCLK, SDA, CS, SPI>(
     rst_pin: RST,
     dc_pin: DC,
@@ -27,17 +41,112 @@ pub fn set_sh1106_display<'d, RST, DC, SCLK, SDA, CS, SPI>(
     sda_pin: SDA,
     cs_pin: CS,
     spi_peripheral: SPI,
+    rotation: DisplayRotation,
+) -> anyhow::Result<(
+    GraphicsMode<DisplaySpiInterface<'d, DC, CS>>,
+    PinDriver<'d, RST, Output> // rst_driverの型 (DropされてしまうとLCDがうまく表示されない)
+)>
+where
+    RST: Peripheral<P = RST> + OutputPin,
+    DC: Peripheral<P = DC> + OutputPin,
+    CS: Peripheral<P = CS> + OutputPin,
+    SCLK: Peripheral<P = SCLK> + OutputPin,
+    SDA: Peripheral<P = SDA> + OutputPin,
+    SPI: Peripheral<P = SPI> + spi::Spi + esp_idf_svc::hal::spi::SpiAnyPins + 'd,
+{
+    let (spi_device, dc_driver, cs_driver, rst_driver) = open_spi_with_reset(
+        rst_pin, dc_pin, sclk_pin, sda_pin, cs_pin, spi_peripheral
+    )?;
+
+    let mut display :GraphicsMode<_>= Builder::new()
+        .with_size(DisplaySize::Display128x64)
+        .with_rotation(rotation)
+        .connect_spi(spi_device, dc_driver, cs_driver)
+        .into();
+
+    display.init().map_err(|e| anyhow::anyhow!("Display init error: {:?}", e))?;
+    log::info!("OLED Initialized");
+
+    Ok((display, rst_driver))
+}
+
+/// `set_sh1106_display` と同じピン構成で、差分転送を行う `BufferedDisplay` を初期化する
+pub fn set_sh1106_buffered_display<'d, RST, DC, SCLK, SDA, CS, SPI>(
+    rst_pin: RST,
+    dc_pin: DC,
+    sclk_pin: SCLK,
+    sda_pin: SDA,
+    cs_pin: CS,
+    spi_peripheral: SPI,
+) -> anyhow::Result<(
+    BufferedDisplay<DisplaySpiInterface<'d, DC, CS>>,
+    PinDriver<'d, RST, Output> // rst_driverの型 (DropされてしまうとLCDがうまく表示されない)
+)>
+where
+    RST: Peripheral<P = RST> + OutputPin,
+    DC: Peripheral<P = DC> + OutputPin,
+    CS: Peripheral<P = CS> + OutputPin,
+    SCLK: Peripheral<P = SCLK> + OutputPin,
+    SDA: Peripheral<P = SDA> + OutputPin,
+    SPI: Peripheral<P = SPI> + spi::Spi + esp_idf_svc::hal::spi::SpiAnyPins + 'd,
+{
+    set_buffered_display(
+        Controller::Sh1106, rst_pin, dc_pin, sclk_pin, sda_pin, cs_pin, spi_peripheral
+    )
+}
+
+/// `controller` で指定したコントローラのディスプレイを、差分転送を行う `BufferedDisplay` として初期化する
+///
+/// ピン構成は `set_sh1106_display` と同じ。コントローラは `Controller::from_build_env` で
+/// ビルド時の設定から選べる
+pub fn set_buffered_display<'d, RST, DC, SCLK, SDA, CS, SPI>(
+    controller: Controller,
+    rst_pin: RST,
+    dc_pin: DC,
+    sclk_pin: SCLK,
+    sda_pin: SDA,
+    cs_pin: CS,
+    spi_peripheral: SPI,
 ) -> anyhow::Result<(
-    GraphicsMode<
-        SpiInterface<
-            SpiDeviceDriver<'d, SpiDriver<'d>>,
-            // PinDriverにOutputモードを指定
-            PinDriver<'d, DC, Output>,
-            PinDriver<'d, CS, Output>
-        >
-    >,
+    BufferedDisplay<DisplaySpiInterface<'d, DC, CS>>,
     PinDriver<'d, RST, Output> // rst_driverの型 (DropされてしまうとLCDがうまく表示されない)
 )>
+where
+    RST: Peripheral<P = RST> + OutputPin,
+    DC: Peripheral<P = DC> + OutputPin,
+    CS: Peripheral<P = CS> + OutputPin,
+    SCLK: Peripheral<P = SCLK> + OutputPin,
+    SDA: Peripheral<P = SDA> + OutputPin,
+    SPI: Peripheral<P = SPI> + spi::Spi + esp_idf_svc::hal::spi::SpiAnyPins + 'd,
+{
+    let (spi_device, dc_driver, cs_driver, rst_driver) = open_spi_with_reset(
+        rst_pin, dc_pin, sclk_pin, sda_pin, cs_pin, spi_peripheral
+    )?;
+
+    let mut display = BufferedDisplay::new(SpiInterface::new(spi_device, dc_driver, cs_driver))
+        .with_controller(controller);
+
+    display.init().map_err(|e| anyhow::anyhow!("Display init error: {:?}", e))?;
+    log::info!("OLED Initialized ({})", controller);
+
+    Ok((display, rst_driver))
+}
+
+/// SPIドライバと制御ピンを用意し、ディスプレイのハードウェアリセットを行う
+#[allow(clippy::type_complexity)]
+fn open_spi_with_reset<'d, RST, DC, SCLK, SDA, CS, SPI>(
+    rst_pin: RST,
+    dc_pin: DC,
+    sclk_pin: SCLK,
+    sda_pin: SDA,
+    cs_pin: CS,
+    spi_peripheral: SPI,
+) -> anyhow::Result<(
+    SpiDeviceDriver<'d, SpiDriver<'d>>,
+    PinDriver<'d, DC, Output>,
+    PinDriver<'d, CS, Output>,
+    PinDriver<'d, RST, Output>,
+)>
 where
     RST: Peripheral<P = RST> + OutputPin,
     DC: Peripheral<P = DC> + OutputPin,
@@ -72,14 +181,5 @@ where
     rst_driver.set_high()?;
     FreeRtos::delay_ms(50);
 
-    let mut display :GraphicsMode<_>= Builder::new()
-        .with_size(DisplaySize::Display128x64)
-        .with_rotation(DisplayRotation::Rotate0)
-        .connect_spi(spi_device, dc_driver, cs_driver)
-        .into();
-
-    display.init().map_err(|e| anyhow::anyhow!("Display init error: {:?}", e))?;
-    log::info!("OLED Initialized");
-
-    Ok((display, rst_driver))
+    Ok((spi_device, dc_driver, cs_driver, rst_driver))
 }
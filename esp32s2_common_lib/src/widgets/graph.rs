@@ -0,0 +1,148 @@
+//! 自動スケーリング付きの折れ線グラフ・棒グラフ
+
+use embedded_graphics::{
+    pixelcolor::BinaryColor,
+    prelude::*,
+    primitives::{Line, PrimitiveStyle, Rectangle},
+};
+
+/// グラフの種類
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum GraphKind {
+    /// 折れ線グラフ
+    Line,
+    /// 棒グラフ
+    Bar,
+}
+
+/// データの最小値と最大値を返します。
+///
+/// すべて同じ値の場合でも描画できるように、範囲に幅を持たせます。
+/// データが空の場合は `(0.0, 1.0)` を返します。
+pub fn auto_range(data: &[f32]) -> (f32, f32) {
+    let (min, max) = data
+        .iter()
+        .filter(|v| v.is_finite())
+        .fold((f32::MAX, f32::MIN), |(min, max), &v| (min.min(v), max.max(v)));
+    if min > max {
+        (0.0, 1.0)
+    } else if min == max {
+        (min - 1.0, max + 1.0)
+    } else {
+        (min, max)
+    }
+}
+
+/// 値を `area` 内の y 座標に変換します。`max` が上端、`min` が下端になります。
+pub(crate) fn value_to_y(value: f32, (min, max): (f32, f32), area: &Rectangle) -> i32 {
+    let bottom = area.top_left.y + area.size.height as i32 - 1;
+    let fraction = ((value - min) / (max - min)).clamp(0.0, 1.0);
+    bottom - (fraction * (area.size.height as f32 - 1.0)).round() as i32
+}
+
+/// 枠付きのグラフ
+///
+/// 範囲を指定しない場合はデータの最小値・最大値に合わせて自動でスケーリングします。
+pub struct Graph<'a> {
+    bounds: Rectangle,
+    data: &'a [f32],
+    kind: GraphKind,
+    range: Option<(f32, f32)>,
+}
+
+impl<'a> Graph<'a> {
+    pub fn new(bounds: Rectangle, data: &'a [f32], kind: GraphKind) -> Self {
+        Self {
+            bounds,
+            data,
+            kind,
+            range: None,
+        }
+    }
+
+    /// 縦軸の範囲を固定します。
+    pub fn with_range(mut self, min: f32, max: f32) -> Self {
+        self.range = Some((min, max));
+        self
+    }
+
+    /// 描画に使う縦軸の範囲
+    pub fn range(&self) -> (f32, f32) {
+        match self.range {
+            Some((min, max)) if max > min => (min, max),
+            _ => auto_range(self.data),
+        }
+    }
+
+    /// 枠の内側の描画領域
+    fn plot_area(&self) -> Rectangle {
+        self.bounds.offset(-1)
+    }
+}
+
+impl Drawable for Graph<'_> {
+    type Color = BinaryColor;
+    type Output = ();
+
+    fn draw<D>(&self, target: &mut D) -> Result<(), D::Error>
+    where
+        D: DrawTarget<Color = BinaryColor>,
+    {
+        self.bounds
+            .into_styled(PrimitiveStyle::with_stroke(BinaryColor::On, 1))
+            .draw(target)?;
+
+        let area = self.plot_area();
+        if self.data.is_empty() || area.is_zero_sized() {
+            return Ok(());
+        }
+
+        let range = self.range();
+        let width = area.size.width as usize;
+        // 横幅より多いデータは新しいものだけを表示する
+        let data = &self.data[self.data.len().saturating_sub(width)..];
+        let style = PrimitiveStyle::with_stroke(BinaryColor::On, 1);
+
+        match self.kind {
+            GraphKind::Line => {
+                let step = if data.len() > 1 {
+                    (width - 1) as f32 / (data.len() - 1) as f32
+                } else {
+                    0.0
+                };
+                let points = data.iter().enumerate().map(|(i, &v)| {
+                    Point::new(
+                        area.top_left.x + (i as f32 * step).round() as i32,
+                        value_to_y(v, range, &area),
+                    )
+                });
+                let mut previous: Option<Point> = None;
+                for point in points {
+                    match previous {
+                        Some(p) => Line::new(p, point).into_styled(style).draw(target)?,
+                        None => Pixel(point, BinaryColor::On).draw(target)?,
+                    }
+                    previous = Some(point);
+                }
+            }
+            GraphKind::Bar => {
+                let bar_width = (width / data.len()).max(1) as u32;
+                // 0 が範囲内にあれば 0 を基準に、なければ下端を基準にする
+                let baseline = value_to_y(0.0f32.clamp(range.0, range.1), range, &area);
+                for (i, &v) in data.iter().enumerate() {
+                    let x = area.top_left.x + (i as u32 * bar_width) as i32;
+                    let y = value_to_y(v, range, &area);
+                    let top = y.min(baseline);
+                    let height = (y - baseline).unsigned_abs() + 1;
+                    // 棒と棒の間に 1 ピクセルの隙間を空ける
+                    let w = if bar_width > 2 { bar_width - 1 } else { bar_width };
+                    target.fill_solid(
+                        &Rectangle::new(Point::new(x, top), Size::new(w, height)),
+                        BinaryColor::On,
+                    )?;
+                }
+            }
+        }
+        Ok(())
+    }
+}
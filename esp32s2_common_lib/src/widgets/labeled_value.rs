@@ -0,0 +1,69 @@
+//! ラベルと値を1行に並べて表示するウィジェット
+
+use embedded_graphics::{
+    mono_font::{ascii::FONT_5X7, MonoFont, MonoTextStyle},
+    pixelcolor::BinaryColor,
+    prelude::*,
+    text::{Alignment, Baseline, Text, TextStyleBuilder},
+};
+
+/// 左寄せのラベルと右寄せの値
+///
+/// 例: `TEMP        23.5C`
+pub struct LabeledValue<'a> {
+    label: &'a str,
+    value: &'a str,
+    position: Point,
+    width: u32,
+    font: &'a MonoFont<'a>,
+}
+
+impl<'a> LabeledValue<'a> {
+    /// `position` を左上とし、幅 `width` の中に `label` と `value` を並べます。
+    pub fn new(label: &'a str, value: &'a str, position: Point, width: u32) -> Self {
+        Self {
+            label,
+            value,
+            position,
+            width,
+            font: &FONT_5X7,
+        }
+    }
+
+    /// 描画に使うフォントを変更します。
+    pub fn with_font(mut self, font: &'a MonoFont<'a>) -> Self {
+        self.font = font;
+        self
+    }
+
+    /// 1行の高さ
+    pub fn height(&self) -> u32 {
+        self.font.character_size.height
+    }
+}
+
+impl Drawable for LabeledValue<'_> {
+    type Color = BinaryColor;
+    type Output = ();
+
+    fn draw<D>(&self, target: &mut D) -> Result<(), D::Error>
+    where
+        D: DrawTarget<Color = BinaryColor>,
+    {
+        let style = MonoTextStyle::new(self.font, BinaryColor::On);
+        Text::with_baseline(self.label, self.position, style, Baseline::Top).draw(target)?;
+
+        let right = self.position + Point::new(self.width as i32 - 1, 0);
+        Text::with_text_style(
+            self.value,
+            right,
+            style,
+            TextStyleBuilder::new()
+                .alignment(Alignment::Right)
+                .baseline(Baseline::Top)
+                .build(),
+        )
+        .draw(target)?;
+        Ok(())
+    }
+}
@@ -0,0 +1,100 @@
+//! 画面中央に重ねて表示するメッセージボックス
+
+use embedded_graphics::{
+    mono_font::{ascii::FONT_5X7, MonoTextStyle},
+    pixelcolor::BinaryColor,
+    prelude::*,
+    primitives::{PrimitiveStyle, PrimitiveStyleBuilder, Rectangle},
+    text::{Alignment, Baseline, Text, TextStyleBuilder},
+};
+
+use super::text_console::wrap;
+
+/// タイトル付きのメッセージボックス
+///
+/// 背景を消去してから描画するので、ほかの描画の上に重ねて表示できます。
+/// メッセージは枠の幅に合わせて折り返され、収まらない行は表示されません。
+pub struct MessageBox<'a> {
+    title: &'a str,
+    message: &'a str,
+    bounds: Rectangle,
+}
+
+impl<'a> MessageBox<'a> {
+    /// 128x64 の画面中央に表示するメッセージボックスを作成します。
+    pub fn new(title: &'a str, message: &'a str) -> Self {
+        Self {
+            title,
+            message,
+            bounds: Rectangle::new(Point::new(8, 8), Size::new(112, 48)),
+        }
+    }
+
+    /// 表示位置と大きさを変更します。
+    pub fn with_bounds(mut self, bounds: Rectangle) -> Self {
+        self.bounds = bounds;
+        self
+    }
+}
+
+impl Drawable for MessageBox<'_> {
+    type Color = BinaryColor;
+    type Output = ();
+
+    fn draw<D>(&self, target: &mut D) -> Result<(), D::Error>
+    where
+        D: DrawTarget<Color = BinaryColor>,
+    {
+        let frame = PrimitiveStyleBuilder::new()
+            .stroke_color(BinaryColor::On)
+            .stroke_width(1)
+            .fill_color(BinaryColor::Off)
+            .build();
+        self.bounds.into_styled(frame).draw(target)?;
+
+        // タイトルバー (反転表示)
+        let font = &FONT_5X7;
+        let line_height = font.character_size.height as i32 + 1;
+        let title_bar = Rectangle::new(
+            self.bounds.top_left,
+            Size::new(self.bounds.size.width, line_height as u32 + 1),
+        );
+        title_bar
+            .into_styled(PrimitiveStyle::with_fill(BinaryColor::On))
+            .draw(target)?;
+        let center = TextStyleBuilder::new()
+            .alignment(Alignment::Center)
+            .baseline(Baseline::Top)
+            .build();
+        Text::with_text_style(
+            self.title,
+            self.bounds.top_left + Point::new(self.bounds.size.width as i32 / 2, 1),
+            MonoTextStyle::new(font, BinaryColor::Off),
+            center,
+        )
+        .draw(target)?;
+
+        // 本文
+        let body = Rectangle::new(
+            title_bar.top_left + Point::new(3, title_bar.size.height as i32 + 2),
+            Size::new(
+                self.bounds.size.width.saturating_sub(6),
+                self.bounds.size.height.saturating_sub(title_bar.size.height + 4),
+            ),
+        );
+        let advance = font.character_size.width + font.character_spacing;
+        let columns = ((body.size.width + font.character_spacing) / advance).max(1) as usize;
+        let rows = (body.size.height as i32 / line_height).max(1) as usize;
+        let style = MonoTextStyle::new(font, BinaryColor::On);
+
+        let lines = self.message.split('\n').flat_map(|p| wrap(p, columns));
+        for (i, line) in lines.take(rows).enumerate() {
+            let position = Point::new(
+                body.top_left.x + body.size.width as i32 / 2,
+                body.top_left.y + i as i32 * line_height,
+            );
+            Text::with_text_style(&line, position, style, center).draw(target)?;
+        }
+        Ok(())
+    }
+}
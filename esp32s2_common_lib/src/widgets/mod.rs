@@ -0,0 +1,14 @@
+//! embedded-graphics で描画する 128x64 モノクロディスプレイ用のウィジェット
+//!
+//! どのウィジェットも `Drawable` を実装しているので、`draw(&mut display)` で描画できます。
+
+pub mod graph;
+pub mod labeled_value;
+pub mod message_box;
+pub mod progress_bar;
+pub mod qr_code;
+pub mod scaled_text;
+pub mod seven_segment;
+pub mod status_bar;
+pub mod strip_chart;
+pub mod text_console;
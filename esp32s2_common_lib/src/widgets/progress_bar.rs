@@ -0,0 +1,56 @@
+//! 枠付きのプログレスバー
+
+use embedded_graphics::{
+    pixelcolor::BinaryColor,
+    prelude::*,
+    primitives::{PrimitiveStyle, Rectangle},
+};
+
+/// 枠付きのプログレスバー
+pub struct ProgressBar {
+    bounds: Rectangle,
+    /// 0.0 から 1.0 までの進捗
+    fraction: f32,
+}
+
+impl ProgressBar {
+    /// `fraction` (0.0 から 1.0) の進捗を表示するバーを作成します。範囲外の値は丸められます。
+    pub fn new(bounds: Rectangle, fraction: f32) -> Self {
+        Self {
+            bounds,
+            fraction: if fraction.is_nan() { 0.0 } else { fraction.clamp(0.0, 1.0) },
+        }
+    }
+
+    /// `min` から `max` の範囲での `value` の位置を表示するバーを作成します。
+    pub fn from_range(bounds: Rectangle, value: f32, min: f32, max: f32) -> Self {
+        let span = max - min;
+        let fraction = if span > 0.0 { (value - min) / span } else { 0.0 };
+        Self::new(bounds, fraction)
+    }
+}
+
+impl Drawable for ProgressBar {
+    type Color = BinaryColor;
+    type Output = ();
+
+    fn draw<D>(&self, target: &mut D) -> Result<(), D::Error>
+    where
+        D: DrawTarget<Color = BinaryColor>,
+    {
+        self.bounds
+            .into_styled(PrimitiveStyle::with_stroke(BinaryColor::On, 1))
+            .draw(target)?;
+
+        // 枠の内側に 1 ピクセルの余白を空けて塗りつぶす
+        let inner = self.bounds.offset(-2);
+        let width = (inner.size.width as f32 * self.fraction).round() as u32;
+        if width > 0 {
+            target.fill_solid(
+                &Rectangle::new(inner.top_left, Size::new(width, inner.size.height)),
+                BinaryColor::On,
+            )?;
+        }
+        Ok(())
+    }
+}
@@ -0,0 +1,113 @@
+//! 文字列を QR コードにして表示するウィジェット
+//!
+//! 指定した領域に収まる最大の整数倍に拡大し、領域の中央に描画します。
+//! 128x64 の画面では高さ 64 ピクセルに収まるよう、クワイエットゾーン (周囲の余白) は
+//! 規格の 4 モジュールではなく 2 モジュールにしています。
+
+use embedded_graphics::{
+    pixelcolor::BinaryColor,
+    prelude::*,
+    primitives::Rectangle,
+};
+use qrcodegen::{DataTooLong, QrCode, QrCodeEcc};
+
+/// クワイエットゾーンのデフォルトの幅 (モジュール数)
+pub const DEFAULT_QUIET_ZONE: u32 = 2;
+
+/// QR コード
+///
+/// 紙に印刷したものと同じく、明るい背景に暗いモジュールで描画します。
+/// (点灯した背景に消灯したモジュール。読み取れない場合は `with_invert` を試してください)
+pub struct QrCodeView {
+    code: QrCode,
+    area: Rectangle,
+    quiet_zone: u32,
+    invert: bool,
+}
+
+impl QrCodeView {
+    /// `text` を誤り訂正レベル L で QR コードにし、`area` に収まるように表示します。
+    ///
+    /// 文字列が長すぎる場合はエラーを返します。
+    pub fn new(text: &str, area: Rectangle) -> Result<Self, DataTooLong> {
+        let code = QrCode::encode_text(text, QrCodeEcc::Low)?;
+        Ok(Self::from_code(code, area))
+    }
+
+    /// エンコード済みの QR コードを表示します。
+    pub fn from_code(code: QrCode, area: Rectangle) -> Self {
+        Self {
+            code,
+            area,
+            quiet_zone: DEFAULT_QUIET_ZONE,
+            invert: false,
+        }
+    }
+
+    /// クワイエットゾーンの幅 (モジュール数) を変更します。
+    pub fn with_quiet_zone(mut self, modules: u32) -> Self {
+        self.quiet_zone = modules;
+        self
+    }
+
+    /// 暗い背景に明るいモジュールで描画するかどうかを設定します。
+    pub fn with_invert(mut self, invert: bool) -> Self {
+        self.invert = invert;
+        self
+    }
+
+    /// 1辺のモジュール数 (クワイエットゾーンを含まない)
+    pub fn modules(&self) -> u32 {
+        self.code.size() as u32
+    }
+
+    /// 1モジュールのピクセル数
+    ///
+    /// 領域に収まらない場合も 1 を返します (はみ出した部分は描画されません)。
+    pub fn scale(&self) -> u32 {
+        let total = self.modules() + self.quiet_zone * 2;
+        let side = self.area.size.width.min(self.area.size.height);
+        (side / total).max(1)
+    }
+
+    /// クワイエットゾーンを含めた描画範囲
+    pub fn bounding_box(&self) -> Rectangle {
+        let side = (self.modules() + self.quiet_zone * 2) * self.scale();
+        let offset = Point::new(
+            (self.area.size.width as i32 - side as i32) / 2,
+            (self.area.size.height as i32 - side as i32) / 2,
+        );
+        Rectangle::new(self.area.top_left + offset, Size::new(side, side))
+    }
+}
+
+impl Drawable for QrCodeView {
+    type Color = BinaryColor;
+    type Output = ();
+
+    fn draw<D>(&self, target: &mut D) -> Result<(), D::Error>
+    where
+        D: DrawTarget<Color = BinaryColor>,
+    {
+        let (light, dark) = if self.invert {
+            (BinaryColor::Off, BinaryColor::On)
+        } else {
+            (BinaryColor::On, BinaryColor::Off)
+        };
+        let bounds = self.bounding_box();
+        target.fill_solid(&bounds, light)?;
+
+        let scale = self.scale();
+        let origin = bounds.top_left + Point::new((self.quiet_zone * scale) as i32, (self.quiet_zone * scale) as i32);
+        let size = self.code.size();
+        for y in 0..size {
+            for x in 0..size {
+                if self.code.get_module(x, y) {
+                    let top_left = origin + Point::new(x * scale as i32, y * scale as i32);
+                    target.fill_solid(&Rectangle::new(top_left, Size::new(scale, scale)), dark)?;
+                }
+            }
+        }
+        Ok(())
+    }
+}
@@ -0,0 +1,121 @@
+//! ビットマップフォントを整数倍に拡大して描画するテキスト
+//!
+//! embedded-graphics の `MonoFont` をそのまま使い、1ピクセルを `scale` x `scale`
+//! の正方形に置き換えて描画します。
+
+use embedded_graphics::{
+    mono_font::{MonoFont, MonoTextStyle},
+    pixelcolor::BinaryColor,
+    prelude::*,
+    primitives::Rectangle,
+    text::{Baseline, Text},
+};
+
+/// 描画先の座標を拡大する `DrawTarget` のアダプタ
+///
+/// 任意の `Drawable` を拡大して描画するのにも使えます。
+pub struct ScaledTarget<'a, D> {
+    target: &'a mut D,
+    origin: Point,
+    scale: u32,
+}
+
+impl<'a, D> ScaledTarget<'a, D>
+where
+    D: DrawTarget<Color = BinaryColor>,
+{
+    /// `origin` を原点として `scale` 倍に拡大して `target` に描画します。
+    pub fn new(target: &'a mut D, origin: Point, scale: u32) -> Self {
+        Self {
+            target,
+            origin,
+            scale: scale.max(1),
+        }
+    }
+}
+
+impl<D> Dimensions for ScaledTarget<'_, D>
+where
+    D: DrawTarget<Color = BinaryColor>,
+{
+    fn bounding_box(&self) -> Rectangle {
+        let area = self.target.bounding_box();
+        let scale = self.scale as i32;
+        let top_left = area.top_left - self.origin;
+        Rectangle::new(
+            Point::new(top_left.x.div_euclid(scale), top_left.y.div_euclid(scale)),
+            Size::new(area.size.width.div_ceil(self.scale), area.size.height.div_ceil(self.scale)),
+        )
+    }
+}
+
+impl<D> DrawTarget for ScaledTarget<'_, D>
+where
+    D: DrawTarget<Color = BinaryColor>,
+{
+    type Color = BinaryColor;
+    type Error = D::Error;
+
+    fn draw_iter<I>(&mut self, pixels: I) -> Result<(), Self::Error>
+    where
+        I: IntoIterator<Item = Pixel<Self::Color>>,
+    {
+        let size = Size::new(self.scale, self.scale);
+        for Pixel(point, color) in pixels {
+            let top_left = self.origin + point * self.scale as i32;
+            self.target.fill_solid(&Rectangle::new(top_left, size), color)?;
+        }
+        Ok(())
+    }
+}
+
+/// 拡大表示するテキスト
+pub struct ScaledText<'a> {
+    text: &'a str,
+    position: Point,
+    font: &'a MonoFont<'a>,
+    scale: u32,
+    color: BinaryColor,
+}
+
+impl<'a> ScaledText<'a> {
+    /// `position` を左上として、`font` を `scale` 倍にした `text` を作成します。
+    pub fn new(text: &'a str, position: Point, font: &'a MonoFont<'a>, scale: u32) -> Self {
+        Self {
+            text,
+            position,
+            font,
+            scale: scale.max(1),
+            color: BinaryColor::On,
+        }
+    }
+
+    /// 文字色を変更します。
+    pub fn with_color(mut self, color: BinaryColor) -> Self {
+        self.color = color;
+        self
+    }
+
+    /// 描画したときの大きさ
+    pub fn size(&self) -> Size {
+        let count = self.text.chars().count() as u32;
+        let advance = self.font.character_size.width + self.font.character_spacing;
+        let width = (count * advance).saturating_sub(self.font.character_spacing);
+        Size::new(width, self.font.character_size.height) * self.scale
+    }
+}
+
+impl Drawable for ScaledText<'_> {
+    type Color = BinaryColor;
+    type Output = ();
+
+    fn draw<D>(&self, target: &mut D) -> Result<(), D::Error>
+    where
+        D: DrawTarget<Color = BinaryColor>,
+    {
+        let mut scaled = ScaledTarget::new(target, self.position, self.scale);
+        let style = MonoTextStyle::new(self.font, self.color);
+        Text::with_baseline(self.text, Point::zero(), style, Baseline::Top).draw(&mut scaled)?;
+        Ok(())
+    }
+}
@@ -0,0 +1,205 @@
+//! 7セグメント風の大きな数字
+//!
+//! `FONT_5X7` では 128x64 の画面で時刻が小さすぎるため、
+//! 長方形の組み合わせで任意の大きさの数字を描画します。
+//!
+//! 対応している文字は `0`-`9`, `-`, `:`, `.`, 空白です。それ以外の文字は空白として扱います。
+
+use embedded_graphics::{
+    pixelcolor::BinaryColor,
+    prelude::*,
+    primitives::Rectangle,
+};
+
+// セグメントのビット (a: 上, b: 右上, c: 右下, d: 下, e: 左下, f: 左上, g: 中央)
+const SEG_A: u8 = 1 << 0;
+const SEG_B: u8 = 1 << 1;
+const SEG_C: u8 = 1 << 2;
+const SEG_D: u8 = 1 << 3;
+const SEG_E: u8 = 1 << 4;
+const SEG_F: u8 = 1 << 5;
+const SEG_G: u8 = 1 << 6;
+
+/// 数字ごとの点灯セグメント
+const DIGITS: [u8; 10] = [
+    SEG_A | SEG_B | SEG_C | SEG_D | SEG_E | SEG_F,         // 0
+    SEG_B | SEG_C,                                         // 1
+    SEG_A | SEG_B | SEG_G | SEG_E | SEG_D,                 // 2
+    SEG_A | SEG_B | SEG_G | SEG_C | SEG_D,                 // 3
+    SEG_F | SEG_G | SEG_B | SEG_C,                         // 4
+    SEG_A | SEG_F | SEG_G | SEG_C | SEG_D,                 // 5
+    SEG_A | SEG_F | SEG_G | SEG_E | SEG_C | SEG_D,         // 6
+    SEG_A | SEG_B | SEG_C,                                 // 7
+    SEG_A | SEG_B | SEG_C | SEG_D | SEG_E | SEG_F | SEG_G, // 8
+    SEG_A | SEG_B | SEG_C | SEG_D | SEG_F | SEG_G,         // 9
+];
+
+/// 7セグメント表示のスタイル
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct SevenSegmentStyle {
+    /// 1桁の大きさ
+    pub digit_size: Size,
+    /// セグメントの太さ
+    pub thickness: u32,
+    /// 文字の間隔
+    pub spacing: u32,
+    pub color: BinaryColor,
+}
+
+impl SevenSegmentStyle {
+    pub fn new(digit_size: Size, thickness: u32) -> Self {
+        Self {
+            digit_size,
+            thickness,
+            spacing: thickness,
+            color: BinaryColor::On,
+        }
+    }
+
+    /// 文字の間隔を変更します。
+    pub fn with_spacing(mut self, spacing: u32) -> Self {
+        self.spacing = spacing;
+        self
+    }
+
+    /// `text` を描画したときの大きさ
+    pub fn text_size(&self, text: &str) -> Size {
+        let count = text.chars().count() as u32;
+        let width: u32 = text.chars().map(|c| self.char_width(c)).sum::<u32>()
+            + count.saturating_sub(1) * self.spacing;
+        Size::new(width, self.digit_size.height)
+    }
+
+    /// 1文字分の幅
+    fn char_width(&self, c: char) -> u32 {
+        match c {
+            ':' | '.' => self.thickness,
+            _ => self.digit_size.width,
+        }
+    }
+}
+
+/// 7セグメント風の文字列
+pub struct SevenSegment<'a> {
+    text: &'a str,
+    position: Point,
+    style: SevenSegmentStyle,
+}
+
+impl<'a> SevenSegment<'a> {
+    /// `position` を左上として `text` を描画する 7 セグメント表示を作成します。
+    pub fn new(text: &'a str, position: Point, style: SevenSegmentStyle) -> Self {
+        Self { text, position, style }
+    }
+
+    /// 描画したときの大きさ
+    pub fn size(&self) -> Size {
+        self.style.text_size(self.text)
+    }
+
+    /// 描画範囲
+    pub fn bounding_box(&self) -> Rectangle {
+        Rectangle::new(self.position, self.size())
+    }
+
+    fn draw_digit<D>(&self, target: &mut D, origin: Point, segments: u8) -> Result<(), D::Error>
+    where
+        D: DrawTarget<Color = BinaryColor>,
+    {
+        let Size { width: w, height: h } = self.style.digit_size;
+        let t = self.style.thickness;
+        // thickness は公開フィールドなので、桁の大きさより太くても引き算が負にならないようにする
+        let right = w.saturating_sub(t);
+        let bottom = h.saturating_sub(t);
+        let mid = bottom / 2;
+        let upper = mid.saturating_sub(t);
+        let lower = h.saturating_sub(mid + 2 * t);
+        let horizontal = w.saturating_sub(2 * t);
+
+        let rects = [
+            (SEG_A, Point::new(t as i32, 0), Size::new(horizontal, t)),
+            (SEG_B, Point::new(right as i32, t as i32), Size::new(t, upper)),
+            (SEG_C, Point::new(right as i32, (mid + t) as i32), Size::new(t, lower)),
+            (SEG_D, Point::new(t as i32, bottom as i32), Size::new(horizontal, t)),
+            (SEG_E, Point::new(0, (mid + t) as i32), Size::new(t, lower)),
+            (SEG_F, Point::new(0, t as i32), Size::new(t, upper)),
+            (SEG_G, Point::new(t as i32, mid as i32), Size::new(horizontal, t)),
+        ];
+
+        for (segment, offset, size) in rects {
+            if segments & segment != 0 {
+                target.fill_solid(&Rectangle::new(origin + offset, size), self.style.color)?;
+            }
+        }
+        Ok(())
+    }
+}
+
+impl Drawable for SevenSegment<'_> {
+    type Color = BinaryColor;
+    type Output = Point;
+
+    /// 描画し、次の文字を描く位置を返します。
+    fn draw<D>(&self, target: &mut D) -> Result<Point, D::Error>
+    where
+        D: DrawTarget<Color = BinaryColor>,
+    {
+        let t = self.style.thickness;
+        let h = self.style.digit_size.height;
+        let dot = Size::new(t, t);
+        let mut x = self.position.x;
+
+        for c in self.text.chars() {
+            let origin = Point::new(x, self.position.y);
+            match c {
+                '0'..='9' => {
+                    let segments = DIGITS[c as usize - '0' as usize];
+                    self.draw_digit(target, origin, segments)?;
+                }
+                '-' => self.draw_digit(target, origin, SEG_G)?,
+                ':' => {
+                    target.fill_solid(&Rectangle::new(origin + Point::new(0, (h / 3).saturating_sub(t / 2) as i32), dot), self.style.color)?;
+                    target.fill_solid(&Rectangle::new(origin + Point::new(0, (h * 2 / 3).saturating_sub(t / 2) as i32), dot), self.style.color)?;
+                }
+                '.' => {
+                    target.fill_solid(&Rectangle::new(origin + Point::new(0, h.saturating_sub(t) as i32), dot), self.style.color)?;
+                }
+                _ => {}
+            }
+            x += (self.style.char_width(c) + self.style.spacing) as i32;
+        }
+        Ok(Point::new(x, self.position.y))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use embedded_graphics::mock_display::MockDisplay;
+
+    #[test]
+    fn text_size_includes_spacing() {
+        let style = SevenSegmentStyle::new(Size::new(10, 20), 2);
+        assert_eq!(style.text_size("12:34"), Size::new(10 * 4 + 2 + 2 * 4, 20));
+        assert_eq!(style.text_size(""), Size::new(0, 20));
+    }
+
+    #[test]
+    fn draws_digit_segments() {
+        let mut display = MockDisplay::new();
+        let style = SevenSegmentStyle::new(Size::new(5, 7), 1);
+        let next = SevenSegment::new("1", Point::zero(), style).draw(&mut display).unwrap();
+        assert_eq!(next, Point::new(6, 0));
+        // 1 は右側の縦のセグメント (b, c) だけ
+        assert_eq!(display.affected_area(), Rectangle::new(Point::new(4, 1), Size::new(1, 5)));
+    }
+
+    #[test]
+    fn thickness_larger_than_digit_does_not_panic() {
+        let mut display = MockDisplay::new();
+        display.set_allow_out_of_bounds_drawing(true);
+        display.set_allow_overdraw(true);
+        let style = SevenSegmentStyle::new(Size::new(4, 4), 10);
+        SevenSegment::new("8:8.-", Point::zero(), style).draw(&mut display).unwrap();
+    }
+}
@@ -0,0 +1,135 @@
+//! 画面上端のステータスバー (タイトルと Wi-Fi の電波強度アイコン)
+
+use embedded_graphics::{
+    mono_font::{ascii::FONT_5X7, MonoTextStyle},
+    pixelcolor::BinaryColor,
+    prelude::*,
+    primitives::{Line, PrimitiveStyle, Rectangle},
+    text::{Baseline, Text},
+};
+
+/// ステータスバーの高さ (区切り線を含む)
+pub const STATUS_BAR_HEIGHT: u32 = 9;
+
+/// Wi-Fi アイコンの表示内容
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum WifiIndicator {
+    /// アイコンを表示しない
+    Hidden,
+    /// 未接続
+    Disconnected,
+    /// 接続中 (RSSI, dBm)
+    Connected { rssi: i8 },
+}
+
+impl WifiIndicator {
+    /// RSSI から電波強度のバーの本数 (0-4) を求めます。
+    pub fn bars(&self) -> u8 {
+        match *self {
+            WifiIndicator::Connected { rssi } => match rssi {
+                r if r >= -55 => 4,
+                r if r >= -67 => 3,
+                r if r >= -75 => 2,
+                r if r >= -85 => 1,
+                _ => 0,
+            },
+            _ => 0,
+        }
+    }
+}
+
+/// 画面上端に表示するステータスバー
+pub struct StatusBar<'a> {
+    title: &'a str,
+    wifi: WifiIndicator,
+    width: u32,
+}
+
+impl<'a> StatusBar<'a> {
+    /// 幅 128 ピクセルのステータスバーを作成します。
+    pub fn new(title: &'a str) -> Self {
+        Self {
+            title,
+            wifi: WifiIndicator::Hidden,
+            width: 128,
+        }
+    }
+
+    /// Wi-Fi アイコンの表示内容を設定します。
+    pub fn with_wifi(mut self, wifi: WifiIndicator) -> Self {
+        self.wifi = wifi;
+        self
+    }
+
+    /// 幅を変更します。
+    pub fn with_width(mut self, width: u32) -> Self {
+        self.width = width;
+        self
+    }
+
+    /// ステータスバーの下の、自由に使える領域
+    pub fn content_area(screen: Size) -> Rectangle {
+        Rectangle::new(
+            Point::new(0, STATUS_BAR_HEIGHT as i32),
+            Size::new(screen.width, screen.height.saturating_sub(STATUS_BAR_HEIGHT)),
+        )
+    }
+
+    /// 右上に Wi-Fi アイコンを描画します。`right` はアイコンの右端の x 座標です。
+    fn draw_wifi_icon<D>(&self, target: &mut D, right: i32) -> Result<(), D::Error>
+    where
+        D: DrawTarget<Color = BinaryColor>,
+    {
+        // 4本のバー (幅2, 間隔1, 高さ 2/4/6/7)
+        const HEIGHTS: [u32; 4] = [2, 4, 6, 7];
+        let left = right - 10;
+        let bars = self.wifi.bars();
+
+        for (i, &height) in HEIGHTS.iter().enumerate() {
+            let x = left + i as i32 * 3;
+            let bar = Rectangle::new(Point::new(x, 7 - height as i32), Size::new(2, height));
+            if (i as u8) < bars {
+                target.fill_solid(&bar, BinaryColor::On)?;
+            } else {
+                // 弱いバーは下端の点だけ表示する
+                target.fill_solid(&Rectangle::new(Point::new(x, 6), Size::new(2, 1)), BinaryColor::On)?;
+            }
+        }
+
+        if self.wifi == WifiIndicator::Disconnected {
+            // 未接続なら × を重ねる
+            let style = PrimitiveStyle::with_stroke(BinaryColor::On, 1);
+            Line::new(Point::new(left, 0), Point::new(left + 4, 4)).into_styled(style).draw(target)?;
+            Line::new(Point::new(left, 4), Point::new(left + 4, 0)).into_styled(style).draw(target)?;
+        }
+        Ok(())
+    }
+}
+
+impl Drawable for StatusBar<'_> {
+    type Color = BinaryColor;
+    type Output = ();
+
+    fn draw<D>(&self, target: &mut D) -> Result<(), D::Error>
+    where
+        D: DrawTarget<Color = BinaryColor>,
+    {
+        target.fill_solid(
+            &Rectangle::new(Point::zero(), Size::new(self.width, STATUS_BAR_HEIGHT)),
+            BinaryColor::Off,
+        )?;
+
+        let style = MonoTextStyle::new(&FONT_5X7, BinaryColor::On);
+        Text::with_baseline(self.title, Point::new(1, 0), style, Baseline::Top).draw(target)?;
+
+        if self.wifi != WifiIndicator::Hidden {
+            self.draw_wifi_icon(target, self.width as i32 - 1)?;
+        }
+
+        let y = STATUS_BAR_HEIGHT as i32 - 1;
+        Line::new(Point::new(0, y), Point::new(self.width as i32 - 1, y))
+            .into_styled(PrimitiveStyle::with_stroke(BinaryColor::On, 1))
+            .draw(target)?;
+        Ok(())
+    }
+}
@@ -0,0 +1,216 @@
+//! 時系列データを右から左へ流して表示するストリップチャート
+//!
+//! 1回の `push` で全系列に1サンプルずつ追加し、1サンプルを横1ピクセルとして描画します。
+//! 描画領域の幅を超えた古いサンプルは捨てられます。
+//! モノクロ画面でも系列を区別できるよう、系列ごとに実線・点線・破線を使い分けます。
+
+use std::collections::VecDeque;
+
+use embedded_graphics::{
+    pixelcolor::BinaryColor,
+    prelude::*,
+    primitives::{PrimitiveStyle, Rectangle},
+};
+
+use super::graph::{auto_range, value_to_y};
+
+/// 系列の線の種類
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SeriesStyle {
+    /// 実線
+    Solid,
+    /// 点線 (1ピクセルおき)
+    Dotted,
+    /// 破線 (3ピクセル描いて2ピクセル空ける)
+    Dashed,
+}
+
+impl SeriesStyle {
+    /// 系列の番号に対応する線の種類 (実線・点線・破線の順に繰り返す)
+    pub fn for_index(index: usize) -> Self {
+        match index % 3 {
+            0 => SeriesStyle::Solid,
+            1 => SeriesStyle::Dotted,
+            _ => SeriesStyle::Dashed,
+        }
+    }
+
+    /// x 座標 (描画領域の左端からの距離) の列を描くかどうか
+    fn visible(&self, x: i32) -> bool {
+        match self {
+            SeriesStyle::Solid => true,
+            SeriesStyle::Dotted => x % 2 == 0,
+            SeriesStyle::Dashed => x % 5 < 3,
+        }
+    }
+
+    /// 凡例用に、`start` から右へ `width` ピクセルの水平線を描画します。
+    pub fn draw_sample<D>(&self, target: &mut D, start: Point, width: u32) -> Result<(), D::Error>
+    where
+        D: DrawTarget<Color = BinaryColor>,
+    {
+        let pixels = (0..width as i32)
+            .filter(|&x| self.visible(x))
+            .map(|x| Pixel(start + Point::new(x, 0), BinaryColor::On));
+        target.draw_iter(pixels)
+    }
+}
+
+/// 複数系列のストリップチャート
+///
+/// 範囲を指定しない場合は、表示中のサンプルの最小値・最大値に合わせて自動でスケーリングします。
+pub struct StripChart {
+    bounds: Rectangle,
+    series: Vec<VecDeque<f32>>,
+    range: Option<(f32, f32)>,
+    min_span: f32,
+}
+
+impl StripChart {
+    /// `series` 本の系列を表示するチャートを作成します。
+    pub fn new(bounds: Rectangle, series: usize) -> Self {
+        let capacity = bounds.size.width.saturating_sub(2) as usize;
+        Self {
+            bounds,
+            series: (0..series).map(|_| VecDeque::with_capacity(capacity)).collect(),
+            range: None,
+            min_span: 0.0,
+        }
+    }
+
+    /// 縦軸の範囲を固定します。
+    pub fn with_range(mut self, min: f32, max: f32) -> Self {
+        self.set_range(Some((min, max)));
+        self
+    }
+
+    /// 自動スケーリング時の縦軸の最小の幅を設定します。
+    ///
+    /// 値がほとんど変化しないときに、ノイズが画面いっぱいに拡大されるのを防ぎます。
+    pub fn with_min_span(mut self, span: f32) -> Self {
+        self.min_span = span;
+        self
+    }
+
+    /// 縦軸の範囲を変更します。`None` で自動スケーリングになります。
+    pub fn set_range(&mut self, range: Option<(f32, f32)>) {
+        self.range = range.filter(|(min, max)| max > min);
+    }
+
+    /// 自動スケーリングかどうか
+    pub fn is_auto_range(&self) -> bool {
+        self.range.is_none()
+    }
+
+    /// 全系列に1サンプルずつ追加します。
+    ///
+    /// `values` が系列の数より少ない場合、足りない系列には前回の値を追加します。
+    pub fn push(&mut self, values: &[f32]) {
+        let capacity = self.capacity();
+        for (i, samples) in self.series.iter_mut().enumerate() {
+            let value = values
+                .get(i)
+                .copied()
+                .or_else(|| samples.back().copied())
+                .unwrap_or(0.0);
+            if samples.len() >= capacity {
+                samples.pop_front();
+            }
+            samples.push_back(value);
+        }
+    }
+
+    /// すべてのサンプルを削除します。
+    pub fn clear(&mut self) {
+        for samples in &mut self.series {
+            samples.clear();
+        }
+    }
+
+    /// 表示できるサンプル数 (枠の内側の幅)
+    pub fn capacity(&self) -> usize {
+        self.plot_area().size.width as usize
+    }
+
+    /// 系列 `index` の最新の値
+    pub fn latest(&self, index: usize) -> Option<f32> {
+        self.series.get(index).and_then(|samples| samples.back().copied())
+    }
+
+    /// 描画に使う縦軸の範囲
+    pub fn range(&self) -> (f32, f32) {
+        if let Some(range) = self.range {
+            return range;
+        }
+
+        let data: Vec<f32> = self.series.iter().flatten().copied().collect();
+        let (min, max) = auto_range(&data);
+        let span = max - min;
+        if span < self.min_span {
+            let center = (min + max) / 2.0;
+            (center - self.min_span / 2.0, center + self.min_span / 2.0)
+        } else {
+            (min, max)
+        }
+    }
+
+    /// 枠の内側の描画領域
+    fn plot_area(&self) -> Rectangle {
+        self.bounds.offset(-1)
+    }
+}
+
+impl Drawable for StripChart {
+    type Color = BinaryColor;
+    type Output = ();
+
+    fn draw<D>(&self, target: &mut D) -> Result<(), D::Error>
+    where
+        D: DrawTarget<Color = BinaryColor>,
+    {
+        self.bounds
+            .into_styled(PrimitiveStyle::with_stroke(BinaryColor::On, 1))
+            .draw(target)?;
+
+        let area = self.plot_area();
+        if area.is_zero_sized() {
+            return Ok(());
+        }
+        let range = self.range();
+        let right = area.top_left.x + area.size.width as i32 - 1;
+
+        // 0 が範囲内にあれば基準線を点線で描く
+        if range.0 < 0.0 && range.1 > 0.0 {
+            let y = value_to_y(0.0, range, &area);
+            let pixels = (area.top_left.x..=right)
+                .step_by(4)
+                .map(|x| Pixel(Point::new(x, y), BinaryColor::On));
+            target.draw_iter(pixels)?;
+        }
+
+        for (index, samples) in self.series.iter().enumerate() {
+            let style = SeriesStyle::for_index(index);
+            // 最新のサンプルを右端に揃える
+            let left = right + 1 - samples.len() as i32;
+            let mut previous: Option<i32> = None;
+            for (i, &value) in samples.iter().enumerate() {
+                let x = left + i as i32;
+                let y = value_to_y(value, range, &area);
+                if style.visible(x - area.top_left.x) {
+                    // 前のサンプルとの間を縦線でつなぐ
+                    let (top, bottom) = match previous {
+                        Some(p) if p < y => (p + 1, y),
+                        Some(p) if p > y => (y, p - 1),
+                        _ => (y, y),
+                    };
+                    target.fill_solid(
+                        &Rectangle::new(Point::new(x, top), Size::new(1, (bottom - top + 1) as u32)),
+                        BinaryColor::On,
+                    )?;
+                }
+                previous = Some(y);
+            }
+        }
+        Ok(())
+    }
+}
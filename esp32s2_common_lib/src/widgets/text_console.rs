@@ -0,0 +1,293 @@
+//! OLED 用のスクロール可能なテキストコンソール
+//!
+//! 行の履歴を保持し、表示領域の幅に合わせて単語単位で折り返します。
+//! `ConsoleLogger` を使うと `log` クレートの出力をそのままコンソールに流せるので、
+//! 起動中の診断メッセージを実機の画面で確認できます。
+//!
+//! ## 使用例
+//!
+//! ```no_run
+//! use std::sync::{Arc, Mutex};
+//! use embedded_graphics::prelude::*;
+//! use esp32s2_common_lib::widgets::text_console::{ConsoleLogger, TextConsole};
+//!
+//! let console = Arc::new(Mutex::new(TextConsole::new_full_screen()));
+//! ConsoleLogger::new(console.clone()).install().unwrap();
+//!
+//! log::info!("connecting wifi...");
+//! // console.lock().unwrap().draw(&mut display)?;
+//! // display.flush()?;
+//! ```
+
+use std::collections::VecDeque;
+use std::sync::{Arc, Mutex};
+
+use embedded_graphics::{
+    mono_font::{ascii::FONT_5X7, MonoFont, MonoTextStyle},
+    pixelcolor::BinaryColor,
+    prelude::*,
+    primitives::{PrimitiveStyle, Rectangle},
+    text::{Baseline, Text},
+};
+
+/// 保持する行数のデフォルト値
+pub const DEFAULT_HISTORY: usize = 64;
+
+/// 行履歴を持つテキストコンソール
+pub struct TextConsole {
+    area: Rectangle,
+    font: &'static MonoFont<'static>,
+    lines: VecDeque<String>,
+    history: usize,
+    /// 最下行からのスクロール量 (行数)
+    scroll: usize,
+}
+
+impl TextConsole {
+    /// 指定した領域に `FONT_5X7` で描画するコンソールを作成します。
+    pub fn new(area: Rectangle) -> Self {
+        Self {
+            area,
+            font: &FONT_5X7,
+            lines: VecDeque::new(),
+            history: DEFAULT_HISTORY,
+            scroll: 0,
+        }
+    }
+
+    /// 128x64 の画面全体を使うコンソールを作成します。
+    pub fn new_full_screen() -> Self {
+        Self::new(Rectangle::new(Point::zero(), Size::new(128, 64)))
+    }
+
+    /// 描画に使うフォントを変更します。
+    pub fn with_font(mut self, font: &'static MonoFont<'static>) -> Self {
+        self.font = font;
+        self
+    }
+
+    /// 保持する行数を変更します。
+    pub fn with_history(mut self, history: usize) -> Self {
+        self.history = history.max(1);
+        self.trim_history();
+        self
+    }
+
+    /// 1行に表示できる文字数
+    pub fn columns(&self) -> usize {
+        let advance = self.font.character_size.width + self.font.character_spacing;
+        ((self.area.size.width + self.font.character_spacing) / advance).max(1) as usize
+    }
+
+    /// 画面に表示できる行数
+    pub fn rows(&self) -> usize {
+        (self.area.size.height / self.font.character_size.height).max(1) as usize
+    }
+
+    /// 保持している行 (折り返し後)
+    pub fn lines(&self) -> impl Iterator<Item = &str> {
+        self.lines.iter().map(|s| s.as_str())
+    }
+
+    /// メッセージを追加します。
+    ///
+    /// 改行で段落を区切り、それぞれを表示幅に合わせて折り返します。
+    /// スクロール中でなければ最新の行が表示されるようにします。
+    pub fn push_str(&mut self, msg: &str) {
+        let columns = self.columns();
+        for paragraph in msg.split('\n') {
+            for line in wrap(paragraph, columns) {
+                self.lines.push_back(line);
+                if self.scroll > 0 {
+                    // スクロール位置を保つため、追加した分だけずらす
+                    self.scroll += 1;
+                }
+            }
+        }
+        self.trim_history();
+    }
+
+    /// すべての行を消去します。
+    pub fn clear(&mut self) {
+        self.lines.clear();
+        self.scroll = 0;
+    }
+
+    /// 古い行の方向へスクロールします。
+    pub fn scroll_up(&mut self, lines: usize) {
+        self.scroll = (self.scroll + lines).min(self.max_scroll());
+    }
+
+    /// 新しい行の方向へスクロールします。
+    pub fn scroll_down(&mut self, lines: usize) {
+        self.scroll = self.scroll.saturating_sub(lines);
+    }
+
+    /// 最新の行が見える位置に戻します。
+    pub fn scroll_to_bottom(&mut self) {
+        self.scroll = 0;
+    }
+
+    fn max_scroll(&self) -> usize {
+        self.lines.len().saturating_sub(self.rows())
+    }
+
+    fn trim_history(&mut self) {
+        while self.lines.len() > self.history {
+            self.lines.pop_front();
+        }
+        self.scroll = self.scroll.min(self.max_scroll());
+    }
+
+    /// 現在表示される行の範囲
+    fn visible_lines(&self) -> impl Iterator<Item = &String> {
+        let end = self.lines.len() - self.scroll;
+        let start = end.saturating_sub(self.rows());
+        self.lines.range(start..end)
+    }
+}
+
+impl Drawable for TextConsole {
+    type Color = BinaryColor;
+    type Output = ();
+
+    /// 領域を消去してから、表示中の行を上から順に描画します。
+    fn draw<D>(&self, target: &mut D) -> Result<(), D::Error>
+    where
+        D: DrawTarget<Color = BinaryColor>,
+    {
+        self.area
+            .into_styled(PrimitiveStyle::with_fill(BinaryColor::Off))
+            .draw(target)?;
+
+        let text_style = MonoTextStyle::new(self.font, BinaryColor::On);
+        let line_height = self.font.character_size.height as i32;
+        for (i, line) in self.visible_lines().enumerate() {
+            let position = self.area.top_left + Point::new(0, i as i32 * line_height);
+            Text::with_baseline(line, position, text_style, Baseline::Top).draw(target)?;
+        }
+        Ok(())
+    }
+}
+
+/// 1つの段落を `columns` 文字ごとに単語単位で折り返します。
+///
+/// 1行に収まらない長い単語は文字単位で分割します。
+pub(crate) fn wrap(paragraph: &str, columns: usize) -> Vec<String> {
+    let mut lines = Vec::new();
+    let mut current = String::new();
+    let mut current_len = 0;
+
+    for word in paragraph.split_whitespace() {
+        let mut word_len = word.chars().count();
+        let mut word = word;
+
+        if current_len > 0 && current_len + 1 + word_len > columns {
+            lines.push(std::mem::take(&mut current));
+            current_len = 0;
+        }
+
+        // 長すぎる単語を分割 (ここに来るとき current は必ず空)
+        while word_len > columns {
+            let split = word
+                .char_indices()
+                .nth(columns)
+                .map(|(i, _)| i)
+                .unwrap_or(word.len());
+            lines.push(word[..split].to_string());
+            word = &word[split..];
+            word_len = word.chars().count();
+        }
+
+        if word_len == 0 {
+            continue;
+        }
+        if current_len > 0 {
+            current.push(' ');
+            current_len += 1;
+        }
+        current.push_str(word);
+        current_len += word_len;
+    }
+
+    if current_len > 0 || lines.is_empty() {
+        lines.push(current);
+    }
+    lines
+}
+
+/// `log` の出力を `TextConsole` に書き込むロガー
+///
+/// ディスプレイは別スレッドから描画できないため、ロガーは行を追加するだけです。
+/// 画面への反映はアプリケーション側で `draw` と `flush` を呼び出してください。
+pub struct ConsoleLogger {
+    console: Arc<Mutex<TextConsole>>,
+    level: log::LevelFilter,
+    forward: Option<Box<dyn log::Log>>,
+}
+
+impl ConsoleLogger {
+    pub fn new(console: Arc<Mutex<TextConsole>>) -> Self {
+        Self {
+            console,
+            level: log::LevelFilter::Info,
+            forward: None,
+        }
+    }
+
+    /// コンソールに表示するログレベルを設定します。
+    pub fn with_level(mut self, level: log::LevelFilter) -> Self {
+        self.level = level;
+        self
+    }
+
+    /// シリアル出力も残したい場合に、転送先のロガーを指定します。
+    ///
+    /// 例: `Box::new(esp_idf_svc::log::EspLogger::new())`
+    pub fn with_forward(mut self, logger: Box<dyn log::Log>) -> Self {
+        self.forward = Some(logger);
+        self
+    }
+
+    /// グローバルロガーとして登録します。
+    pub fn install(self) -> Result<(), log::SetLoggerError> {
+        let max_level = match &self.forward {
+            Some(_) => log::LevelFilter::max(),
+            None => self.level,
+        };
+        log::set_logger(Box::leak(Box::new(self)))?;
+        log::set_max_level(max_level);
+        Ok(())
+    }
+}
+
+impl log::Log for ConsoleLogger {
+    fn enabled(&self, metadata: &log::Metadata) -> bool {
+        metadata.level() <= self.level
+            || self.forward.as_ref().is_some_and(|f| f.enabled(metadata))
+    }
+
+    fn log(&self, record: &log::Record) {
+        if record.level() <= self.level {
+            let prefix = match record.level() {
+                log::Level::Error => 'E',
+                log::Level::Warn => 'W',
+                log::Level::Info => 'I',
+                log::Level::Debug => 'D',
+                log::Level::Trace => 'T',
+            };
+            if let Ok(mut console) = self.console.lock() {
+                console.push_str(&format!("{} {}", prefix, record.args()));
+            }
+        }
+        if let Some(forward) = &self.forward {
+            forward.log(record);
+        }
+    }
+
+    fn flush(&self) {
+        if let Some(forward) = &self.forward {
+            forward.flush();
+        }
+    }
+}
@@ -0,0 +1,336 @@
+//! Wi-Fi の接続先 (SSID とパスワード) を NVS に保存する
+//!
+//! SSID とパスワードをビルド時に埋め込むと、接続先ごとにファームウェアを作り直す必要があります。
+//! `CredentialStore` は NVS (不揮発性ストレージ) に接続先を最大 `MAX_NETWORKS` 件保存し、実行中に変更できるようにします。
+//! NVS に保存されていないときだけ、ビルド時の値 (`option_env!("SSID")` など) を使います。
+//!
+//! パスワードが空の接続先はオープンなネットワーク (認証なし) として接続します。
+//!
+//! 複数の接続先を保存したときは、`select_networks` で周囲に見えるものを優先度と受信強度の順に並べ、
+//! 先頭から順に接続を試します (`wifi_manager` はこの順で接続します)。
+//!
+//! ## 使用例
+//!
+//! ```no_run
+//! use esp_idf_svc::nvs::EspDefaultNvsPartition;
+//! use esp32s2_common_lib::wifi_credentials::{CredentialStore, Credentials};
+//!
+//! let nvs = EspDefaultNvsPartition::take()?;
+//! let mut store = CredentialStore::new(nvs.clone())?;
+//! store.add(&Credentials::new("office", "********")?, 10)?;
+//! store.add(&Credentials::new("lab", "********")?, 0)?;
+//! store.add(&Credentials::new("guest", "")?, 0)?; // オープンなネットワーク
+//!
+//! let fallback = Credentials::from_build_env(option_env!("SSID"), option_env!("SSID_PASSWORD"));
+//! let networks = store.load_or(fallback)?;
+//! if networks.is_empty() { /* 接続先が未設定 */ }
+//! # Ok::<(), anyhow::Error>(())
+//! ```
+
+use core::fmt;
+
+use esp_idf_svc::nvs::{EspDefaultNvsPartition, EspNvs, NvsDefault};
+use esp_idf_svc::sys::EspError;
+use esp_idf_svc::wifi::{AuthMethod, ClientConfiguration};
+
+/// NVS の名前空間
+const NAMESPACE: &str = "wifi";
+
+/// 保存できる接続先の数
+pub const MAX_NETWORKS: usize = 8;
+/// `set` で保存したときの優先度
+pub const DEFAULT_PRIORITY: u8 = 0;
+
+/// SSID の最大の長さ (バイト)
+pub const MAX_SSID_LEN: usize = 32;
+/// パスワードの最大の長さ (バイト)
+pub const MAX_PASSWORD_LEN: usize = 64;
+
+/// 接続先の SSID とパスワード
+#[derive(Clone, PartialEq, Eq)]
+pub struct Credentials {
+    pub ssid: String,
+    /// オープンなネットワークでは空
+    pub password: String,
+}
+
+impl Credentials {
+    /// 長さを確認して作成します。
+    pub fn new(ssid: &str, password: &str) -> Result<Self, InvalidCredentials> {
+        if ssid.is_empty() || ssid.len() > MAX_SSID_LEN {
+            return Err(InvalidCredentials::Ssid);
+        }
+        if password.len() > MAX_PASSWORD_LEN {
+            return Err(InvalidCredentials::Password);
+        }
+        Ok(Self {
+            ssid: ssid.to_string(),
+            password: password.to_string(),
+        })
+    }
+
+    /// ビルド時の値から作成します。SSID が指定されていないか、長さが正しくない場合は `None` を返します。
+    pub fn from_build_env(ssid: Option<&str>, password: Option<&str>) -> Option<Self> {
+        Self::new(ssid?, password.unwrap_or("")).ok()
+    }
+
+    /// 接続に使う認証方式。パスワードが空ならオープンなネットワークとして `AuthMethod::None` を返します。
+    pub fn auth_method(&self) -> AuthMethod {
+        if self.password.is_empty() {
+            AuthMethod::None
+        } else {
+            AuthMethod::WPA2Personal
+        }
+    }
+
+    /// `EspWifi::set_configuration` に渡す設定を作成します。
+    pub fn client_configuration(&self) -> Result<ClientConfiguration, InvalidCredentials> {
+        Ok(ClientConfiguration {
+            ssid: self.ssid.as_str().try_into().map_err(|_| InvalidCredentials::Ssid)?,
+            password: self.password.as_str().try_into().map_err(|_| InvalidCredentials::Password)?,
+            auth_method: self.auth_method(),
+            ..Default::default()
+        })
+    }
+}
+
+impl fmt::Debug for Credentials {
+    /// ログにパスワードが出ないようにする
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("Credentials")
+            .field("ssid", &self.ssid)
+            .field("password", &"***")
+            .finish()
+    }
+}
+
+/// SSID またはパスワードの長さが正しくない
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum InvalidCredentials {
+    /// SSID が空か、32 バイトを超えている
+    Ssid,
+    /// パスワードが 64 バイトを超えている
+    Password,
+}
+
+impl fmt::Display for InvalidCredentials {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            InvalidCredentials::Ssid => write!(f, "SSID must be 1-{} bytes", MAX_SSID_LEN),
+            InvalidCredentials::Password => write!(f, "password must be at most {} bytes", MAX_PASSWORD_LEN),
+        }
+    }
+}
+
+impl std::error::Error for InvalidCredentials {}
+
+/// 保存した接続先と優先度
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct KnownNetwork {
+    pub credentials: Credentials,
+    /// 大きいほど優先して接続する
+    pub priority: u8,
+}
+
+impl KnownNetwork {
+    pub fn new(credentials: Credentials, priority: u8) -> Self {
+        Self { credentials, priority }
+    }
+
+    pub fn ssid(&self) -> &str {
+        &self.credentials.ssid
+    }
+}
+
+/// 接続を試す順に接続先を並べます。
+///
+/// `visible` (スキャンで見つかった SSID と受信強度) にあるものを優先度の高い順、
+/// 同じ優先度では受信強度の強い順に並べ、見つからなかったもの (SSID を隠しているネットワークなど) は
+/// 優先度の高い順に最後に並べます。
+pub fn select_networks<'a>(known: &'a [KnownNetwork], visible: &[(&str, i8)]) -> Vec<&'a KnownNetwork> {
+    let rssi = |network: &KnownNetwork| {
+        visible
+            .iter()
+            .filter(|(ssid, _)| *ssid == network.ssid())
+            .map(|(_, rssi)| *rssi)
+            .max()
+    };
+    let mut candidates: Vec<(&KnownNetwork, Option<i8>)> = known.iter().map(|network| (network, rssi(network))).collect();
+    // 見えているもの → 優先度 → 受信強度 の順 (None < Some なので降順に並べる)
+    candidates.sort_by(|(a, a_rssi), (b, b_rssi)| {
+        (b_rssi.is_some(), b.priority, b_rssi).cmp(&(a_rssi.is_some(), a.priority, a_rssi))
+    });
+    candidates.into_iter().map(|(network, _)| network).collect()
+}
+
+/// NVS に保存した接続先
+///
+/// パスワードが空の接続先もそのまま保存し、接続時はオープンなネットワークとして扱います
+/// (`Credentials::auth_method`)。
+pub struct CredentialStore {
+    nvs: EspNvs<NvsDefault>,
+}
+
+impl CredentialStore {
+    /// デフォルトの NVS パーティションの `wifi` 名前空間を開きます。
+    ///
+    /// `EspWifi::new` にも同じパーティションを渡せるよう、`clone` したものを渡してください。
+    pub fn new(partition: EspDefaultNvsPartition) -> Result<Self, EspError> {
+        Ok(Self {
+            nvs: EspNvs::new(partition, NAMESPACE, true)?,
+        })
+    }
+
+    /// 保存されている接続先を優先度の高い順に読み込みます。
+    pub fn networks(&self) -> Result<Vec<KnownNetwork>, EspError> {
+        let mut networks = Vec::new();
+        let mut ssid_buf = [0u8; MAX_SSID_LEN + 1];
+        let mut password_buf = [0u8; MAX_PASSWORD_LEN + 1];
+        for i in 0..MAX_NETWORKS {
+            let Some(ssid) = self.nvs.get_str(&key("ssid", i), &mut ssid_buf)? else {
+                continue;
+            };
+            let password = self.nvs.get_str(&key("pass", i), &mut password_buf)?.unwrap_or("");
+            let priority = self.nvs.get_u8(&key("prio", i))?.unwrap_or(DEFAULT_PRIORITY);
+            if let Ok(credentials) = Credentials::new(ssid, password) {
+                networks.push(KnownNetwork::new(credentials, priority));
+            }
+        }
+        networks.sort_by(|a, b| b.priority.cmp(&a.priority));
+        Ok(networks)
+    }
+
+    /// 接続先を追加します。同じ SSID があれば置き換えます。
+    ///
+    /// `MAX_NETWORKS` 件保存済みのときは、優先度の最も低いものを削除してから追加します。
+    pub fn add(&mut self, credentials: &Credentials, priority: u8) -> Result<(), EspError> {
+        let mut networks = self.networks()?;
+        networks.retain(|n| n.ssid() != credentials.ssid);
+        if networks.len() >= MAX_NETWORKS {
+            // 優先度の高い順に並んでいるので、最後が最も低い
+            if let Some(removed) = networks.pop() {
+                log::warn!("Too many Wi-Fi networks, removed {}", removed.ssid());
+            }
+        }
+        networks.push(KnownNetwork::new(credentials.clone(), priority));
+        self.save(&networks)?;
+        log::info!("Wi-Fi credentials saved (SSID: {}, priority: {})", credentials.ssid, priority);
+        Ok(())
+    }
+
+    /// 接続先を `DEFAULT_PRIORITY` で追加します。同じ SSID があれば優先度はそのままにします。
+    pub fn set(&mut self, credentials: &Credentials) -> Result<(), EspError> {
+        let priority = self
+            .networks()?
+            .iter()
+            .find(|n| n.ssid() == credentials.ssid)
+            .map_or(DEFAULT_PRIORITY, |n| n.priority);
+        self.add(credentials, priority)
+    }
+
+    /// 接続先の優先度を変更します。`ssid` が保存されていなければ `false` を返します。
+    pub fn set_priority(&mut self, ssid: &str, priority: u8) -> Result<bool, EspError> {
+        let mut networks = self.networks()?;
+        let Some(network) = networks.iter_mut().find(|n| n.ssid() == ssid) else {
+            return Ok(false);
+        };
+        network.priority = priority;
+        self.save(&networks)?;
+        Ok(true)
+    }
+
+    /// 接続先を削除します。`ssid` が保存されていなければ `false` を返します。
+    pub fn remove(&mut self, ssid: &str) -> Result<bool, EspError> {
+        let mut networks = self.networks()?;
+        let len = networks.len();
+        networks.retain(|n| n.ssid() != ssid);
+        if networks.len() == len {
+            return Ok(false);
+        }
+        self.save(&networks)?;
+        log::info!("Wi-Fi credentials removed (SSID: {})", ssid);
+        Ok(true)
+    }
+
+    /// 保存されている接続先をすべて消去します。
+    pub fn clear(&mut self) -> Result<(), EspError> {
+        self.save(&[])?;
+        log::info!("Wi-Fi credentials cleared");
+        Ok(())
+    }
+
+    /// 保存されている接続先を読み込み、なければ `fallback` (ビルド時の値など) だけを返します。
+    pub fn load_or(&self, fallback: Option<Credentials>) -> Result<Vec<KnownNetwork>, EspError> {
+        let networks = self.networks()?;
+        if !networks.is_empty() {
+            let ssids: Vec<&str> = networks.iter().map(|n| n.ssid()).collect();
+            log::info!("Using Wi-Fi credentials from NVS (SSID: {})", ssids.join(", "));
+            return Ok(networks);
+        }
+        Ok(fallback
+            .map(|credentials| {
+                log::info!("Using build-time Wi-Fi credentials (SSID: {})", credentials.ssid);
+                vec![KnownNetwork::new(credentials, DEFAULT_PRIORITY)]
+            })
+            .unwrap_or_default())
+    }
+
+    /// `networks` を先頭のスロットから書き込み、残りのスロットを消去する
+    fn save(&mut self, networks: &[KnownNetwork]) -> Result<(), EspError> {
+        for i in 0..MAX_NETWORKS {
+            match networks.get(i) {
+                Some(network) => {
+                    self.nvs.set_str(&key("ssid", i), &network.credentials.ssid)?;
+                    self.nvs.set_str(&key("pass", i), &network.credentials.password)?;
+                    self.nvs.set_u8(&key("prio", i), network.priority)?;
+                }
+                None => {
+                    self.nvs.remove(&key("ssid", i))?;
+                    self.nvs.remove(&key("pass", i))?;
+                    self.nvs.remove(&key("prio", i))?;
+                }
+            }
+        }
+        Ok(())
+    }
+}
+
+/// スロット `index` の NVS のキー (例: `ssid3`)
+fn key(name: &str, index: usize) -> String {
+    format!("{}{}", name, index)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn empty_password_is_open_network() {
+        let open = Credentials::new("guest", "").unwrap();
+        assert_eq!(open.auth_method(), AuthMethod::None);
+        let secured = Credentials::new("office", "password").unwrap();
+        assert_eq!(secured.auth_method(), AuthMethod::WPA2Personal);
+
+        // ビルド時にパスワードを指定しなければオープンなネットワーク
+        let fallback = Credentials::from_build_env(Some("guest"), None).unwrap();
+        assert_eq!(fallback.auth_method(), AuthMethod::None);
+    }
+
+    #[test]
+    fn validates_lengths() {
+        assert_eq!(Credentials::new("", "password"), Err(InvalidCredentials::Ssid));
+        assert_eq!(Credentials::new(&"a".repeat(MAX_SSID_LEN + 1), ""), Err(InvalidCredentials::Ssid));
+        assert_eq!(Credentials::new("office", &"a".repeat(MAX_PASSWORD_LEN + 1)), Err(InvalidCredentials::Password));
+        assert!(Credentials::new(&"a".repeat(MAX_SSID_LEN), &"a".repeat(MAX_PASSWORD_LEN)).is_ok());
+        assert_eq!(Credentials::from_build_env(None, Some("password")), None);
+    }
+
+    #[test]
+    fn selects_visible_networks_by_priority_and_signal() {
+        let network = |ssid: &str, priority| KnownNetwork::new(Credentials::new(ssid, "").unwrap(), priority);
+        let known = [network("home", 0), network("office", 10), network("hidden", 20), network("lab", 10)];
+        let visible = [("home", -40), ("lab", -50), ("office", -70), ("lab", -80)];
+        let order: Vec<&str> = select_networks(&known, &visible).iter().map(|n| n.ssid()).collect();
+        assert_eq!(order, ["lab", "office", "home", "hidden"]);
+    }
+}
@@ -0,0 +1,454 @@
+//! 切断時に自動で再接続する Wi-Fi の接続管理
+//!
+//! `WifiManager` はバックグラウンドのスレッドでステーションとして接続し、
+//! 保存した接続先のうち周囲に見えるものを優先度と受信強度の順に試します。
+//! 接続がタイムアウトしたり切れたりすると、間隔を倍々に延ばしながら (指数バックオフ) 接続し直します。
+//! 接続状態は `state` で取得するか、`subscribe` で変化を受け取れます。
+//!
+//! ## 使用例
+//!
+//! ```no_run
+//! # fn example(
+//! #     wifi: esp_idf_svc::wifi::EspWifi<'static>,
+//! #     sys_loop: esp_idf_svc::eventloop::EspSystemEventLoop,
+//! #     store: esp32s2_common_lib::wifi_credentials::CredentialStore,
+//! # ) -> anyhow::Result<()> {
+//! use std::time::Duration;
+//! use esp32s2_common_lib::wifi_manager::{WifiManager, WifiManagerConfig};
+//!
+//! let networks = store.load_or(None)?;
+//! let manager = WifiManager::start(wifi, &sys_loop, networks, WifiManagerConfig::default())?;
+//! let states = manager.subscribe();
+//! match manager.wait_connected(Duration::from_secs(30)) {
+//!     Some(ip) => log::info!("IP: {}", ip),
+//!     None => log::warn!("not connected yet: {}", manager.state()),
+//! }
+//! // メインループで状態の変化を受け取る
+//! while let Ok(state) = states.try_recv() {
+//!     log::info!("Wi-Fi: {}", state);
+//! }
+//! # Ok(())
+//! # }
+//! ```
+
+use core::fmt;
+use std::net::Ipv4Addr;
+use std::sync::{mpsc, Arc, Condvar, Mutex};
+use std::thread;
+use std::time::{Duration, Instant};
+
+use esp_idf_svc::eventloop::{EspSubscription, EspSystemEventLoop, System};
+use esp_idf_svc::sys::{esp, esp_wifi_sta_get_ap_info, wifi_ap_record_t};
+use esp_idf_svc::wifi::{ClientConfiguration, Configuration, EspWifi, ScanConfig, WifiEvent};
+
+use crate::wifi_credentials::{select_networks, Credentials, KnownNetwork};
+use crate::widgets::status_bar::WifiIndicator;
+
+/// 接続を管理するスレッドのスタックサイズ (バイト)
+///
+/// スキャン結果の処理や esp-idf の呼び出し、ログの整形をこのスレッドで行う
+const WORKER_STACK_SIZE: usize = 8192;
+
+/// 接続状態
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum WifiState {
+    /// 停止中
+    Stopped,
+    /// `ssid` に接続中 (何回目の試行か)
+    Connecting { ssid: String, attempt: u32 },
+    /// `ssid` に接続済み
+    Connected { ssid: String, ip: Ipv4Addr },
+    /// 接続に失敗し、`delay` 後に再試行する
+    Backoff { attempt: u32, delay: Duration },
+}
+
+impl WifiState {
+    /// 接続済みかどうか
+    pub fn is_connected(&self) -> bool {
+        matches!(self, WifiState::Connected { .. })
+    }
+
+    /// 接続済みなら IP アドレスを返します。
+    pub fn ip(&self) -> Option<Ipv4Addr> {
+        match self {
+            WifiState::Connected { ip, .. } => Some(*ip),
+            _ => None,
+        }
+    }
+
+    /// 接続済みなら SSID を返します。
+    pub fn ssid(&self) -> Option<&str> {
+        match self {
+            WifiState::Connected { ssid, .. } => Some(ssid),
+            _ => None,
+        }
+    }
+}
+
+impl fmt::Display for WifiState {
+    /// OLED に表示できる短い文字列
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            WifiState::Stopped => write!(f, "stopped"),
+            WifiState::Connecting { ssid, attempt } => write!(f, "connecting {} ({})", ssid, attempt),
+            WifiState::Connected { ssid, ip } => write!(f, "{} {}", ssid, ip),
+            WifiState::Backoff { delay, .. } => write!(f, "retry in {}s", delay.as_secs()),
+        }
+    }
+}
+
+/// 再試行の間隔 (指数バックオフ)
+///
+/// `next_delay` を呼ぶたびに間隔が倍になり、`max` で頭打ちになります。
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Backoff {
+    initial: Duration,
+    max: Duration,
+    next: Duration,
+}
+
+impl Backoff {
+    /// 最初の間隔 `initial` から、最大 `max` まで延ばすバックオフを作成します。
+    pub fn new(initial: Duration, max: Duration) -> Self {
+        Self {
+            initial,
+            max,
+            next: initial.min(max),
+        }
+    }
+
+    /// 次の再試行までの間隔を返し、その次の間隔を倍にします。
+    pub fn next_delay(&mut self) -> Duration {
+        let delay = self.next;
+        self.next = (self.next * 2).min(self.max);
+        delay
+    }
+
+    /// 接続に成功したときに、間隔を最初の値に戻します。
+    pub fn reset(&mut self) {
+        self.next = self.initial.min(self.max);
+    }
+}
+
+impl Default for Backoff {
+    /// 1 秒から最大 60 秒
+    fn default() -> Self {
+        Self::new(Duration::from_secs(1), Duration::from_secs(60))
+    }
+}
+
+/// `WifiManager` の設定
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct WifiManagerConfig {
+    connect_timeout: Duration,
+    backoff: Backoff,
+}
+
+impl WifiManagerConfig {
+    /// 1回の接続 (IP アドレスの取得まで) を待つ時間を設定します (デフォルトは 15 秒)。
+    pub fn with_connect_timeout(mut self, timeout: Duration) -> Self {
+        self.connect_timeout = timeout;
+        self
+    }
+
+    /// 再試行の間隔を設定します。
+    pub fn with_backoff(mut self, backoff: Backoff) -> Self {
+        self.backoff = backoff;
+        self
+    }
+}
+
+impl Default for WifiManagerConfig {
+    fn default() -> Self {
+        Self {
+            connect_timeout: Duration::from_secs(15),
+            backoff: Backoff::default(),
+        }
+    }
+}
+
+/// 接続を管理するスレッドへの通知
+enum Command {
+    /// Wi-Fi のイベントループから届いた切断
+    StaDisconnected,
+    /// `WifiManager` の停止
+    Stop,
+}
+
+/// 接続を管理するスレッドと共有する状態
+struct Shared {
+    state: Mutex<WifiState>,
+    changed: Condvar,
+    subscribers: Mutex<Vec<mpsc::Sender<WifiState>>>,
+}
+
+impl Shared {
+    fn set_state(&self, state: WifiState) {
+        {
+            let mut current = self.state.lock().unwrap();
+            if *current == state {
+                return;
+            }
+            *current = state.clone();
+        }
+        log::info!("Wi-Fi: {}", state);
+        self.changed.notify_all();
+        // 受け取り側が破棄された購読は取り除く
+        self.subscribers
+            .lock()
+            .unwrap()
+            .retain(|subscriber| subscriber.send(state.clone()).is_ok());
+    }
+}
+
+/// 切断時に自動で再接続する Wi-Fi の接続管理
+pub struct WifiManager {
+    shared: Arc<Shared>,
+    commands: mpsc::Sender<Command>,
+    worker: Option<thread::JoinHandle<EspWifi<'static>>>,
+    _subscription: EspSubscription<'static, System>,
+}
+
+impl WifiManager {
+    /// `networks` のいずれかへの接続をバックグラウンドで開始します。
+    ///
+    /// 毎回周囲をスキャンし、`select_networks` の順に接続を試します。すべて失敗するとバックオフします。
+    /// 接続を待たずに戻ります。接続を待つには `wait_connected` を使ってください。
+    pub fn start(
+        mut wifi: EspWifi<'static>,
+        sys_loop: &EspSystemEventLoop,
+        networks: Vec<KnownNetwork>,
+        config: WifiManagerConfig,
+    ) -> anyhow::Result<Self> {
+        if networks.is_empty() {
+            anyhow::bail!("No Wi-Fi networks to connect to");
+        }
+        wifi.set_configuration(&Configuration::Client(ClientConfiguration::default()))?;
+        if !wifi.is_started()? {
+            wifi.start()?;
+        }
+
+        let (commands, receiver) = mpsc::channel();
+        let events = commands.clone();
+        let subscription = sys_loop.subscribe::<WifiEvent, _>(move |event| {
+            if matches!(event, WifiEvent::StaDisconnected(..)) {
+                let _ = events.send(Command::StaDisconnected);
+            }
+        })?;
+
+        let shared = Arc::new(Shared {
+            state: Mutex::new(WifiState::Stopped),
+            changed: Condvar::new(),
+            subscribers: Mutex::new(Vec::new()),
+        });
+        let worker_shared = shared.clone();
+        let worker = thread::Builder::new()
+            .name("wifi_manager".to_string())
+            .stack_size(WORKER_STACK_SIZE)
+            .spawn(move || run(wifi, networks, config, &worker_shared, &receiver))?;
+
+        Ok(Self {
+            shared,
+            commands,
+            worker: Some(worker),
+            _subscription: subscription,
+        })
+    }
+
+    /// 現在の接続状態
+    pub fn state(&self) -> WifiState {
+        self.shared.state.lock().unwrap().clone()
+    }
+
+    /// 接続済みなら IP アドレスを返します。
+    pub fn ip(&self) -> Option<Ipv4Addr> {
+        self.state().ip()
+    }
+
+    /// 接続中のアクセスポイントの受信強度 (dBm)。接続していなければ `None` を返します。
+    pub fn rssi(&self) -> Option<i8> {
+        if !self.state().is_connected() {
+            return None;
+        }
+        let mut info = wifi_ap_record_t::default();
+        // 接続中のアクセスポイントの情報を取得する (EspWifi には RSSI を取得する API がない)
+        esp!(unsafe { esp_wifi_sta_get_ap_info(&mut info) }).ok()?;
+        Some(info.rssi)
+    }
+
+    /// `StatusBar` に表示する Wi-Fi アイコンの内容
+    pub fn indicator(&self) -> WifiIndicator {
+        match self.rssi() {
+            Some(rssi) => WifiIndicator::Connected { rssi },
+            None => WifiIndicator::Disconnected,
+        }
+    }
+
+    /// 接続するまで最大 `timeout` 待ち、IP アドレスを返します。
+    ///
+    /// 時間内に接続できなければ `None` を返します (バックグラウンドでの再試行は続きます)。
+    pub fn wait_connected(&self, timeout: Duration) -> Option<Ipv4Addr> {
+        let state = self.shared.state.lock().unwrap();
+        let (state, _) = self
+            .shared
+            .changed
+            .wait_timeout_while(state, timeout, |state| !state.is_connected())
+            .unwrap();
+        state.ip()
+    }
+
+    /// 状態が変化するたびに新しい状態を受け取るチャンネルを返します。
+    ///
+    /// 最初に現在の状態を1回受け取ります。
+    pub fn subscribe(&self) -> mpsc::Receiver<WifiState> {
+        let (sender, receiver) = mpsc::channel();
+        let mut subscribers = self.shared.subscribers.lock().unwrap();
+        let _ = sender.send(self.state());
+        subscribers.push(sender);
+        receiver
+    }
+
+    /// 再接続を止めて切断し、`EspWifi` を返します。
+    ///
+    /// 設定用のポータル (`provisioning`) などに Wi-Fi を渡すときに使います。
+    pub fn stop(mut self) -> EspWifi<'static> {
+        self.stop_worker().expect("Wi-Fi manager worker panicked")
+    }
+
+    fn stop_worker(&mut self) -> Option<EspWifi<'static>> {
+        let worker = self.worker.take()?;
+        let _ = self.commands.send(Command::Stop);
+        let wifi = worker.join().ok();
+        self.shared.set_state(WifiState::Stopped);
+        wifi
+    }
+}
+
+impl Drop for WifiManager {
+    fn drop(&mut self) {
+        self.stop_worker();
+    }
+}
+
+/// 接続を管理するスレッドの本体。停止すると `EspWifi` を返す。
+fn run(
+    mut wifi: EspWifi<'static>,
+    networks: Vec<KnownNetwork>,
+    config: WifiManagerConfig,
+    shared: &Shared,
+    commands: &mpsc::Receiver<Command>,
+) -> EspWifi<'static> {
+    let mut backoff = config.backoff;
+    let mut attempt = 0;
+    loop {
+        attempt += 1;
+        let mut connected = None;
+        for network in candidates(&mut wifi, &networks) {
+            if stop_requested(commands) {
+                return disconnect(wifi);
+            }
+            let ssid = network.ssid().to_string();
+            shared.set_state(WifiState::Connecting { ssid: ssid.clone(), attempt });
+            match connect_once(&mut wifi, &network.credentials, config.connect_timeout) {
+                Ok(ip) => {
+                    connected = Some((ssid, ip));
+                    break;
+                }
+                Err(e) => log::warn!("Wi-Fi connection to {} failed (attempt {}): {:?}", ssid, attempt, e),
+            }
+        }
+
+        match connected {
+            Some((ssid, ip)) => {
+                attempt = 0;
+                backoff.reset();
+                // 接続を試している間に届いた古い切断の通知を捨てる
+                if stop_requested(commands) {
+                    return disconnect(wifi);
+                }
+                if !wifi.is_connected().unwrap_or(false) {
+                    continue;
+                }
+                shared.set_state(WifiState::Connected { ssid, ip });
+                match commands.recv() {
+                    Ok(Command::StaDisconnected) => log::warn!("Wi-Fi disconnected, reconnecting"),
+                    Ok(Command::Stop) | Err(_) => return disconnect(wifi),
+                }
+            }
+            None => {
+                let delay = backoff.next_delay();
+                shared.set_state(WifiState::Backoff { attempt, delay });
+                // 待っている間も停止だけは受け付ける
+                let deadline = Instant::now() + delay;
+                loop {
+                    let remaining = deadline.saturating_duration_since(Instant::now());
+                    match commands.recv_timeout(remaining) {
+                        Ok(Command::StaDisconnected) => {}
+                        Ok(Command::Stop) | Err(mpsc::RecvTimeoutError::Disconnected) => return disconnect(wifi),
+                        Err(mpsc::RecvTimeoutError::Timeout) => break,
+                    }
+                }
+            }
+        }
+    }
+}
+
+/// 届いている通知を捨て、停止が要求されていれば `true` を返す
+fn stop_requested(commands: &mpsc::Receiver<Command>) -> bool {
+    loop {
+        match commands.try_recv() {
+            Ok(Command::StaDisconnected) => {}
+            Ok(Command::Stop) | Err(mpsc::TryRecvError::Disconnected) => return true,
+            Err(mpsc::TryRecvError::Empty) => return false,
+        }
+    }
+}
+
+/// 周囲のネットワークをスキャンし、接続を試す順に接続先を並べる
+fn candidates<'a>(wifi: &mut EspWifi<'static>, networks: &'a [KnownNetwork]) -> Vec<&'a KnownNetwork> {
+    // 1件だけならスキャンしても順番は変わらない
+    if networks.len() <= 1 {
+        return networks.iter().collect();
+    }
+    let found = match wifi
+        .start_scan(&ScanConfig::default(), true)
+        .and_then(|_| wifi.get_scan_result())
+    {
+        Ok(found) => found,
+        Err(e) => {
+            log::warn!("Wi-Fi scan failed: {:?}", e);
+            Vec::new()
+        }
+    };
+    let visible: Vec<(&str, i8)> = found.iter().map(|ap| (ap.ssid.as_str(), ap.signal_strength)).collect();
+    select_networks(networks, &visible)
+}
+
+/// `credentials` のネットワークに1回接続を試し、`timeout` 以内に IP アドレスが割り当てられればそれを返す
+fn connect_once(
+    wifi: &mut EspWifi<'static>,
+    credentials: &Credentials,
+    timeout: Duration,
+) -> anyhow::Result<Ipv4Addr> {
+    let deadline = Instant::now() + timeout;
+    wifi.set_configuration(&Configuration::Client(credentials.client_configuration()?))?;
+    wifi.connect()?;
+    loop {
+        if wifi.is_connected()? {
+            let ip = wifi.sta_netif().get_ip_info()?.ip;
+            if !ip.is_unspecified() {
+                return Ok(ip);
+            }
+        }
+        if Instant::now() >= deadline {
+            let _ = wifi.disconnect();
+            anyhow::bail!("Wi-Fi connection timed out after {:?}", timeout);
+        }
+        thread::sleep(Duration::from_millis(100));
+    }
+}
+
+fn disconnect(mut wifi: EspWifi<'static>) -> EspWifi<'static> {
+    let _ = wifi.disconnect();
+    wifi
+}
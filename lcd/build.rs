@@ -1,3 +1,27 @@
+use esp32s2_asset_builder::{AssetBuilder, FontSubset};
+
 fn main() {
     embuild::espidf::sysenv::output();
+
+    // assets/ の画像とフォントを OUT_DIR/assets.rs に変換する
+    AssetBuilder::new()
+        .image("LOGO", "assets/logo.pbm")
+        .animation("SPINNER", &[
+            ("assets/spinner_0.pbm", 80),
+            ("assets/spinner_1.pbm", 80),
+            ("assets/spinner_2.pbm", 80),
+            ("assets/spinner_3.pbm", 80),
+            ("assets/spinner_4.pbm", 80),
+            ("assets/spinner_5.pbm", 80),
+            ("assets/spinner_6.pbm", 80),
+            ("assets/spinner_7.pbm", 80),
+        ])
+        // src/ の文字列リテラルで使っている文字と ASCII・かなだけをフォントから取り出す
+        .font(
+            "FONT_JA",
+            &["assets/fonts/sample_ja_8x8.bdf", "assets/fonts/5x7_ascii.bdf"],
+            FontSubset::new().ascii().kana().scan_sources("src"),
+        )
+        .write("assets.rs")
+        .expect("Failed to convert assets");
 }
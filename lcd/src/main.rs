@@ -1,14 +1,31 @@
+use std::time::{Duration, Instant};
+
 use embedded_graphics::{
-    mono_font::{ascii::* , MonoTextStyle},
-    pixelcolor::{BinaryColor},
+    image::Image,
+    mono_font::{ascii::FONT_5X7, MonoTextStyle},
+    pixelcolor::BinaryColor,
     prelude::*,
-    primitives::{PrimitiveStyleBuilder, Rectangle},
-    text::Text,
+    primitives::Rectangle,
+    text::{Alignment, Text},
 };
 use esp_idf_svc::hal::delay::FreeRtos;
 use esp_idf_svc::hal::prelude::Peripherals;
 
-use esp32s2_common_lib::sh1106_display::set_sh1106_display;
+use esp32s2_common_lib::animation::AnimationPlayer;
+use esp32s2_common_lib::render_loop::FrameTimer;
+use esp32s2_common_lib::buffered_display::Controller;
+use esp32s2_common_lib::mono_display::MonoDisplay;
+use esp32s2_common_lib::sh1106_display::set_buffered_display;
+use esp32s2_common_lib::widgets::{
+    labeled_value::LabeledValue,
+    progress_bar::ProgressBar,
+    status_bar::StatusBar,
+};
+
+/// build.rs で assets/ の画像・フォントから生成した定数
+mod assets {
+    include!(concat!(env!("OUT_DIR"), "/assets.rs"));
+}
 
 fn main() -> anyhow::Result<()> {
     esp_idf_svc::sys::link_patches();
@@ -26,7 +43,8 @@ fn main() -> anyhow::Result<()> {
     let cs_pin = peripherals.pins.gpio34;
     let spi_peripheral = peripherals.spi2;
 
-    let (mut display, _rst_driver) = set_sh1106_display(
+    let (mut display, _rst_driver) = set_buffered_display(
+        Controller::from_build_env(option_env!("DISPLAY_CONTROLLER"))?,
         rst_pin,
         dc_pin,
         sclk_pin, 
@@ -36,48 +54,99 @@ fn main() -> anyhow::Result<()> {
     )?;
 
     // 8. 描画処理
+    // 起動ロゴ (assets/logo.pbm をビルド時に変換したもの)
     // `clear`は引数を取らず、エラーも返さない
     display.clear();
 
-   let style = PrimitiveStyleBuilder::new()
-       .stroke_color(BinaryColor::On)
-       .stroke_width(1)
-       .build();
-
-    Rectangle::new(Point::new(2, 2), Size::new(126, 60))
-        .into_styled(style)
+    let width = display.size().width as i32;
+    let logo_x = (width - assets::LOGO.size().width as i32) / 2;
+    Image::new(&assets::LOGO, Point::new(logo_x, 0))
         .draw(&mut display)
-        .map_err(|e| anyhow::anyhow!("Draw rectangle error: {:?}", e))?;
+        .map_err(|e| anyhow::anyhow!("Draw logo error: {:?}", e))?;
+    Text::with_alignment(
+        "lcd_example",
+        Point::new(width / 2, 56),
+        MonoTextStyle::new(&FONT_5X7, BinaryColor::On),
+        Alignment::Center,
+    )
+    .draw(&mut display)
+    .map_err(|e| anyhow::anyhow!("Draw text error: {:?}", e))?;
 
-    let text_style = MonoTextStyle::new(&FONT_5X7, BinaryColor::On);
-    Text::new("Hello OLED!", Point::new(10, 25), text_style)
-        .draw(&mut display)
-        .map_err(|e| anyhow::anyhow!("Draw text error: {:?}", e))?;
+    display.flush().map_err(|e| anyhow::anyhow!("Display flush error: {:?}", e))?;
+    FreeRtos::delay_ms(1000);
 
+    // 日本語の表示 (assets/fonts の BDF からビルド時に取り出したフォント)
+    display.clear();
+    let line_height = assets::FONT_JA.line_height() as i32;
+    let greeting = "こんにちは 世界";
+    let greeting_x = (width - assets::FONT_JA.text_width(greeting) as i32) / 2;
+    display.draw_text(greeting, Point::new(greeting_x, 24), &assets::FONT_JA);
+    display.draw_text("lcd_example", Point::new(greeting_x, 24 + line_height * 2), &assets::FONT_JA);
     display.flush().map_err(|e| anyhow::anyhow!("Display flush error: {:?}", e))?;
+    FreeRtos::delay_ms(1000);
+
+    // 右上で回り続けるスピナー (assets/spinner_*.pbm)
+    let content = StatusBar::content_area(display.size());
+    let mut spinner = AnimationPlayer::new(
+        &assets::SPINNER,
+        Point::new(content.top_left.x + content.size.width as i32 - 18, content.top_left.y + 2),
+    )
+    .with_loop(true);
 
     let mut sec = 0;
+    let mut last_tick = Instant::now();
+    let mut redraw = true;
+    // 描画と転送の時間を差し引いて 25fps で回す
+    let mut timer = FrameTimer::new(25);
     loop {
-        display.clear();
-
-        let style = PrimitiveStyleBuilder::new()
-        .stroke_color(BinaryColor::On)
-        .stroke_width(1)
-        .build();
-
-        Rectangle::new(Point::new(2, 2), Size::new(3, 3))
-        .into_styled(style)
-        .draw(&mut display)
-        .map_err(|e| anyhow::anyhow!("Draw rectangle error: {:?}", e))?;
-
-        let text_style = MonoTextStyle::new(&FONT_5X7, BinaryColor::On);
-        Text::new(format!("SEC {}", sec).as_str(), Point::new(10, 25), text_style)
-        .draw(&mut display)
-        .map_err(|e| anyhow::anyhow!("Draw text error: {:?}", e))?;
-
-        display.flush().map_err(|e| anyhow::anyhow!("Display flush error: {:?}", e))?;
-        sec += 1;
-        sec = sec % 60;
-        FreeRtos::delay_ms(1000);
+        timer.begin_frame();
+        if spinner.update() {
+            redraw = true;
+        }
+        if last_tick.elapsed() >= Duration::from_secs(1) {
+            last_tick += Duration::from_secs(1);
+            sec += 1;
+            sec = sec % 60;
+            redraw = true;
+        }
+
+        if redraw {
+            display.clear();
+
+            StatusBar::new("lcd_example")
+                .draw(&mut display)
+                .map_err(|e| anyhow::anyhow!("Draw status bar error: {:?}", e))?;
+
+            LabeledValue::new("SEC", format!("{}", sec).as_str(), content.top_left + Point::new(2, 4), content.size.width - 24)
+                .draw(&mut display)
+                .map_err(|e| anyhow::anyhow!("Draw text error: {:?}", e))?;
+
+            spinner
+                .draw(&mut display)
+                .map_err(|e| anyhow::anyhow!("Draw animation error: {:?}", e))?;
+
+            ProgressBar::from_range(
+                Rectangle::new(content.top_left + Point::new(2, 22), Size::new(content.size.width - 4, 8)),
+                sec as f32,
+                0.0,
+                59.0,
+            )
+            .draw(&mut display)
+            .map_err(|e| anyhow::anyhow!("Draw progress bar error: {:?}", e))?;
+
+            let stats = timer.stats();
+            LabeledValue::new(
+                "FLUSH",
+                format!("{}us", stats.flush_time.as_micros()).as_str(),
+                content.top_left + Point::new(2, 34),
+                content.size.width - 4,
+            )
+            .draw(&mut display)
+            .map_err(|e| anyhow::anyhow!("Draw text error: {:?}", e))?;
+
+            timer.flush(&mut display).map_err(|e| anyhow::anyhow!("Display flush error: {:?}", e))?;
+            redraw = false;
+        }
+        timer.end_frame();
     }
 }
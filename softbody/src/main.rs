@@ -1,8 +1,8 @@
 use embedded_graphics::{
     mono_font::{ascii::* , MonoTextStyle},
-    pixelcolor::BinaryColor,
+    pixelcolor::{BinaryColor, Gray8},
     prelude::*,
-    primitives::{Circle, PrimitiveStyleBuilder, Rectangle},
+    primitives::{Circle, PrimitiveStyle, PrimitiveStyleBuilder, Rectangle},
     text::Text,
 };
 use esp_idf_svc::hal::delay::FreeRtos;
@@ -14,8 +14,41 @@ use esp_idf_svc::hal::units::FromValueType;
 
 use softbody::core::{Simulation, SimulationConfig, SoftBodyConfig, Vec2};
 
-use esp32s2_common_lib::sh1106_display::set_sh1106_display;
+use esp32s2_common_lib::buffered_display::Controller;
+use esp32s2_common_lib::dither::{BayerMatrix, OrderedDither};
+use esp32s2_common_lib::sh1106_display::set_buffered_display;
 use esp32s2_common_lib::mma7660fc::{Mode, DEFAULT_I2C_ADDRESS, Mma7660fc};
+use esp32s2_common_lib::render_loop::{FpsOverlay, FrameTimer};
+
+/// 目標のフレームレート (1フレームでシミュレーションを 1/40 秒進める)
+const TARGET_FPS: u32 = 40;
+/// 右上にフレームレートを表示する
+const SHOW_FPS: bool = true;
+/// 粒子の混み具合 (圧力の目安) を塗りの濃さで表す
+const SHADE_PRESSURE: bool = true;
+/// この距離より近い粒子を押し合っているとみなす
+const PRESSURE_RADIUS: f64 = 5.0;
+
+/// 粒子ごとに、近くにある粒子の数から塗りの明るさを求める
+fn pressure_shades(sim: &Simulation) -> Vec<u8> {
+    let particles = &sim.particles;
+    particles
+        .iter()
+        .map(|p| {
+            let neighbors = particles
+                .iter()
+                .filter(|q| {
+                    let dx = q.pos.x - p.pos.x;
+                    let dy = q.pos.y - p.pos.y;
+                    dx * dx + dy * dy < PRESSURE_RADIUS * PRESSURE_RADIUS
+                })
+                .count()
+                .saturating_sub(1); // 自分自身を除く
+            // 離れていても見えるよう最低限の明るさを残す
+            (64 + neighbors * 32).min(255) as u8
+        })
+        .collect()
+}
 
 fn create_simulation02_small() -> Simulation {
     let sim_width = 126.0;
@@ -73,7 +106,8 @@ fn main() -> anyhow::Result<()> {
     let cs_pin = peripherals.pins.gpio34;
     let spi_peripheral = peripherals.spi2;
 
-    let (mut display, _rst_driver) = set_sh1106_display(
+    let (mut display, _rst_driver) = set_buffered_display(
+        Controller::from_build_env(option_env!("DISPLAY_CONTROLLER"))?,
         rst_pin,
         dc_pin,
         sclk_pin, 
@@ -141,7 +175,10 @@ fn main() -> anyhow::Result<()> {
     let mut sim = create_simulation02_small();
     //sim.add_soft_body(&fixed_anchor);
 
+    // SPI の転送時間によらず一定の間隔でシミュレーションを進める
+    let mut timer = FrameTimer::new(TARGET_FPS);
     loop {
+        timer.begin_frame();
         display.clear();
         match sensor.get_acceleration() {
             Ok(accel) => {
@@ -149,28 +186,41 @@ fn main() -> anyhow::Result<()> {
                 log::info!("加速度: x={}, y={}, z={}", accel.x, accel.y, accel.z);
                 let new_gravity = Vec2::new(-accel.y as f64, -accel.z as f64) * 50.0;
                 sim.config_mut().gravity = new_gravity;
-                let style = PrimitiveStyleBuilder::new()
-                    .stroke_color(BinaryColor::On)
-                    .stroke_width(1)
-                    .build();
-
-                sim.step(0.025);
-
-                for p in &sim.particles {
-                    Circle::new(Point::new(p.pos.x as i32, p.pos.y as i32), 4)
-                    .into_styled(style)
-                    .draw(&mut display)
-                    .map_err(|e| anyhow::anyhow!("Draw text error: {:?}", e))?;
-                }
-
-                display
-                    .flush()
-                    .map_err(|e| anyhow::anyhow!("Display flush error: {:?}", e))?;
             }
             Err(e) => {
                 log::error!("加速度の読み取りに失敗しました: {:?}", e);
             }
         }
-        FreeRtos::delay_ms(5);
+
+        sim.step(timer.frame_duration().as_secs_f64());
+
+        if SHADE_PRESSURE {
+            let shades = pressure_shades(&sim);
+            let mut target = OrderedDither::new(&mut display, BayerMatrix::Bayer2);
+            for (p, shade) in sim.particles.iter().zip(shades) {
+                Circle::new(Point::new(p.pos.x as i32, p.pos.y as i32), 5)
+                    .into_styled(PrimitiveStyle::with_fill(Gray8::new(shade)))
+                    .draw(&mut target)
+                    .map_err(|e| anyhow::anyhow!("Draw particle error: {:?}", e))?;
+            }
+        } else {
+            for p in &sim.particles {
+                Circle::new(Point::new(p.pos.x as i32, p.pos.y as i32), 4)
+                .into_styled(style)
+                .draw(&mut display)
+                .map_err(|e| anyhow::anyhow!("Draw text error: {:?}", e))?;
+            }
+        }
+
+        if SHOW_FPS {
+            FpsOverlay::new(timer.stats())
+                .draw(&mut display)
+                .map_err(|e| anyhow::anyhow!("Draw FPS error: {:?}", e))?;
+        }
+
+        timer
+            .flush(&mut display)
+            .map_err(|e| anyhow::anyhow!("Display flush error: {:?}", e))?;
+        timer.end_frame();
     }
 }
@@ -1,10 +1,29 @@
+use std::io::Write;
+use std::path::Path;
+
+use flate2::{write::GzEncoder, Compression};
 
 fn main() {
     embuild::espidf::sysenv::output();
     let _ = dotenvy::from_filename(".env");
+    // .env やシェルの SSID を変えたら埋め込む値を更新する
+    println!("cargo:rerun-if-changed=.env");
 
-    for (key, value) in std::env::vars() {
-        println!("cargo:rustc-env={}={}", key, value);
-        //if key.starts_with("API_") || key.starts_with("APP_") { }
+    // Wi-Fi の接続先の初期値 (NVS に保存されていないときだけ使う) とディスプレイのコントローラ。
+    // ほかの環境変数は埋め込まない
+    for key in ["SSID", "SSID_PASSWORD", "DISPLAY_CONTROLLER"] {
+        println!("cargo:rerun-if-env-changed={}", key);
+        if let Ok(value) = std::env::var(key) {
+            println!("cargo:rustc-env={}={}", key, value);
+        }
     }
+
+    // 操作パネル (web/index.html) を gzip で圧縮して埋め込む (src/http_api.rs の `/`)
+    println!("cargo:rerun-if-changed=web/index.html");
+    let html = std::fs::read("web/index.html").expect("read web/index.html");
+    let mut encoder = GzEncoder::new(Vec::new(), Compression::best());
+    encoder.write_all(&html).expect("compress web/index.html");
+    let gzip = encoder.finish().expect("compress web/index.html");
+    let out_dir = std::env::var("OUT_DIR").expect("OUT_DIR");
+    std::fs::write(Path::new(&out_dir).join("index.html.gz"), gzip).expect("write index.html.gz");
 }
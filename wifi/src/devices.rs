@@ -0,0 +1,134 @@
+//! TCP サーバーと HTTP API から操作するデバイス
+//!
+//! どちらのサーバー (と WebSocket の配信スレッド) も別のスレッドから操作するので、
+//! `Arc<Mutex<Devices>>` で共有します。
+
+use esp_idf_svc::hal::gpio::{Gpio2, Gpio3, Output, PinDriver};
+use esp_idf_svc::hal::i2c::I2cDriver;
+use esp_idf_svc::hal::rmt::{FixedLengthSignal, PinState, Pulse, TxRmtDriver};
+
+use sh1106::interface::DisplayInterface;
+
+use esp32s2_common_lib::buffered_display::BufferedDisplay;
+use esp32s2_common_lib::mma7660fc::{Acceleration, Mma7660fc, Tilt};
+use esp32s2_common_lib::wifi_credentials::CredentialStore;
+use esp32s2_common_lib::wifi_manager::WifiManager;
+
+use std::time::Duration;
+
+/// コマンドで操作するデバイス
+pub struct Devices<DI> {
+    pub led1: PinDriver<'static, Gpio2, Output>,
+    pub led2: PinDriver<'static, Gpio3, Output>,
+    pub neopixel_tx: TxRmtDriver<'static>,
+    /// NeoPixel に最後に設定した色
+    pub pixel: Rgb,
+    pub display: BufferedDisplay<DI>,
+    /// 加速度センサー (接続されていなければ `None`)
+    pub sensor: Option<Mma7660fc<I2cDriver<'static>>>,
+    pub credential_store: CredentialStore,
+    pub wifi_manager: WifiManager,
+}
+
+impl<DI> Devices<DI>
+where
+    DI: DisplayInterface,
+{
+    /// LED (1 または 2) を点灯・消灯する
+    pub fn set_led(&mut self, index: u8, on: bool) -> anyhow::Result<()> {
+        match (index, on) {
+            (1, true) => self.led1.set_high()?,
+            (1, false) => self.led1.set_low()?,
+            (2, true) => self.led2.set_high()?,
+            (2, false) => self.led2.set_low()?,
+            _ => anyhow::bail!("Invalid LED: {}", index),
+        }
+        Ok(())
+    }
+
+    /// LED 1, 2 が点灯しているかどうか
+    pub fn leds(&self) -> [bool; 2] {
+        [self.led1.is_set_high(), self.led2.is_set_high()]
+    }
+
+    /// NeoPixel の色を設定する
+    pub fn set_pixel(&mut self, rgb: Rgb) -> anyhow::Result<()> {
+        neopixel(rgb, &mut self.neopixel_tx)?;
+        self.pixel = rgb;
+        Ok(())
+    }
+
+    /// 加速度を読み取る。センサーが接続されていなければ `None` を返す
+    pub fn acceleration(&mut self) -> anyhow::Result<Option<Acceleration>> {
+        let Some(sensor) = self.sensor.as_mut() else {
+            return Ok(None);
+        };
+        let accel = sensor
+            .get_acceleration()
+            .map_err(|e| anyhow::anyhow!("Accelerometer error: {:?}", e))?;
+        Ok(Some(accel))
+    }
+
+    /// 向き・表裏・タップ・揺れを読み取る。センサーが接続されていなければ `None` を返す
+    pub fn tilt(&mut self) -> anyhow::Result<Option<Tilt>> {
+        let Some(sensor) = self.sensor.as_mut() else {
+            return Ok(None);
+        };
+        let tilt = sensor
+            .get_tilt()
+            .map_err(|e| anyhow::anyhow!("Accelerometer error: {:?}", e))?;
+        Ok(Some(tilt))
+    }
+}
+
+fn neopixel(rgb: Rgb, tx: &mut TxRmtDriver) -> anyhow::Result<()> {
+    let color: u32 = rgb.into();
+    let ticks_hz = tx.counter_clock()?;
+
+    let (t0h, t0l, t1h, t1l) = (
+        Pulse::new_with_duration(ticks_hz, PinState::High, &Duration::from_nanos(350))?,
+        Pulse::new_with_duration(ticks_hz, PinState::Low, &Duration::from_nanos(800))?,
+        Pulse::new_with_duration(ticks_hz, PinState::High, &Duration::from_nanos(700))?,
+        Pulse::new_with_duration(ticks_hz, PinState::Low, &Duration::from_nanos(600))?,
+    );
+
+    let mut signal = FixedLengthSignal::<24>::new();
+
+    for i in (0..24).rev() {
+        let p = 2_u32.pow(i);
+        let bit: bool = p & color != 0;
+
+        let (high_pulse, low_pulse) = if bit { (t1h, t1l) } else { (t0h, t0l) };
+
+        signal.set(23 - i as usize, &(high_pulse, low_pulse))?;
+    }
+
+    tx.start_blocking(&signal)?;
+
+    Ok(())
+}
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct Rgb {
+    pub r: u8,
+    pub g: u8,
+    pub b: u8,
+}
+
+impl Rgb {
+    pub fn new(r: u8, g: u8, b: u8) -> Self {
+        Self { r, g, b }
+    }
+}
+
+impl From<Rgb> for u32 {
+    /// Convert RGB to u32 color value
+    ///
+    /// e.g. rgb: (1,2,4)
+    /// G R B
+    /// 7 0 7 0 7 0
+    /// 00000010 00000001 00000100
+    fn from(rgb: Rgb) -> Self {
+        ((rgb.g as u32) << 16) | ((rgb.r as u32) << 8) | rgb.b as u32
+    }
+}
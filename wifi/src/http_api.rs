@@ -0,0 +1,228 @@
+//! JSON で LED・NeoPixel・加速度センサーを操作する HTTP API
+//!
+//! TCP サーバーのコマンドと同じデバイスを `Arc<Mutex<Devices>>` で共有します。
+//! curl やダッシュボードから使えるよう、リクエストとレスポンスはすべて JSON です。
+//!
+//! | メソッド | パス | 内容 |
+//! | --- | --- | --- |
+//! | GET | `/` | ブラウザ用の操作パネル (`web/index.html`) |
+//! | GET | `/status` | Wi-Fi・LED・NeoPixel・センサー・時刻の状態 |
+//! | POST | `/led` | `{"led": 1, "on": true}` で LED を点灯・消灯 |
+//! | POST | `/neopixel` | `{"r": 255, "g": 0, "b": 0}` で NeoPixel の色を設定 |
+//! | GET | `/accel` | 加速度 `{"x", "y", "z"}` (センサー未接続なら 503) |
+//! | GET | `/time` | SNTP で同期した時刻 |
+//! | WebSocket | `/ws` | 加速度と向き・タップ・揺れのイベントを配信 (`sensor_stream`) |
+//!
+//! エラーのときは `{"error": "..."}` を返します。
+
+use std::net::Ipv4Addr;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Arc, Mutex, MutexGuard};
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use esp_idf_svc::http::server::{Configuration as HttpConfiguration, EspHttpConnection, EspHttpServer, Request};
+use esp_idf_svc::http::Method;
+use esp_idf_svc::io::{Read, Write};
+
+use serde::{de::DeserializeOwned, Deserialize, Serialize};
+use sh1106::interface::DisplayInterface;
+
+use crate::devices::{Devices, Rgb};
+use crate::sensor_stream;
+
+/// HTTP サーバーのポート
+pub const HTTP_PORT: u16 = 80;
+
+/// ビルド時に gzip で圧縮した操作パネル (build.rs)
+const INDEX_HTML_GZ: &[u8] = include_bytes!(concat!(env!("OUT_DIR"), "/index.html.gz"));
+
+/// 受け付けるリクエストボディの最大の大きさ (バイト)
+const MAX_BODY_LEN: usize = 256;
+
+#[derive(Serialize)]
+struct Status {
+    wifi: WifiStatus,
+    leds: [bool; 2],
+    neopixel: Pixel,
+    accelerometer: bool,
+    time_synced: bool,
+    uptime_ms: i64,
+}
+
+#[derive(Serialize)]
+struct WifiStatus {
+    state: String,
+    ssid: Option<String>,
+    ip: Option<Ipv4Addr>,
+    rssi: Option<i8>,
+}
+
+#[derive(Serialize, Deserialize)]
+struct LedRequest {
+    led: u8,
+    on: bool,
+}
+
+#[derive(Serialize, Deserialize)]
+struct Pixel {
+    r: u8,
+    g: u8,
+    b: u8,
+}
+
+#[derive(Serialize)]
+struct Accel {
+    x: i8,
+    y: i8,
+    z: i8,
+}
+
+#[derive(Serialize)]
+struct Time {
+    synced: bool,
+    unix: u64,
+    iso8601: String,
+}
+
+#[derive(Serialize)]
+struct ErrorBody<'a> {
+    error: &'a str,
+}
+
+/// HTTP API を `HTTP_PORT` で起動します。返り値を破棄するとサーバーは止まります。
+///
+/// `time_synced` は SNTP の同期が終わったら `true` にします。
+pub fn start<DI>(devices: Arc<Mutex<Devices<DI>>>, time_synced: Arc<AtomicBool>) -> anyhow::Result<EspHttpServer<'static>>
+where
+    DI: DisplayInterface + Send + 'static,
+{
+    let mut server = EspHttpServer::new(&HttpConfiguration {
+        http_port: HTTP_PORT,
+        ..Default::default()
+    })?;
+
+    // Python のないスマートフォンのブラウザからも操作できるよう、パネルを返す
+    server.fn_handler("/", Method::Get, |req| -> anyhow::Result<()> {
+        req.into_response(
+            200,
+            None,
+            &[("Content-Type", "text/html; charset=utf-8"), ("Content-Encoding", "gzip")],
+        )?
+        .write_all(INDEX_HTML_GZ)?;
+        Ok(())
+    })?;
+
+    let status_devices = devices.clone();
+    let status_synced = time_synced.clone();
+    server.fn_handler("/status", Method::Get, move |req| -> anyhow::Result<()> {
+        let status = match lock(&status_devices) {
+            Ok(devices) => {
+                let state = devices.wifi_manager.state();
+                Status {
+                    wifi: WifiStatus {
+                        state: state.to_string(),
+                        ssid: state.ssid().map(str::to_string),
+                        ip: state.ip(),
+                        rssi: devices.wifi_manager.rssi(),
+                    },
+                    leds: devices.leds(),
+                    neopixel: Pixel { r: devices.pixel.r, g: devices.pixel.g, b: devices.pixel.b },
+                    accelerometer: devices.sensor.is_some(),
+                    time_synced: status_synced.load(Ordering::Acquire),
+                    uptime_ms: unsafe { esp_idf_svc::sys::esp_timer_get_time() } / 1000,
+                }
+            }
+            Err(e) => return write_error(req, 500, &e.to_string()),
+        };
+        write_json(req, 200, &status)
+    })?;
+
+    let led_devices = devices.clone();
+    server.fn_handler("/led", Method::Post, move |mut req| -> anyhow::Result<()> {
+        let led: LedRequest = match read_json(&mut req) {
+            Ok(led) => led,
+            Err(e) => return write_error(req, 400, &e.to_string()),
+        };
+        if !(1..=2).contains(&led.led) {
+            return write_error(req, 400, "led must be 1 or 2");
+        }
+        match lock(&led_devices).and_then(|mut devices| devices.set_led(led.led, led.on)) {
+            Ok(()) => write_json(req, 200, &led),
+            Err(e) => write_error(req, 500, &e.to_string()),
+        }
+    })?;
+
+    let pixel_devices = devices.clone();
+    server.fn_handler("/neopixel", Method::Post, move |mut req| -> anyhow::Result<()> {
+        let pixel: Pixel = match read_json(&mut req) {
+            Ok(pixel) => pixel,
+            Err(e) => return write_error(req, 400, &e.to_string()),
+        };
+        let rgb = Rgb::new(pixel.r, pixel.g, pixel.b);
+        match lock(&pixel_devices).and_then(|mut devices| devices.set_pixel(rgb)) {
+            Ok(()) => write_json(req, 200, &pixel),
+            Err(e) => write_error(req, 500, &e.to_string()),
+        }
+    })?;
+
+    // 加速度を連続で受け取る WebSocket (/ws)
+    sensor_stream::attach(&mut server, devices.clone())?;
+
+    let accel_devices = devices;
+    server.fn_handler("/accel", Method::Get, move |req| -> anyhow::Result<()> {
+        match lock(&accel_devices).and_then(|mut devices| devices.acceleration()) {
+            Ok(Some(accel)) => write_json(req, 200, &Accel { x: accel.x, y: accel.y, z: accel.z }),
+            Ok(None) => write_error(req, 503, "accelerometer not connected"),
+            Err(e) => write_error(req, 500, &e.to_string()),
+        }
+    })?;
+
+    server.fn_handler("/time", Method::Get, move |req| -> anyhow::Result<()> {
+        let now = SystemTime::now();
+        let unix = now.duration_since(UNIX_EPOCH).map(|d| d.as_secs()).unwrap_or(0);
+        let time = Time {
+            synced: time_synced.load(Ordering::Acquire),
+            unix,
+            iso8601: chrono::DateTime::<chrono::Utc>::from(now).to_rfc3339_opts(chrono::SecondsFormat::Secs, true),
+        };
+        write_json(req, 200, &time)
+    })?;
+
+    log::info!("HTTP API listening on 0.0.0.0:{}", HTTP_PORT);
+    Ok(server)
+}
+
+fn lock<DI>(devices: &Mutex<Devices<DI>>) -> anyhow::Result<MutexGuard<'_, Devices<DI>>> {
+    devices.lock().map_err(|_| anyhow::anyhow!("Device lock poisoned"))
+}
+
+/// リクエストボディを JSON として読み取る。`MAX_BODY_LEN` を超えるとエラーにする
+fn read_json<T: DeserializeOwned>(req: &mut Request<&mut EspHttpConnection>) -> anyhow::Result<T> {
+    let len = req.content_len().unwrap_or(0) as usize;
+    if len > MAX_BODY_LEN {
+        anyhow::bail!("request body too large (max {} bytes)", MAX_BODY_LEN);
+    }
+    let mut body = vec![0; len];
+    let mut read = 0;
+    while read < len {
+        let n = req.read(&mut body[read..])?;
+        if n == 0 {
+            break;
+        }
+        read += n;
+    }
+    body.truncate(read);
+    Ok(serde_json::from_slice(&body)?)
+}
+
+fn write_json<T: Serialize>(req: Request<&mut EspHttpConnection>, status: u16, value: &T) -> anyhow::Result<()> {
+    let body = serde_json::to_vec(value)?;
+    req.into_response(status, None, &[("Content-Type", "application/json")])?
+        .write_all(&body)?;
+    Ok(())
+}
+
+fn write_error(req: Request<&mut EspHttpConnection>, status: u16, message: &str) -> anyhow::Result<()> {
+    log::warn!("HTTP {}: {}", status, message);
+    write_json(req, status, &ErrorBody { error: message })
+}
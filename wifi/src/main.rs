@@ -1,127 +1,248 @@
 use esp_idf_svc::hal::prelude::Peripherals;
 use esp_idf_svc::hal::gpio::PinDriver;
-use esp_idf_svc::hal::rmt::{config::TransmitConfig, FixedLengthSignal, PinState, Pulse, TxRmtDriver};
+use esp_idf_svc::hal::i2c::{I2cConfig, I2cDriver};
+use esp_idf_svc::hal::units::FromValueType;
+use esp_idf_svc::hal::rmt::{config::TransmitConfig, TxRmtDriver};
 use esp_idf_svc::{
-    wifi::EspWifi,
-    wifi::ClientConfiguration,
-    wifi::Configuration,
+    wifi::{BlockingWifi, EspWifi},
     nvs::EspDefaultNvsPartition,
     eventloop::EspSystemEventLoop,
+    sntp::{EspSntp, SntpConf},
 };
 
-use heapless::String;
-use std::env;
-use std::io::{Read, Write};
+use embedded_graphics::{
+    mono_font::{ascii::FONT_4X6, MonoTextStyle},
+    pixelcolor::BinaryColor,
+    prelude::*,
+    primitives::Rectangle,
+    text::{Baseline, Text},
+};
+
+use sh1106::interface::DisplayInterface;
+
+use esp32s2_common_lib::buffered_display::Controller;
+use esp32s2_common_lib::mma7660fc::{Mode, DEFAULT_I2C_ADDRESS, Mma7660fc};
+use esp32s2_common_lib::provisioning::{ProvisioningPortal, DEFAULT_AP_SSID};
+use esp32s2_common_lib::sh1106_display::set_buffered_display;
+use esp32s2_common_lib::widgets::{message_box::MessageBox, qr_code::QrCodeView};
+use esp32s2_common_lib::wifi_credentials::{CredentialStore, Credentials};
+use esp32s2_common_lib::wifi_manager::{WifiManager, WifiManagerConfig};
+
+use std::io::{BufReader, ErrorKind};
 use std::net::{TcpListener, TcpStream};
+use std::sync::atomic::{AtomicBool, AtomicUsize, Ordering};
+use std::sync::{Arc, Mutex};
+use std::thread;
 use std::time::Duration;
 
-const SSID_STR: &'static str = env!("SSID");
-const SSID_PASSWORD_STR: &'static str = env!("SSID_PASSWORD");
+mod devices;
+mod http_api;
+mod protocol;
+mod sensor_stream;
+
+use devices::{Devices, Rgb};
+use protocol::{Command, ErrorCode, Line, Query, Response, PROTOCOL_VERSION};
+
+/// NVS に接続先が保存されていないときに使う、ビルド時の接続先 (.env)
+const BUILD_SSID: Option<&str> = option_env!("SSID");
+const BUILD_SSID_PASSWORD: Option<&str> = option_env!("SSID_PASSWORD");
+
+/// TCP サーバーのポート
+const PORT: u16 = 8080;
+
+/// 起動時に Wi-Fi の接続を待つ時間。過ぎると設定用のポータルを開く
+const CONNECT_TIMEOUT: Duration = Duration::from_secs(30);
+
+/// コマンドが届かないまま接続を切るまでの時間
+const IDLE_TIMEOUT: Duration = Duration::from_secs(60);
+
+/// 同時に接続できるクライアントの数。超えた接続には `ERR 503` を返して切断する
+const MAX_CLIENTS: usize = 4;
+
+/// 接続ごとのスレッドのスタックサイズ (バイト)
+const CLIENT_STACK_SIZE: usize = 8192;
+
+/// タップとみなす加速度のしきい値 (1-31) と、超えたと判定するまでのサンプル数
+const TAP_THRESHOLD: u8 = 12;
+const TAP_DEBOUNCE: u8 = 2;
 
 fn main() -> anyhow::Result<()> {
     esp_idf_svc::sys::link_patches();
 
-    let ssid = String::<32>::try_from(SSID_STR).unwrap();
-    let password = String::<64>::try_from(SSID_PASSWORD_STR).unwrap();
-
     let peripherals = Peripherals::take()?;
+
+    // 接続先を QR コードで表示する OLED
+    let (mut display, _rst_driver) = set_buffered_display(
+        Controller::from_build_env(option_env!("DISPLAY_CONTROLLER"))?,
+        peripherals.pins.gpio38,
+        peripherals.pins.gpio37,
+        peripherals.pins.gpio36,
+        peripherals.pins.gpio35,
+        peripherals.pins.gpio34,
+        peripherals.spi2,
+    )?;
+
+    esp_idf_svc::log::EspLogger::initialize_default();
+
     let sys_loop = EspSystemEventLoop::take().unwrap();
     let nvs = EspDefaultNvsPartition::take().unwrap();
 
-    let mut wifi_driver = EspWifi::new(
-        peripherals.modem,
-        sys_loop,
-        Some(nvs)
-    ).unwrap();
+    let wifi = EspWifi::new(peripherals.modem, sys_loop.clone(), Some(nvs.clone()))?;
+
+    // 接続先は NVS に保存したものを優先し、なければビルド時の値を使う
+    let mut credential_store = CredentialStore::new(nvs)?;
+    let fallback = Credentials::from_build_env(BUILD_SSID, BUILD_SSID_PASSWORD);
+    let networks = credential_store.load_or(fallback)?;
+
+    // 接続できたあとは、切断されても WifiManager がバックグラウンドで再接続する
+    // (保存した接続先のうち、見えているものを優先度と受信強度の順に試す)
+    let connection = if networks.is_empty() {
+        Err(wifi)
+    } else {
+        let target = match networks.as_slice() {
+            [network] => network.ssid().to_string(),
+            _ => format!("{} networks", networks.len()),
+        };
+        display.clear();
+        MessageBox::new("Wi-Fi", &format!("Connecting to {}", target))
+            .draw(&mut display)
+            .map_err(|e| anyhow::anyhow!("Draw message box error: {:?}", e))?;
+        display.flush().map_err(|e| anyhow::anyhow!("Display flush error: {:?}", e))?;
 
-    wifi_driver.set_configuration(&Configuration::Client(ClientConfiguration{
-        ssid,
-        password,
-        ..Default::default()
-    })).unwrap();
+        let manager = WifiManager::start(wifi, &sys_loop, networks, WifiManagerConfig::default())?;
+        match manager.wait_connected(CONNECT_TIMEOUT) {
+            Some(ip) => Ok((manager, ip)),
+            None => {
+                log::warn!("Wi-Fi connection timed out: {}", manager.state());
+                Err(manager.stop())
+            }
+        }
+    };
 
-    wifi_driver.start().unwrap();
-    wifi_driver.connect().unwrap();
+    // 接続先がないか接続できなければ、アクセスポイントになって接続先を設定してもらう
+    let (wifi_manager, ip) = match connection {
+        Ok(connected) => connected,
+        Err(wifi) => {
+            let mut wifi = BlockingWifi::wrap(wifi, sys_loop)?;
+            let portal = ProvisioningPortal::new(DEFAULT_AP_SSID);
+            display.clear();
+            QrCodeView::new(&portal.join_qr_text(), Rectangle::new(Point::zero(), Size::new(64, 64)))
+                .map_err(|e| anyhow::anyhow!("QR code error: {:?}", e))?
+                .draw(&mut display)
+                .map_err(|e| anyhow::anyhow!("Draw QR code error: {:?}", e))?;
+            let style = MonoTextStyle::new(&FONT_4X6, BinaryColor::On);
+            let lines = ["Wi-Fi setup", "", "join AP", portal.ap_ssid(), "", "and enter", "SSID and", "password"];
+            for (i, line) in lines.iter().enumerate() {
+                Text::with_baseline(line, Point::new(66, 2 + i as i32 * 7), style, Baseline::Top)
+                    .draw(&mut display)
+                    .map_err(|e| anyhow::anyhow!("Draw text error: {:?}", e))?;
+            }
+            display.flush().map_err(|e| anyhow::anyhow!("Display flush error: {:?}", e))?;
 
-    let mut led1 = PinDriver::output(peripherals.pins.gpio2)?;
-    let mut led2 = PinDriver::output(peripherals.pins.gpio3)?;
+            let saved = portal.run(&mut wifi, &mut credential_store)?;
+            display.clear();
+            MessageBox::new("Wi-Fi", &format!("Saved {}, restarting", saved.ssid))
+                .draw(&mut display)
+                .map_err(|e| anyhow::anyhow!("Draw message box error: {:?}", e))?;
+            display.flush().map_err(|e| anyhow::anyhow!("Display flush error: {:?}", e))?;
+            esp_idf_svc::hal::reset::restart();
+        }
+    };
+    let ssid = wifi_manager.state().ssid().unwrap_or_default().to_string();
+
+    let led1 = PinDriver::output(peripherals.pins.gpio2)?;
+    let led2 = PinDriver::output(peripherals.pins.gpio3)?;
 
     // NeoPixel (WS2812B) on GPIO18
     let led_pin = peripherals.pins.gpio18;
     let channel = peripherals.rmt.channel0;
     let config = TransmitConfig::new().clock_divider(1);
-    let mut neopixel_tx = TxRmtDriver::new(channel, led_pin, &config)?;
+    let neopixel_tx = TxRmtDriver::new(channel, led_pin, &config)?;
 
-    esp_idf_svc::log::EspLogger::initialize_default();
+    // 加速度センサー (I2C: SDA=GPIO8, SCL=GPIO9)。なくても他のコマンドは使える
+    let i2c_config = I2cConfig::new().baudrate(100.kHz().into());
+    let i2c_driver = I2cDriver::new(peripherals.i2c0, peripherals.pins.gpio8, peripherals.pins.gpio9, &i2c_config)?;
+    let mut sensor = Mma7660fc::new(i2c_driver, DEFAULT_I2C_ADDRESS);
+    // WebSocket の配信 (/ws) でタップと揺れを通知できるよう、アクティブにする前に設定する
+    let ready = sensor
+        .set_tap_detection(TAP_THRESHOLD, TAP_DEBOUNCE)
+        .and_then(|_| sensor.set_shake_detection(true))
+        .and_then(|_| sensor.set_mode(Mode::Active));
+    let sensor = match ready {
+        Ok(_) => Some(sensor),
+        Err(e) => {
+            log::warn!("Accelerometer not available: {:?}", e);
+            None
+        }
+    };
+
+    log::info!("Wi-Fi connected, IP: {:?}", ip);
 
-    while !wifi_driver.is_connected().unwrap(){
-        let config = wifi_driver.get_configuration().unwrap();
-        println!("Waiting for station {:?}", config);
+    // HTTP API の /time で返す時刻を合わせる (同期が終わるまで待たない)
+    let time_synced = Arc::new(AtomicBool::new(false));
+    let synced = time_synced.clone();
+    let _sntp = EspSntp::new_with_callback(&SntpConf::default(), move |_| {
+        log::info!("Time synchronized");
+        synced.store(true, Ordering::Release);
+    })?;
+
+    let listener = TcpListener::bind(("0.0.0.0", PORT))?;
+    log::info!("TCP server listening on 0.0.0.0:{}", PORT);
+
+    // test.py にそのまま渡せる "IP:PORT" を QR コードで表示する
+    let address = format!("{}:{}", ip, PORT);
+    display.clear();
+    QrCodeView::new(&address, Rectangle::new(Point::zero(), Size::new(64, 64)))
+        .map_err(|e| anyhow::anyhow!("QR code error: {:?}", e))?
+        .draw(&mut display)
+        .map_err(|e| anyhow::anyhow!("Draw QR code error: {:?}", e))?;
+    let style = MonoTextStyle::new(&FONT_4X6, BinaryColor::On);
+    let ip = format!("{}", ip);
+    let port = format!("port {}", PORT);
+    let lines = ["Wi-Fi connected", ssid.as_str(), "", ip.as_str(), port.as_str(), "", "scan for", "test.py"];
+    for (i, line) in lines.iter().enumerate() {
+        Text::with_baseline(line, Point::new(66, 2 + i as i32 * 7), style, Baseline::Top)
+            .draw(&mut display)
+            .map_err(|e| anyhow::anyhow!("Draw text error: {:?}", e))?;
     }
+    display.flush().map_err(|e| anyhow::anyhow!("Display flush error: {:?}", e))?;
 
-    let ip_info = wifi_driver.sta_netif().get_ip_info().unwrap();
-    log::info!("Wi-Fi connected, IP: {:?}", ip_info.ip);
+    // 接続ごとのスレッドから操作するので、デバイスはコマンドを実行する間だけロックする
+    let devices = Arc::new(Mutex::new(Devices {
+        led1,
+        led2,
+        neopixel_tx,
+        pixel: Rgb::default(),
+        display,
+        sensor,
+        credential_store,
+        wifi_manager,
+    }));
+    let clients = Arc::new(AtomicUsize::new(0));
 
-    let listener = TcpListener::bind("0.0.0.0:8080")?;
-    log::info!("TCP server listening on 0.0.0.0:8080");
+    // curl やダッシュボードから使う JSON の API (TCP サーバーと同じデバイスを操作する)
+    let _http_server = http_api::start(devices.clone(), time_synced)?;
 
     for stream in listener.incoming() {
         match stream {
             Ok(mut stream) => {
-                log::info!("New connection from {:?}", stream.peer_addr()?);
-                let mut buffer = [0; 64];
-                match stream.read(&mut buffer) {
-                    Ok(bytes_read) => {
-                        if let Ok(request_str) = std::str::from_utf8(&buffer[..bytes_read]) {
-                            let request = request_str.trim();
-                            log::info!("Received: {}", request);
-
-                            match request {
-                                "on" => {
-                                    led1.set_high()?;
-                                    led2.set_low()?;
-                                    stream.write_all(b"LED ON\n")?;
-                                    log::info!("LED ON");
-                                },
-                                "off" => {
-                                    led1.set_low()?;
-                                    led2.set_high()?;
-                                    stream.write_all(b"LED OFF\n")?;
-                                    log::info!("LED OFF");
-                                },
-                                "red" => {
-                                    neopixel(Rgb::new(255, 0, 0), &mut neopixel_tx)?;
-                                    stream.write_all(b"NeoPixel RED\n")?;
-                                    log::info!("NeoPixel RED");
-                                },
-                                "green" => {
-                                    neopixel(Rgb::new(0, 255, 0), &mut neopixel_tx)?;
-                                    stream.write_all(b"NeoPixel GREEN\n")?;
-                                    log::info!("NeoPixel GREEN");
-                                },
-                                "blue" => {
-                                    neopixel(Rgb::new(0, 0, 255), &mut neopixel_tx)?;
-                                    stream.write_all(b"NeoPixel BLUE\n")?;
-                                    log::info!("NeoPixel BLUE");
-                                },
-                                "neopixel_off" => {
-                                    neopixel(Rgb::new(0, 0, 0), &mut neopixel_tx)?;
-                                    stream.write_all(b"NeoPixel OFF\n")?;
-                                    log::info!("NeoPixel OFF");
-                                },
-                                _ => {
-                                    stream.write_all(b"Invalid command. Use 'on', 'off', 'red', 'green', 'blue', or 'neopixel_off'.\n")?;
-                                    log::warn!("Invalid command received: {}", request);
-                                }
-                            }
-                        } else {
-                            stream.write_all(b"Invalid UTF-8 sequence\n")?;
-                            log::error!("Received invalid UTF-8 sequence");
+                let Some(slot) = ClientSlot::acquire(&clients) else {
+                    log::warn!("Too many connections, rejecting {:?}", stream.peer_addr().ok());
+                    let _ = Response::error(ErrorCode::Unavailable, "too many connections").write_to(&mut stream);
+                    continue;
+                };
+                let devices = devices.clone();
+                let spawned = thread::Builder::new()
+                    .name("tcp_client".to_string())
+                    .stack_size(CLIENT_STACK_SIZE)
+                    .spawn(move || {
+                        let _slot = slot;
+                        if let Err(e) = handle_connection(stream, &devices) {
+                            log::error!("Connection error: {:?}", e);
                         }
-                    }
-                    Err(e) => {
-                        log::error!("Failed to read from stream: {}", e);
-                    }
+                    });
+                if let Err(e) = spawned {
+                    log::error!("Failed to spawn connection thread: {}", e);
                 }
             }
             Err(e) => {
@@ -133,53 +254,175 @@ fn main() -> anyhow::Result<()> {
     Ok(())
 }
 
-fn neopixel(rgb: Rgb, tx: &mut TxRmtDriver) -> anyhow::Result<()> {
-    let color: u32 = rgb.into();
-    let ticks_hz = tx.counter_clock()?;
-
-    let (t0h, t0l, t1h, t1l) = (
-        Pulse::new_with_duration(ticks_hz, PinState::High, &Duration::from_nanos(350))?,
-        Pulse::new_with_duration(ticks_hz, PinState::Low, &Duration::from_nanos(800))?,
-        Pulse::new_with_duration(ticks_hz, PinState::High, &Duration::from_nanos(700))?,
-        Pulse::new_with_duration(ticks_hz, PinState::Low, &Duration::from_nanos(600))?,
-    );
-
-    let mut signal = FixedLengthSignal::<24>::new();
-
-    for i in (0..24).rev() {
-        let p = 2_u32.pow(i);
-        let bit: bool = p & color != 0;
-
-        let (high_pulse, low_pulse) = if bit { (t1h, t1l) } else { (t0h, t0l) };
+/// 同時に接続しているクライアントの数の枠。破棄すると枠を返す
+struct ClientSlot {
+    clients: Arc<AtomicUsize>,
+}
 
-        signal.set(23 - i as usize, &(high_pulse, low_pulse))?;
+impl ClientSlot {
+    /// 接続数が `MAX_CLIENTS` 未満なら枠を確保する
+    fn acquire(clients: &Arc<AtomicUsize>) -> Option<Self> {
+        clients
+            .fetch_update(Ordering::AcqRel, Ordering::Acquire, |n| (n < MAX_CLIENTS).then_some(n + 1))
+            .ok()?;
+        Some(Self { clients: clients.clone() })
     }
-
-    tx.start_blocking(&signal)?;
-
-    Ok(())
 }
 
-struct Rgb {
-    r: u8,
-    g: u8,
-    b: u8,
+impl Drop for ClientSlot {
+    fn drop(&mut self) {
+        self.clients.fetch_sub(1, Ordering::AcqRel);
+    }
 }
 
-impl Rgb {
-    pub fn new(r: u8, g: u8, b: u8) -> Self {
-        Self { r, g, b }
+/// 1つの接続から1行ずつコマンドを読み、切断されるか `quit` を受け取るまで応答する
+///
+/// `IDLE_TIMEOUT` の間コマンドが届かなければ `ERR 408` を返して切断する
+fn handle_connection<DI>(stream: TcpStream, devices: &Mutex<Devices<DI>>) -> anyhow::Result<()>
+where
+    DI: DisplayInterface,
+{
+    let peer = stream.peer_addr()?;
+    log::info!("New connection from {:?}", peer);
+    // 送信しないまま放置された接続が、接続数の枠を使い続けないようにする
+    stream.set_read_timeout(Some(IDLE_TIMEOUT))?;
+    let mut writer = stream.try_clone()?;
+    let mut reader = BufReader::new(stream);
+    let mut count = 0;
+    loop {
+        let line = match protocol::read_line(&mut reader) {
+            Ok(Line::Text(line)) => line,
+            Ok(Line::Eof) => break,
+            Ok(Line::TooLong) => {
+                Response::error(ErrorCode::BadRequest, "line too long").write_to(&mut writer)?;
+                continue;
+            }
+            Ok(Line::InvalidUtf8) => {
+                Response::error(ErrorCode::BadRequest, "invalid UTF-8").write_to(&mut writer)?;
+                continue;
+            }
+            Err(e) if matches!(e.kind(), ErrorKind::WouldBlock | ErrorKind::TimedOut) => {
+                log::info!("Connection from {:?} timed out", peer);
+                Response::error(ErrorCode::Timeout, "idle timeout").write_to(&mut writer)?;
+                break;
+            }
+            Err(e) => return Err(e.into()),
+        };
+        if line.trim().is_empty() {
+            continue;
+        }
+        count += 1;
+        let command = match protocol::parse(&line) {
+            Ok(command) => command,
+            Err(e) => {
+                log::warn!("Invalid command: {}", e);
+                Response::from(e).write_to(&mut writer)?;
+                continue;
+            }
+        };
+        // wifi set のパスワードをログに出さないよう、コマンドの種類だけを記録する
+        log::info!("Received: {}", command_name(&command));
+        if command == Command::Quit {
+            Response::Ok.write_to(&mut writer)?;
+            break;
+        }
+        let response = devices
+            .lock()
+            .map_err(|_| anyhow::anyhow!("Device lock poisoned"))
+            .and_then(|mut devices| execute(command, &mut devices))
+            .unwrap_or_else(|e| {
+                log::error!("Command failed: {:?}", e);
+                Response::error(ErrorCode::Internal, format!("{}", e))
+            });
+        response.write_to(&mut writer)?;
     }
+    log::info!("Connection from {:?} closed after {} commands", peer, count);
+    Ok(())
+}
+
+/// コマンドを実行して応答を返す
+fn execute<DI>(command: Command, devices: &mut Devices<DI>) -> anyhow::Result<Response>
+where
+    DI: DisplayInterface,
+{
+    let response = match command {
+        Command::Help => Response::help(),
+        Command::Version => Response::Value(PROTOCOL_VERSION.to_string()),
+        Command::Quit => Response::Ok,
+        Command::Led { index, on } => {
+            devices.set_led(index, on)?;
+            Response::Ok
+        }
+        Command::Pixel { r, g, b } => {
+            devices.set_pixel(Rgb::new(r, g, b))?;
+            Response::Ok
+        }
+        Command::Get(Query::Accel) => match devices.acceleration()? {
+            Some(accel) => Response::Value(format!("{} {} {}", accel.x, accel.y, accel.z)),
+            None => Response::error(ErrorCode::Unavailable, "accelerometer not connected"),
+        },
+        Command::Get(Query::Wifi) => {
+            let rssi = devices
+                .wifi_manager
+                .rssi()
+                .map(|rssi| format!("{} dBm", rssi))
+                .unwrap_or_else(|| "-".to_string());
+            Response::Value(format!("{}, RSSI {}", devices.wifi_manager.state(), rssi))
+        }
+        Command::Screenshot => {
+            // OLED に表示中の内容を PBM (P4) で返す
+            Response::Binary(devices.display.screenshot_pbm())
+        }
+        Command::WifiList => {
+            // パスワードは返さない
+            let networks = devices.credential_store.networks()?;
+            Response::Lines(networks.iter().map(|n| format!("{} {}", n.ssid(), n.priority)).collect())
+        }
+        Command::WifiSet { ssid, password } => match Credentials::new(&ssid, &password) {
+            // 次回の接続から使う
+            Ok(credentials) => {
+                devices.credential_store.set(&credentials)?;
+                Response::Ok
+            }
+            Err(e) => Response::error(ErrorCode::BadRequest, e.to_string()),
+        },
+        Command::WifiPriority { ssid, priority } => {
+            if devices.credential_store.set_priority(&ssid, priority)? {
+                Response::Ok
+            } else {
+                Response::error(ErrorCode::NotFound, format!("unknown SSID: {}", ssid))
+            }
+        }
+        Command::WifiRemove { ssid } => {
+            if devices.credential_store.remove(&ssid)? {
+                Response::Ok
+            } else {
+                Response::error(ErrorCode::NotFound, format!("unknown SSID: {}", ssid))
+            }
+        }
+        Command::WifiClear => {
+            devices.credential_store.clear()?;
+            Response::Ok
+        }
+    };
+    Ok(response)
 }
 
-impl From<Rgb> for u32 {
-    /// Convert RGB to u32 color value
-    ///
-    /// e.g. rgb: (1,2,4)
-    /// G R B
-    /// 7 0 7 0 7 0
-    /// 00000010 00000001 00000100
-    fn from(rgb: Rgb) -> Self {
-        ((rgb.g as u32) << 16) | ((rgb.r as u32) << 8) | rgb.b as u32
+/// ログに出すコマンドの種類 (引数を含めない)
+fn command_name(command: &Command) -> &'static str {
+    match command {
+        Command::Help => "help",
+        Command::Version => "version",
+        Command::Quit => "quit",
+        Command::Led { .. } => "led",
+        Command::Pixel { .. } => "pixel",
+        Command::Get(Query::Accel) => "get accel",
+        Command::Get(Query::Wifi) => "get wifi",
+        Command::Screenshot => "screenshot",
+        Command::WifiList => "wifi list",
+        Command::WifiSet { .. } => "wifi set",
+        Command::WifiPriority { .. } => "wifi priority",
+        Command::WifiRemove { .. } => "wifi remove",
+        Command::WifiClear => "wifi clear",
     }
 }
@@ -0,0 +1,495 @@
+//! TCP サーバーのコマンドプロトコル
+//!
+//! 1行に1コマンド (`\n` 区切り) を送り、1コマンドごとに1つの応答を受け取ります。
+//! 1つの接続で続けて何コマンドでも送れます。`quit` を送るか切断すると終了します。
+//! 一定時間コマンドが届かないと `ERR 408` を返して切断します。
+//!
+//! ```text
+//! > led 1 on
+//! < OK
+//! > pixel 255 128 0
+//! < OK
+//! > get accel
+//! < OK 1 -2 21
+//! > wifi list
+//! < OK 2
+//! < office 10
+//! < lab 0
+//! > pixel red
+//! < ERR 400 invalid number: red
+//! ```
+//!
+//! 応答は次のいずれかです。
+//!
+//! - `OK` または `OK <値>`: 成功
+//! - `OK <n>` に続けて n 行: 複数行の結果 (`help`, `wifi list`)
+//! - `OK <n>` に続けて n バイト: バイナリの結果 (`screenshot`)
+//! - `ERR <コード> <メッセージ>`: 失敗 (コードは `ErrorCode`)
+//!
+//! このモジュールは ESP-IDF に依存しないので、ホストでテストできます。
+//!
+//! ```sh
+//! rustc --edition 2021 --test src/protocol.rs -o target/protocol_test && target/protocol_test
+//! ```
+
+use std::fmt;
+use std::io::{self, BufRead, Read, Write};
+
+/// プロトコルのバージョン。コマンドや応答の形式を互換性なく変えたら上げる
+pub const PROTOCOL_VERSION: u32 = 2;
+
+/// 1行の最大の長さ (バイト、改行を除く)
+pub const MAX_LINE_LEN: usize = 128;
+
+/// `help` で表示するコマンドの一覧 (書式, 説明)
+pub const COMMANDS: &[(&str, &str)] = &[
+    ("help", "list commands"),
+    ("version", "protocol version"),
+    ("led <1|2> <on|off>", "switch an LED"),
+    ("pixel <r> <g> <b>", "set the NeoPixel color (0-255)"),
+    ("pixel off", "turn the NeoPixel off"),
+    ("get accel", "acceleration x y z (raw, -32 to 31)"),
+    ("get wifi", "Wi-Fi state and RSSI"),
+    ("screenshot", "OLED contents as PBM (OK <bytes> + data)"),
+    ("wifi list", "saved SSIDs and priorities"),
+    ("wifi set <ssid> <password>", "add a network (password may be empty)"),
+    ("wifi priority <ssid> <0-255>", "change a network's priority"),
+    ("wifi remove <ssid>", "remove a network"),
+    ("wifi clear", "remove all networks"),
+    ("quit", "close the connection"),
+];
+
+/// 解析したコマンド
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Command {
+    Help,
+    Version,
+    Quit,
+    /// LED (1 または 2) の点灯・消灯
+    Led { index: u8, on: bool },
+    /// NeoPixel の色 (0, 0, 0 で消灯)
+    Pixel { r: u8, g: u8, b: u8 },
+    Get(Query),
+    Screenshot,
+    WifiList,
+    WifiSet { ssid: String, password: String },
+    WifiPriority { ssid: String, priority: u8 },
+    WifiRemove { ssid: String },
+    WifiClear,
+}
+
+/// `get` で読み取る値
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Query {
+    Accel,
+    Wifi,
+}
+
+/// `ERR` 応答のコード
+///
+/// 数値は意味の近い HTTP のステータスコードに合わせる
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ErrorCode {
+    /// 引数の数や値が正しくない
+    BadRequest,
+    /// 知らないコマンド
+    UnknownCommand,
+    /// 一定時間コマンドが届かなかった
+    Timeout,
+    /// 指定したもの (SSID など) が見つからない
+    NotFound,
+    /// 実行中にエラーが発生した
+    Internal,
+    /// 使えない (センサーが接続されていないなど)
+    Unavailable,
+}
+
+impl ErrorCode {
+    pub fn code(self) -> u16 {
+        match self {
+            ErrorCode::BadRequest => 400,
+            ErrorCode::NotFound => 404,
+            ErrorCode::Timeout => 408,
+            ErrorCode::Internal => 500,
+            ErrorCode::UnknownCommand => 501,
+            ErrorCode::Unavailable => 503,
+        }
+    }
+}
+
+/// 解析に失敗した理由
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ParseError {
+    pub code: ErrorCode,
+    pub message: String,
+}
+
+impl ParseError {
+    fn bad_request(message: impl Into<String>) -> Self {
+        Self {
+            code: ErrorCode::BadRequest,
+            message: message.into(),
+        }
+    }
+}
+
+impl fmt::Display for ParseError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{} {}", self.code.code(), self.message)
+    }
+}
+
+impl std::error::Error for ParseError {}
+
+impl From<ParseError> for Response {
+    fn from(e: ParseError) -> Self {
+        Response::Error(e.code, e.message)
+    }
+}
+
+/// 1行を解析します。前後の空白と `\r` は無視します。
+pub fn parse(line: &str) -> Result<Command, ParseError> {
+    let line = line.trim();
+    let (name, rest) = split_word(line);
+    let args: Vec<&str> = rest.split_whitespace().collect();
+    match name.to_ascii_lowercase().as_str() {
+        "help" => no_args(&args, Command::Help),
+        "version" => no_args(&args, Command::Version),
+        "quit" => no_args(&args, Command::Quit),
+        "screenshot" => no_args(&args, Command::Screenshot),
+        "led" => match args.as_slice() {
+            [index, state] => {
+                let index = match *index {
+                    "1" => 1,
+                    "2" => 2,
+                    _ => return Err(ParseError::bad_request(format!("invalid LED: {}", index))),
+                };
+                Ok(Command::Led { index, on: parse_on_off(state)? })
+            }
+            _ => Err(usage("led <1|2> <on|off>")),
+        },
+        "pixel" => match args.as_slice() {
+            [state] if state.eq_ignore_ascii_case("off") => Ok(Command::Pixel { r: 0, g: 0, b: 0 }),
+            [r, g, b] => Ok(Command::Pixel {
+                r: parse_number(r)?,
+                g: parse_number(g)?,
+                b: parse_number(b)?,
+            }),
+            _ => Err(usage("pixel <r> <g> <b> | pixel off")),
+        },
+        "get" => match args.as_slice() {
+            [target] => match target.to_ascii_lowercase().as_str() {
+                "accel" => Ok(Command::Get(Query::Accel)),
+                "wifi" => Ok(Command::Get(Query::Wifi)),
+                _ => Err(ParseError::bad_request(format!("unknown value: {}", target))),
+            },
+            _ => Err(usage("get <accel|wifi>")),
+        },
+        "wifi" => parse_wifi(rest),
+        "" => Err(ParseError::bad_request("empty command")),
+        _ => Err(ParseError {
+            code: ErrorCode::UnknownCommand,
+            message: format!("unknown command: {} (try help)", name),
+        }),
+    }
+}
+
+/// `wifi` のサブコマンド。パスワードには空白を含められるよう、残りをそのまま使う
+fn parse_wifi(rest: &str) -> Result<Command, ParseError> {
+    let (sub, rest) = split_word(rest);
+    match sub.to_ascii_lowercase().as_str() {
+        "list" if rest.is_empty() => Ok(Command::WifiList),
+        "clear" if rest.is_empty() => Ok(Command::WifiClear),
+        "set" => {
+            let (ssid, password) = split_word(rest);
+            if ssid.is_empty() {
+                return Err(usage("wifi set <ssid> <password>"));
+            }
+            Ok(Command::WifiSet {
+                ssid: ssid.to_string(),
+                password: password.to_string(),
+            })
+        }
+        "priority" => match rest.split_whitespace().collect::<Vec<_>>().as_slice() {
+            [ssid, priority] => Ok(Command::WifiPriority {
+                ssid: ssid.to_string(),
+                priority: parse_number(priority)?,
+            }),
+            _ => Err(usage("wifi priority <ssid> <0-255>")),
+        },
+        "remove" => match rest.split_whitespace().collect::<Vec<_>>().as_slice() {
+            [ssid] => Ok(Command::WifiRemove { ssid: ssid.to_string() }),
+            _ => Err(usage("wifi remove <ssid>")),
+        },
+        _ => Err(usage("wifi <list|set|priority|remove|clear>")),
+    }
+}
+
+/// 最初の単語と残り (前後の空白を除く) に分ける
+fn split_word(text: &str) -> (&str, &str) {
+    let text = text.trim();
+    match text.split_once(char::is_whitespace) {
+        Some((word, rest)) => (word, rest.trim()),
+        None => (text, ""),
+    }
+}
+
+fn no_args(args: &[&str], command: Command) -> Result<Command, ParseError> {
+    if args.is_empty() {
+        Ok(command)
+    } else {
+        Err(ParseError::bad_request("unexpected arguments"))
+    }
+}
+
+fn parse_on_off(text: &str) -> Result<bool, ParseError> {
+    match text.to_ascii_lowercase().as_str() {
+        "on" | "1" => Ok(true),
+        "off" | "0" => Ok(false),
+        _ => Err(ParseError::bad_request(format!("expected on or off: {}", text))),
+    }
+}
+
+fn parse_number(text: &str) -> Result<u8, ParseError> {
+    text.parse()
+        .map_err(|_| ParseError::bad_request(format!("invalid number: {}", text)))
+}
+
+fn usage(usage: &str) -> ParseError {
+    ParseError::bad_request(format!("usage: {}", usage))
+}
+
+/// `read_line` で読み取った1行
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Line {
+    /// 1行 (改行を含む)
+    Text(String),
+    /// `MAX_LINE_LEN` を超えた (行の残りは読み捨てた)
+    TooLong,
+    /// UTF-8 ではない
+    InvalidUtf8,
+    /// 切断された
+    Eof,
+}
+
+/// `reader` から1行読み取ります。
+///
+/// TCP では1つのコマンドが複数回に分かれて届いたり、複数のコマンドがまとめて届いたりするので、
+/// `BufRead` に溜めて改行までを1行として取り出します。切断の直前の改行のない行も1行として扱います。
+pub fn read_line<R: BufRead>(reader: &mut R) -> io::Result<Line> {
+    let mut buf = Vec::new();
+    // 改行を含めて MAX_LINE_LEN + 1 バイトまで読む
+    let len = reader.by_ref().take(MAX_LINE_LEN as u64 + 1).read_until(b'\n', &mut buf)?;
+    if len == 0 {
+        return Ok(Line::Eof);
+    }
+    if buf.last() != Some(&b'\n') && buf.len() > MAX_LINE_LEN {
+        skip_line(reader)?;
+        return Ok(Line::TooLong);
+    }
+    match String::from_utf8(buf) {
+        Ok(line) => Ok(Line::Text(line)),
+        Err(_) => Ok(Line::InvalidUtf8),
+    }
+}
+
+/// 次の改行まで読み捨てる (長すぎる行のあとも続けてコマンドを受け付けるため)
+fn skip_line<R: BufRead>(reader: &mut R) -> io::Result<()> {
+    loop {
+        let available = reader.fill_buf()?;
+        if available.is_empty() {
+            return Ok(());
+        }
+        match available.iter().position(|&b| b == b'\n') {
+            Some(i) => {
+                reader.consume(i + 1);
+                return Ok(());
+            }
+            None => {
+                let len = available.len();
+                reader.consume(len);
+            }
+        }
+    }
+}
+
+/// コマンドへの応答
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Response {
+    /// `OK`
+    Ok,
+    /// `OK <値>`
+    Value(String),
+    /// `OK <n>` に続けて n 行
+    Lines(Vec<String>),
+    /// `OK <n>` に続けて n バイト
+    Binary(Vec<u8>),
+    /// `ERR <コード> <メッセージ>`
+    Error(ErrorCode, String),
+}
+
+impl Response {
+    pub fn error(code: ErrorCode, message: impl Into<String>) -> Self {
+        Response::Error(code, message.into())
+    }
+
+    /// `help` の応答
+    pub fn help() -> Self {
+        let mut lines = vec![format!("protocol {}", PROTOCOL_VERSION)];
+        lines.extend(COMMANDS.iter().map(|(usage, description)| format!("{:<30} {}", usage, description)));
+        Response::Lines(lines)
+    }
+
+    /// 送信するバイト列に変換します。
+    pub fn encode(&self) -> Vec<u8> {
+        let mut bytes = Vec::new();
+        match self {
+            Response::Ok => bytes.extend_from_slice(b"OK\n"),
+            Response::Value(value) => bytes.extend_from_slice(format!("OK {}\n", single_line(value)).as_bytes()),
+            Response::Lines(lines) => {
+                bytes.extend_from_slice(format!("OK {}\n", lines.len()).as_bytes());
+                for line in lines {
+                    bytes.extend_from_slice(single_line(line).as_bytes());
+                    bytes.push(b'\n');
+                }
+            }
+            Response::Binary(data) => {
+                bytes.extend_from_slice(format!("OK {}\n", data.len()).as_bytes());
+                bytes.extend_from_slice(data);
+            }
+            Response::Error(code, message) => {
+                bytes.extend_from_slice(format!("ERR {} {}\n", code.code(), single_line(message)).as_bytes())
+            }
+        }
+        bytes
+    }
+
+    /// `writer` に送信します。
+    pub fn write_to<W: Write>(&self, writer: &mut W) -> io::Result<()> {
+        writer.write_all(&self.encode())
+    }
+}
+
+/// 応答の行がずれないよう、改行を空白に置き換える
+fn single_line(text: &str) -> String {
+    text.replace(['\r', '\n'], " ")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parses_commands() {
+        assert_eq!(parse("led 1 on"), Ok(Command::Led { index: 1, on: true }));
+        assert_eq!(parse("  LED 2 off\r\n"), Ok(Command::Led { index: 2, on: false }));
+        assert_eq!(parse("pixel 255 128 0"), Ok(Command::Pixel { r: 255, g: 128, b: 0 }));
+        assert_eq!(parse("pixel off"), Ok(Command::Pixel { r: 0, g: 0, b: 0 }));
+        assert_eq!(parse("get accel"), Ok(Command::Get(Query::Accel)));
+        assert_eq!(parse("help"), Ok(Command::Help));
+        assert_eq!(parse("wifi list"), Ok(Command::WifiList));
+        assert_eq!(
+            parse("wifi priority office 10"),
+            Ok(Command::WifiPriority { ssid: "office".to_string(), priority: 10 })
+        );
+    }
+
+    #[test]
+    fn wifi_set_keeps_spaces_in_password() {
+        assert_eq!(
+            parse("wifi set lab correct horse  battery"),
+            Ok(Command::WifiSet { ssid: "lab".to_string(), password: "correct horse  battery".to_string() })
+        );
+        assert_eq!(
+            parse("wifi set open"),
+            Ok(Command::WifiSet { ssid: "open".to_string(), password: String::new() })
+        );
+    }
+
+    #[test]
+    fn rejects_bad_input() {
+        assert_eq!(parse("neopixel_off").unwrap_err().code, ErrorCode::UnknownCommand);
+        assert_eq!(parse("").unwrap_err().code, ErrorCode::BadRequest);
+        assert_eq!(parse("led 3 on").unwrap_err().code, ErrorCode::BadRequest);
+        assert_eq!(parse("pixel 256 0 0").unwrap_err().code, ErrorCode::BadRequest);
+        assert_eq!(parse("pixel 1 2").unwrap_err().code, ErrorCode::BadRequest);
+        assert_eq!(parse("help me").unwrap_err().code, ErrorCode::BadRequest);
+        assert_eq!(parse("wifi set").unwrap_err().code, ErrorCode::BadRequest);
+    }
+
+    #[test]
+    fn encodes_responses() {
+        assert_eq!(Response::Ok.encode(), b"OK\n");
+        assert_eq!(Response::Value("1 -2 21".to_string()).encode(), b"OK 1 -2 21\n");
+        assert_eq!(Response::Lines(vec!["a".to_string(), "b".to_string()]).encode(), b"OK 2\na\nb\n");
+        assert_eq!(Response::Binary(vec![1, 2, 3]).encode(), b"OK 3\n\x01\x02\x03");
+        assert_eq!(
+            Response::from(parse("bogus").unwrap_err()).encode(),
+            b"ERR 501 unknown command: bogus (try help)\n"
+        );
+        assert_eq!(Response::error(ErrorCode::Internal, "a\nb").encode(), b"ERR 500 a b\n");
+        assert_eq!(Response::error(ErrorCode::NotFound, "unknown SSID: x").encode(), b"ERR 404 unknown SSID: x\n");
+    }
+
+    /// 数バイトずつしか返さない (TCP で分割されて届くのと同じ)
+    struct Chunked<'a> {
+        data: &'a [u8],
+        chunk: usize,
+    }
+
+    impl Read for Chunked<'_> {
+        fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+            let len = self.chunk.min(buf.len()).min(self.data.len());
+            buf[..len].copy_from_slice(&self.data[..len]);
+            self.data = &self.data[len..];
+            Ok(len)
+        }
+    }
+
+    fn read_all(data: &[u8], chunk: usize) -> Vec<Line> {
+        let mut reader = io::BufReader::with_capacity(8, Chunked { data, chunk });
+        let mut lines = Vec::new();
+        loop {
+            let line = read_line(&mut reader).unwrap();
+            if line == Line::Eof {
+                return lines;
+            }
+            lines.push(line);
+        }
+    }
+
+    #[test]
+    fn reads_lines_split_across_reads() {
+        let text = |s: &str| Line::Text(s.to_string());
+        for chunk in [1, 3, 64] {
+            assert_eq!(
+                read_all(b"led 1 on\npixel 255 128 0\r\nget accel", chunk),
+                vec![text("led 1 on\n"), text("pixel 255 128 0\r\n"), text("get accel")]
+            );
+        }
+    }
+
+    #[test]
+    fn skips_long_and_invalid_lines() {
+        let mut data = vec![b'x'; MAX_LINE_LEN * 3];
+        data.extend_from_slice(b"\n\xff\xfe\nhelp\n");
+        assert_eq!(
+            read_all(&data, 5),
+            vec![Line::TooLong, Line::InvalidUtf8, Line::Text("help\n".to_string())]
+        );
+
+        let mut data = vec![b'x'; MAX_LINE_LEN];
+        data.push(b'\n');
+        assert_eq!(read_all(&data, 7).len(), 1);
+        assert!(matches!(read_all(&data, 7)[0], Line::Text(_)));
+    }
+
+    #[test]
+    fn help_lists_every_command() {
+        let Response::Lines(lines) = Response::help() else {
+            panic!("help must return lines");
+        };
+        assert_eq!(lines.len(), COMMANDS.len() + 1);
+        assert_eq!(lines[0], format!("protocol {}", PROTOCOL_VERSION));
+    }
+}
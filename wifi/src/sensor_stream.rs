@@ -0,0 +1,350 @@
+//! 加速度センサーの値を WebSocket (`/ws`) で配信する
+//!
+//! 接続するとデフォルトの `DEFAULT_RATE_HZ` で JSON のサンプルを送り始めます。
+//! テキストメッセージ `{"rate": 50, "format": "binary"}` で、接続ごとに頻度と形式を変えられます
+//! (`rate` は 1-`MAX_RATE_HZ`、`format` は `json` または `binary`)。
+//! 向き・表裏が変わったときと、タップ・揺れを検出したときはイベントも送ります。
+//!
+//! ## JSON
+//!
+//! ```text
+//! {"type":"sample","t":12345,"x":1,"y":-2,"z":21}
+//! {"type":"orientation","t":12345,"orientation":"up","facing":"front"}
+//! {"type":"tap","t":12345}
+//! {"type":"shake","t":12345}
+//! ```
+//!
+//! ## バイナリ (8 バイト)
+//!
+//! | バイト | サンプル | イベント |
+//! | --- | --- | --- |
+//! | 0 | 0 | 1 |
+//! | 1-3 | x, y, z (i8) | 種類 (0: 向き, 1: タップ, 2: 揺れ), 向き, 表裏 |
+//! | 4-7 | 起動からの時間 (ミリ秒, u32 LE) | 同じ |
+//!
+//! 向きは 0: 不明, 1: left, 2: right, 3: down, 4: up、表裏は 0: 不明, 1: front, 2: back です。
+
+use std::sync::mpsc::{self, RecvTimeoutError};
+use std::sync::{Arc, Mutex};
+use std::thread;
+use std::time::{Duration, Instant};
+
+use esp_idf_svc::http::server::ws::EspHttpWsDetachedSender;
+use esp_idf_svc::http::server::EspHttpServer;
+use esp_idf_svc::ws::FrameType;
+
+use serde::{Deserialize, Serialize};
+use serde_json::json;
+use sh1106::interface::DisplayInterface;
+
+use esp32s2_common_lib::mma7660fc::{Acceleration, Facing, Orientation, Tilt};
+
+use crate::devices::Devices;
+
+/// 接続直後の配信頻度 (Hz)
+pub const DEFAULT_RATE_HZ: u32 = 10;
+
+/// 設定できる最大の配信頻度 (Hz)
+pub const MAX_RATE_HZ: u32 = 50;
+
+/// 受け付ける設定メッセージの最大の大きさ (バイト)
+const MAX_MESSAGE_LEN: usize = 128;
+
+/// 接続がないときに確認する間隔
+const IDLE_POLL: Duration = Duration::from_millis(200);
+
+/// 配信スレッドのスタックサイズ (バイト)
+const STREAM_STACK_SIZE: usize = 6144;
+
+/// 送信するフレームの形式
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Format {
+    Json,
+    Binary,
+}
+
+/// クライアントから送る設定。省略した項目は変えない
+#[derive(Debug, Deserialize)]
+struct StreamConfig {
+    rate: Option<u32>,
+    format: Option<Format>,
+}
+
+/// WebSocket のハンドラーから配信スレッドへの通知
+///
+/// 送信 (`EspHttpWsDetachedSender::send`) は HTTP サーバーのタスクで実行されるので、
+/// ハンドラーと配信スレッドでロックを共有すると互いに待ち続けることがある。
+/// 接続の一覧は配信スレッドだけが持ち、ハンドラーからはチャンネルで伝える
+enum Control {
+    Open(i32, EspHttpWsDetachedSender),
+    Configure(i32, StreamConfig),
+    Close(i32),
+}
+
+/// 配信するデータ
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Frame {
+    Sample(u32, Acceleration),
+    Orientation(u32, Orientation, Facing),
+    Tap(u32),
+    Shake(u32),
+}
+
+impl Frame {
+    fn encode(&self, format: Format) -> (FrameType, Vec<u8>) {
+        match format {
+            Format::Json => {
+                let value = match *self {
+                    Frame::Sample(t, a) => json!({"type": "sample", "t": t, "x": a.x, "y": a.y, "z": a.z}),
+                    Frame::Orientation(t, orientation, facing) => json!({
+                        "type": "orientation",
+                        "t": t,
+                        "orientation": orientation_code(orientation).1,
+                        "facing": facing_code(facing).1,
+                    }),
+                    Frame::Tap(t) => json!({"type": "tap", "t": t}),
+                    Frame::Shake(t) => json!({"type": "shake", "t": t}),
+                };
+                (FrameType::Text(false), value.to_string().into_bytes())
+            }
+            Format::Binary => {
+                let (head, t) = match *self {
+                    Frame::Sample(t, a) => ([0, a.x as u8, a.y as u8, a.z as u8], t),
+                    Frame::Orientation(t, orientation, facing) => {
+                        ([1, 0, orientation_code(orientation).0, facing_code(facing).0], t)
+                    }
+                    Frame::Tap(t) => ([1, 1, 0, 0], t),
+                    Frame::Shake(t) => ([1, 2, 0, 0], t),
+                };
+                let mut bytes = head.to_vec();
+                bytes.extend_from_slice(&t.to_le_bytes());
+                (FrameType::Binary(false), bytes)
+            }
+        }
+    }
+}
+
+fn orientation_code(orientation: Orientation) -> (u8, &'static str) {
+    match orientation {
+        Orientation::Unknown => (0, "unknown"),
+        Orientation::Left => (1, "left"),
+        Orientation::Right => (2, "right"),
+        Orientation::Down => (3, "down"),
+        Orientation::Up => (4, "up"),
+    }
+}
+
+fn facing_code(facing: Facing) -> (u8, &'static str) {
+    match facing {
+        Facing::Unknown => (0, "unknown"),
+        Facing::Front => (1, "front"),
+        Facing::Back => (2, "back"),
+    }
+}
+
+/// 配信先の1つの接続
+struct Subscriber {
+    session: i32,
+    sender: EspHttpWsDetachedSender,
+    rate: u32,
+    format: Format,
+    /// 次にサンプルを送る時刻
+    next: Instant,
+}
+
+impl Subscriber {
+    fn new(session: i32, sender: EspHttpWsDetachedSender) -> Self {
+        Self {
+            session,
+            sender,
+            rate: DEFAULT_RATE_HZ,
+            format: Format::Json,
+            next: Instant::now(),
+        }
+    }
+
+    fn configure(&mut self, config: StreamConfig) -> anyhow::Result<()> {
+        if let Some(rate) = config.rate {
+            self.rate = rate.clamp(1, MAX_RATE_HZ);
+        }
+        if let Some(format) = config.format {
+            self.format = format;
+        }
+        self.next = Instant::now();
+        // 設定の応答は形式によらず JSON で返す
+        let ack = json!({"type": "config", "rate": self.rate, "format": self.format});
+        self.sender.send(FrameType::Text(false), ack.to_string().as_bytes())?;
+        Ok(())
+    }
+
+    fn send(&mut self, frame: &Frame) -> anyhow::Result<()> {
+        let (frame_type, bytes) = frame.encode(self.format);
+        self.sender.send(frame_type, &bytes)?;
+        Ok(())
+    }
+
+    /// イベントと、送る時刻になっていればサンプルを送る
+    fn publish(&mut self, now: Instant, sample: &Frame, events: &[Frame]) -> anyhow::Result<()> {
+        if self.sender.is_closed() {
+            anyhow::bail!("WebSocket closed");
+        }
+        for event in events {
+            self.send(event)?;
+        }
+        if now >= self.next {
+            self.send(sample)?;
+            let interval = Duration::from_millis(1000 / self.rate as u64);
+            // 遅れたときはまとめて送らず、今から数え直す
+            self.next = if self.next + interval < now { now + interval } else { self.next + interval };
+        }
+        Ok(())
+    }
+}
+
+/// `server` に WebSocket の `/ws` を追加し、配信スレッドを起動します。
+///
+/// センサーが接続されていないときは、接続すると `{"type":"error",...}` を返します。
+pub fn attach<DI>(server: &mut EspHttpServer<'static>, devices: Arc<Mutex<Devices<DI>>>) -> anyhow::Result<()>
+where
+    DI: DisplayInterface + Send + 'static,
+{
+    let available = devices
+        .lock()
+        .map_err(|_| anyhow::anyhow!("Device lock poisoned"))?
+        .sensor
+        .is_some();
+    let (control, receiver) = mpsc::channel();
+    thread::Builder::new()
+        .name("sensor_stream".to_string())
+        .stack_size(STREAM_STACK_SIZE)
+        .spawn(move || run(devices, receiver))?;
+
+    server.ws_handler("/ws", move |ws| -> anyhow::Result<()> {
+        let session = ws.session();
+        if ws.is_new() {
+            if !available {
+                let error = json!({"type": "error", "error": "accelerometer not connected"});
+                ws.send(FrameType::Text(false), error.to_string().as_bytes())?;
+                return Ok(());
+            }
+            log::info!("WebSocket session {} opened", session);
+            control.send(Control::Open(session, ws.create_detached_sender()?)).map_err(|_| stopped())?;
+            return Ok(());
+        }
+        if ws.is_closed() {
+            log::info!("WebSocket session {} closed", session);
+            control.send(Control::Close(session)).map_err(|_| stopped())?;
+            return Ok(());
+        }
+
+        let (_, len) = ws.recv(&mut [])?;
+        if len > MAX_MESSAGE_LEN {
+            let error = json!({"type": "error", "error": "message too long"});
+            ws.send(FrameType::Text(false), error.to_string().as_bytes())?;
+            anyhow::bail!("WebSocket message too long: {} bytes", len);
+        }
+        let mut buf = [0; MAX_MESSAGE_LEN];
+        let (frame_type, len) = ws.recv(&mut buf)?;
+        if !matches!(frame_type, FrameType::Text(_)) {
+            return Ok(());
+        }
+        // テキストフレームは末尾に NUL が付いていることがある
+        let text = String::from_utf8_lossy(&buf[..len.min(MAX_MESSAGE_LEN)]);
+        match serde_json::from_str::<StreamConfig>(text.trim_end_matches('\0')) {
+            Ok(config) => control.send(Control::Configure(session, config)).map_err(|_| stopped())?,
+            Err(e) => {
+                let error = json!({"type": "error", "error": e.to_string()});
+                ws.send(FrameType::Text(false), error.to_string().as_bytes())?;
+            }
+        }
+        Ok(())
+    })?;
+    Ok(())
+}
+
+fn stopped() -> anyhow::Error {
+    anyhow::anyhow!("Sensor stream thread stopped")
+}
+
+/// 配信スレッド。接続があれば、いちばん近い送信時刻ごとにセンサーを読み取って送る
+fn run<DI>(devices: Arc<Mutex<Devices<DI>>>, control: mpsc::Receiver<Control>)
+where
+    DI: DisplayInterface,
+{
+    let mut subscribers: Vec<Subscriber> = Vec::new();
+    // 最後に送った向きと表裏。変わったときだけイベントを送る
+    let mut last: Option<(Orientation, Facing)> = None;
+    loop {
+        let wait = subscribers
+            .iter()
+            .map(|s| s.next.saturating_duration_since(Instant::now()))
+            .min()
+            .unwrap_or(IDLE_POLL);
+        match control.recv_timeout(wait) {
+            Ok(Control::Open(session, sender)) => {
+                subscribers.push(Subscriber::new(session, sender));
+                // 新しい接続にも今の向きを送る
+                last = None;
+                continue;
+            }
+            Ok(Control::Configure(session, config)) => {
+                if let Some(subscriber) = subscribers.iter_mut().find(|s| s.session == session) {
+                    if let Err(e) = subscriber.configure(config) {
+                        log::warn!("WebSocket session {} send error: {:?}", session, e);
+                    }
+                }
+                continue;
+            }
+            Ok(Control::Close(session)) => {
+                subscribers.retain(|s| s.session != session);
+                continue;
+            }
+            Err(RecvTimeoutError::Timeout) => {}
+            Err(RecvTimeoutError::Disconnected) => return,
+        }
+        if subscribers.is_empty() {
+            continue;
+        }
+
+        let reading = devices
+            .lock()
+            .map_err(|_| anyhow::anyhow!("Device lock poisoned"))
+            .and_then(|mut devices| Ok(devices.acceleration()?.zip(devices.tilt()?)));
+        let (accel, tilt) = match reading {
+            Ok(Some(reading)) => reading,
+            Ok(None) => continue,
+            Err(e) => {
+                log::warn!("Sensor read error: {:?}", e);
+                thread::sleep(IDLE_POLL);
+                continue;
+            }
+        };
+        let t = (unsafe { esp_idf_svc::sys::esp_timer_get_time() } / 1000) as u32;
+        let events = events(t, &tilt, &mut last);
+        let now = Instant::now();
+        subscribers.retain_mut(|subscriber| match subscriber.publish(now, &Frame::Sample(t, accel), &events) {
+            Ok(()) => true,
+            Err(e) => {
+                log::info!("WebSocket session {} dropped: {:?}", subscriber.session, e);
+                false
+            }
+        });
+    }
+}
+
+/// TILT レジスタの内容から送るイベントを作る
+fn events(t: u32, tilt: &Tilt, last: &mut Option<(Orientation, Facing)>) -> Vec<Frame> {
+    let mut events = Vec::new();
+    if *last != Some((tilt.orientation, tilt.facing)) {
+        *last = Some((tilt.orientation, tilt.facing));
+        events.push(Frame::Orientation(t, tilt.orientation, tilt.facing));
+    }
+    if tilt.tap {
+        events.push(Frame::Tap(t));
+    }
+    if tilt.shake {
+        events.push(Frame::Shake(t));
+    }
+    events
+}
@@ -1,24 +1,112 @@
 import socket
+import sys
 import time
 
 HOST = '192.168.1.13'  # ESP32-S2のIPアドレスに置き換える
 PORT = 8080
 
+# OLED の QR コードを読み取った "IP:PORT" を引数で渡せる
+# 例: python test.py 192.168.1.13:8080
+#
+# 2つ目以降の引数にコマンドを指定すると1回だけ送る (コマンドの一覧は help)
+# 例: python test.py 192.168.1.13:8080 help
+# 例: python test.py 192.168.1.13:8080 wifi set MY_SSID MY_PASSWORD
+# screenshot を指定すると、OLED の表示内容を PBM で保存する
+# 例: python test.py 192.168.1.13:8080 screenshot screen.pbm
+# -i を指定すると、1つの接続のまま入力したコマンドを順に送る (空行か Ctrl-D で終了)
+# 例: python test.py 192.168.1.13:8080 -i
+if len(sys.argv) > 1:
+    HOST, _, port = sys.argv[1].partition(':')
+    if port:
+        PORT = int(port)
 
-def send_cmd(cmd:bytes):
-    with socket.socket(socket.AF_INET, socket.SOCK_STREAM) as s:
-        s.connect((HOST, PORT))
-        s.sendall(cmd)
-        data = s.recv(1024)
-        print(f"Received: {data.decode()}")
+# 応答の1行目の後に、行 (OK <行数>) またはバイト列 (OK <バイト数>) が続くコマンド
+MULTILINE_COMMANDS = ('help', 'wifi list')
+BINARY_COMMANDS = ('screenshot',)
 
 
-while True:
-    send_cmd(b'on')
-    time.sleep(1)
-    send_cmd(b'off')
-    time.sleep(1)
-    send_cmd(b'green')
-    time.sleep(1)
-    send_cmd(b'neopixel_off')
-    time.sleep(1)
+class Connection:
+    """1つの接続で続けてコマンドを送る"""
+
+    def __init__(self):
+        self.sock = socket.create_connection((HOST, PORT))
+        self.file = self.sock.makefile('rb')
+
+    def close(self):
+        self.file.close()
+        self.sock.close()
+
+    def command(self, cmd: str):
+        """コマンドを送り、(1行目, 続きのデータ) を返す"""
+        self.sock.sendall(cmd.encode() + b'\n')
+        status = self.file.readline().decode().rstrip('\n')
+        body = None
+        if status.startswith('OK ') and cmd.strip() in MULTILINE_COMMANDS:
+            count = int(status.split()[1])
+            body = [self.file.readline().decode().rstrip('\n') for _ in range(count)]
+        elif status.startswith('OK ') and cmd.strip() in BINARY_COMMANDS:
+            body = self.file.read(int(status.split()[1]))
+        return status, body
+
+
+def screenshot(path: str):
+    conn = Connection()
+    status, data = conn.command('screenshot')
+    conn.close()
+    if data is None:
+        print(status)
+        return
+    with open(path, 'wb') as f:
+        f.write(data)
+    print(f"Saved: {path} ({len(data)} bytes)")
+
+
+if len(sys.argv) > 2 and sys.argv[2] == 'screenshot':
+    screenshot(sys.argv[3] if len(sys.argv) > 3 else 'screenshot.pbm')
+    sys.exit()
+
+
+def interactive():
+    conn = Connection()
+    try:
+        while True:
+            try:
+                cmd = input('> ').strip()
+            except EOFError:
+                break
+            if not cmd:
+                break
+            status, body = conn.command(cmd)
+            print(status)
+            if isinstance(body, list):
+                for line in body:
+                    print(line)
+            elif body is not None:
+                print(f"({len(body)} bytes)")
+            if cmd == 'quit' or status.startswith('ERR 408'):
+                break
+    finally:
+        conn.close()
+
+
+if len(sys.argv) > 2 and sys.argv[2] == '-i':
+    interactive()
+    sys.exit()
+
+if len(sys.argv) > 2:
+    conn = Connection()
+    status, body = conn.command(' '.join(sys.argv[2:]))
+    conn.close()
+    print(status)
+    for line in body or []:
+        print(line)
+    sys.exit()
+
+conn = Connection()
+try:
+    while True:
+        for cmd in ('led 1 on', 'led 1 off', 'pixel 0 255 0', 'pixel off', 'get accel'):
+            print(f"{cmd}: {conn.command(cmd)[0]}")
+            time.sleep(1)
+finally:
+    conn.close()